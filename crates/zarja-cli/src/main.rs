@@ -4,15 +4,32 @@
 //! and reconstructs them into human-readable `.proto` source files.
 
 use anyhow::{bail, Context, Result};
+#[cfg(feature = "object-files")]
+use bytes::Bytes;
 use clap::{Args, Parser, ValueEnum};
-use zarja_core::{ProtoReconstructor, Scanner, ScanStrategy, ScannerConfig};
+use serde_json::{json, Value};
 use std::collections::HashMap;
+#[cfg(feature = "object-files")]
+use std::collections::HashSet;
 use std::fs;
 use std::io::Write;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
 use tracing::{debug, error, info, trace, warn, Level};
 use tracing_subscriber::EnvFilter;
 use walkdir::WalkDir;
+use zarja_core::scanner::{map_file, ScanIter, ThreadBudget};
+#[cfg(feature = "extended-formats")]
+use zarja_core::DescriptorSet;
+#[cfg(feature = "object-files")]
+use zarja_core::DyldCache;
+use zarja_core::{
+    Diagnostic, ProtoReconstructor, ReconstructionSession, ScanResult, Scanner, ScannerConfig,
+    SliceMatch, Stage, Verifier,
+};
 
 /// Extract Protocol Buffer definitions from compiled binaries
 #[derive(Parser, Debug)]
@@ -39,6 +56,16 @@ struct Cli {
     #[arg(long, default_value = "0")]
     max_descriptors: usize,
 
+    /// Minimum confidence score (0.0 to 1.0) for a candidate to be decoded;
+    /// 0 keeps every candidate
+    #[arg(long, default_value = "0.5")]
+    min_confidence: f32,
+
+    /// Also look for file names ending in this suffix, besides `.proto` and
+    /// `.protodevel` (repeatable)
+    #[arg(long = "suffix", value_name = "SUFFIX")]
+    suffixes: Vec<String>,
+
     /// Dry run - don't write files, just show what would be extracted
     #[arg(long)]
     dry_run: bool,
@@ -54,6 +81,58 @@ struct Cli {
     /// Conflict resolution strategy for same-name different-content protos
     #[arg(long, value_enum, default_value = "hash-suffix")]
     conflict_strategy: ConflictStrategy,
+
+    /// Recompile each reconstructed file and compare it with the original
+    /// descriptor; exits non-zero if any file differs
+    #[arg(long)]
+    verify: bool,
+
+    /// Write stub files declaring the referenced types of imports that no
+    /// recovered file provides, so the output tree compiles
+    #[arg(long)]
+    stub_missing_imports: bool,
+
+    /// What to do with files identical to a bundled copy (well-known types,
+    /// google/api, google/rpc, gRPC health and reflection, validate)
+    #[arg(long, value_enum, default_value = "write")]
+    bundled: BundledMode,
+
+    /// Recover what decodes from descriptors that are corrupted or cut
+    /// short; such files open with a comment listing what was lost
+    #[arg(long)]
+    salvage: bool,
+
+    /// Write why each rejected candidate was dropped to a JSON file
+    /// (grouped counts are also logged at -v)
+    #[arg(long, value_name = "FILE")]
+    report: Option<PathBuf>,
+
+    /// Only scan the data sections of ELF, Mach-O and PE files, skipping
+    /// code and debug info (other files are scanned in full)
+    #[cfg(feature = "object-files")]
+    #[arg(long)]
+    data_sections_only: bool,
+
+    /// Number of threads for scanning binaries and rendering files (0 = one
+    /// per CPU); threads with no binary left to scan help search the chunks
+    /// of large ones. Output is the same for any number
+    #[arg(short, long, default_value = "0")]
+    jobs: usize,
+}
+
+impl Cli {
+    /// Returns true if rejection diagnostics should be collected
+    fn diagnose(&self) -> bool {
+        self.report.is_some() || self.verbose > 0
+    }
+
+    /// Returns the number of worker threads to use
+    fn jobs(&self) -> usize {
+        match self.jobs {
+            0 => thread::available_parallelism().map_or(1, |n| n.get()),
+            jobs => jobs,
+        }
+    }
 }
 
 #[derive(Args, Debug)]
@@ -75,6 +154,28 @@ enum OutputFormat {
     Proto,
     /// Just the filename (for scripting)
     Filename,
+    /// One binary FileDescriptorSet, descriptors.binpb, for protoc
+    /// --descriptor_set_in
+    #[cfg(feature = "extended-formats")]
+    Binpb,
+    /// The descriptor of each file as protojson (.json)
+    #[cfg(feature = "extended-formats")]
+    Protojson,
+    /// The descriptor of each file in the protobuf text format (.txtpb)
+    #[cfg(feature = "extended-formats")]
+    Textproto,
+    /// .proto files, plus a JSON array on stdout with one record per
+    /// candidate descriptor
+    Json,
+    /// Like json, with one record per line
+    Jsonl,
+}
+
+impl OutputFormat {
+    /// Returns true for the formats that print candidate records
+    fn is_listing(self) -> bool {
+        matches!(self, Self::Json | Self::Jsonl)
+    }
 }
 
 /// Strategy for resolving naming conflicts
@@ -88,6 +189,93 @@ enum ConflictStrategy {
     SkipConflicts,
 }
 
+/// Handling of files that match a bundled descriptor byte for byte
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum BundledMode {
+    /// Write them like any other file
+    Write,
+    /// Write them with a comment saying they match the bundled copy
+    Mark,
+    /// Leave them out of the output
+    Skip,
+}
+
+/// What happened to a recovered file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Decision {
+    /// Written under its own name
+    Written,
+    /// Identical to a file already written
+    Duplicate,
+    /// Written under a new name, since a file of the same name differs
+    Renamed,
+    /// Left out: not a .proto file, a skipped bundled file or a skipped
+    /// conflict
+    Skipped,
+    /// Writing it failed
+    Failed,
+}
+
+impl Decision {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Written => "written",
+            Self::Duplicate => "duplicate",
+            Self::Renamed => "renamed",
+            Self::Skipped => "skipped",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+/// The result of outputting one recovered file
+struct Outcome {
+    decision: Decision,
+    /// Hash of the written content
+    content_hash: Option<String>,
+    output_path: Option<PathBuf>,
+}
+
+impl Outcome {
+    fn skipped() -> Self {
+        Self {
+            decision: Decision::Skipped,
+            content_hash: None,
+            output_path: None,
+        }
+    }
+}
+
+/// A descriptor found by the scanner
+struct Candidate<'a> {
+    /// Position in scan order across the run
+    index: usize,
+    /// The binary, or the image of a dyld shared cache, it was found in
+    binary: &'a Path,
+    /// Byte range in the binary; None for generated stubs
+    range: Option<Range<usize>>,
+    /// The descriptor bytes
+    data: &'a [u8],
+    /// The scanner's confidence score; None for generated stubs
+    confidence: Option<f32>,
+    /// Section, address and nearest symbol in an object file
+    #[cfg(feature = "object-files")]
+    location: Option<&'a zarja_core::Location>,
+    /// The dyld shared cache file holding the image it was found in
+    #[cfg(feature = "object-files")]
+    cache: Option<&'a Path>,
+    /// The slices of a universal binary it was found in
+    slices: &'a [SliceMatch],
+}
+
+/// A reconstructed file, rendered ahead of its registry decision
+struct Rendered {
+    /// The .proto source; None if the file is left out
+    content: Option<String>,
+    /// The verification report, and whether the file recompiled faithfully
+    verified: Option<(String, bool)>,
+}
+
 /// Tracks seen proto files for deduplication
 #[derive(Default)]
 struct ProtoRegistry {
@@ -95,6 +283,13 @@ struct ProtoRegistry {
     seen: HashMap<String, Vec<(String, PathBuf)>>,
     /// Statistics
     stats: RegistryStats,
+    /// Files collected for `--format binpb`
+    #[cfg(feature = "extended-formats")]
+    descriptor_set: DescriptorSet,
+    /// Candidate records for `--format json`, by candidate index
+    records: Vec<(usize, Value)>,
+    /// Rejected candidates, with their binary
+    diagnostics: Vec<(PathBuf, Diagnostic)>,
 }
 
 #[derive(Default)]
@@ -103,6 +298,7 @@ struct RegistryStats {
     duplicates_skipped: usize,
     conflicts_renamed: usize,
     written: usize,
+    verify_failed: usize,
 }
 
 impl ProtoRegistry {
@@ -129,6 +325,19 @@ impl ProtoRegistry {
         self.seen.get(filename).map(|e| e.len()).unwrap_or(0)
     }
 
+    /// Decide what registering a file would do
+    fn classify(&self, filename: &str, content_hash: &str, strategy: ConflictStrategy) -> Decision {
+        if self.is_duplicate(filename, content_hash) {
+            Decision::Duplicate
+        } else if self.variant_count(filename) == 0 {
+            Decision::Written
+        } else if matches!(strategy, ConflictStrategy::SkipConflicts) {
+            Decision::Skipped
+        } else {
+            Decision::Renamed
+        }
+    }
+
     /// Register a proto file and return the resolved output path
     fn register(
         &mut self,
@@ -215,6 +424,61 @@ impl ProtoRegistry {
             self.stats.written
         );
     }
+
+    /// Log the rejected candidates grouped by stage and error, and write
+    /// them to the `--report` file
+    fn report_diagnostics(&self, cli: &Cli) -> Result<()> {
+        let mut groups: Vec<((Stage, &str), usize)> = Vec::new();
+        for (_, diagnostic) in &self.diagnostics {
+            let key = (diagnostic.stage, diagnostic.kind);
+            match groups.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, count)) => *count += 1,
+                None => groups.push((key, 1)),
+            }
+        }
+        groups.sort();
+        for ((stage, kind), count) in &groups {
+            info!("Rejected at {}: {} candidate(s) ({})", stage, count, kind);
+        }
+        for (binary, diagnostic) in &self.diagnostics {
+            debug!("{}: {}", binary.display(), diagnostic);
+        }
+
+        let Some(path) = &cli.report else {
+            return Ok(());
+        };
+        let summary: serde_json::Map<String, Value> = groups
+            .iter()
+            .map(|((stage, kind), count)| (format!("{}/{}", stage, kind), json!(count)))
+            .collect();
+        let diagnostics: Vec<Value> = self
+            .diagnostics
+            .iter()
+            .map(|(binary, diagnostic)| {
+                json!({
+                    "binary": binary,
+                    "offset": diagnostic.offset,
+                    "stage": diagnostic.stage.as_str(),
+                    "error": diagnostic.kind,
+                    "message": diagnostic.message,
+                    "context": diagnostic.context,
+                })
+            })
+            .collect();
+        let report = json!({ "summary": summary, "diagnostics": diagnostics });
+        fs::write(path, format!("{:#}\n", report))
+            .with_context(|| format!("Failed to write report: {}", path.display()))?;
+        info!("Wrote diagnostics report to {}", path.display());
+        Ok(())
+    }
+
+    /// Fail the run if any file didn't survive verification
+    fn check_verified(&self) -> Result<()> {
+        if self.stats.verify_failed > 0 {
+            bail!("{} file(s) failed verification", self.stats.verify_failed);
+        }
+        Ok(())
+    }
 }
 
 fn main() -> Result<()> {
@@ -253,13 +517,19 @@ fn process_single_file(cli: &Cli, file: &Path) -> Result<()> {
     }
 
     let mut registry = ProtoRegistry::new();
-    process_binary(cli, file, &mut registry)?;
+    let mut scanned = Vec::new();
+    let budget = ThreadBudget::new(cli.jobs() - 1);
+    for scan in scan_binary(cli, file, budget)? {
+        scan.merge(&mut scanned, &mut registry);
+    }
+    extract_descriptors(cli, scanned, &mut registry);
+    registry.report_diagnostics(cli)?;
 
     if !cli.list_only && !cli.dry_run {
         registry.print_summary();
     }
 
-    Ok(())
+    registry.check_verified()
 }
 
 /// Process a directory of binaries recursively
@@ -273,12 +543,12 @@ fn process_directory(cli: &Cli, directory: &Path) -> Result<()> {
 
     info!("Scanning directory: {}", directory.display());
 
-    let mut registry = ProtoRegistry::new();
-    let mut binaries_processed = 0;
-
-    // Walk the directory
+    // Sorted, so that the first occurrence of a file and the order of
+    // conflict renames don't depend on the file system
+    let mut binaries = Vec::new();
     for entry in WalkDir::new(directory)
         .follow_links(false)
+        .sort_by_file_name()
         .into_iter()
         .filter_map(|e| e.ok())
     {
@@ -305,21 +575,121 @@ fn process_directory(cli: &Cli, directory: &Path) -> Result<()> {
             continue;
         }
 
-        debug!("Processing binary: {}", path.display());
-        if let Err(e) = process_binary(cli, path, &mut registry) {
+        binaries.push(path.to_path_buf());
+    }
+
+    // Subcaches are scanned with their main dyld shared cache file
+    #[cfg(feature = "object-files")]
+    {
+        let subcaches: HashSet<PathBuf> = binaries
+            .iter()
+            .flat_map(|path| dyld_subcaches(path))
+            .collect();
+        binaries.retain(|path| !subcaches.contains(path));
+    }
+
+    // Workers left without a binary to scan help with the chunks of the
+    // large binaries still being scanned
+    let pool = cli.jobs().clamp(1, binaries.len().max(1));
+    let budget = ThreadBudget::new(cli.jobs() - pool);
+    let scans = parallel_map_then(
+        pool,
+        &binaries,
+        |path| {
+            debug!("Processing binary: {}", path.display());
+            scan_binary(cli, path, budget.clone())
+        },
+        || budget.release(),
+    );
+
+    // Merge in path order, as a sequential run would
+    let mut registry = ProtoRegistry::new();
+    let mut scanned = Vec::new();
+    for (path, scan) in binaries.iter().zip(scans) {
+        match scan {
+            Ok(scans) => {
+                for scan in scans {
+                    scan.merge(&mut scanned, &mut registry);
+                }
+            }
             // Log error but continue with other files
-            warn!("Error processing {}: {}", path.display(), e);
+            Err(e) => warn!("Error processing {}: {}", path.display(), e),
         }
-        binaries_processed += 1;
     }
 
-    info!("Processed {} binaries", binaries_processed);
+    // Extract only after every binary is scanned, so custom options can be
+    // resolved against extensions found in any of them
+    extract_descriptors(cli, scanned, &mut registry);
+    registry.report_diagnostics(cli)?;
+
+    info!("Processed {} binaries", binaries.len());
 
     if !cli.list_only && !cli.dry_run {
         registry.print_summary();
     }
 
-    Ok(())
+    registry.check_verified()
+}
+
+/// Apply `f` to every item on up to `jobs` threads, returning the results
+/// in item order
+fn parallel_map<T, R, F>(jobs: usize, items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    parallel_map_then(jobs, items, f, || {})
+}
+
+/// Like [`parallel_map`], calling `idle` on each thread that finds no items
+/// left
+fn parallel_map_then<T, R, F, I>(jobs: usize, items: &[T], f: F, idle: I) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+    I: Fn() + Sync,
+{
+    let jobs = jobs.clamp(1, items.len().max(1));
+    if jobs == 1 {
+        let results = items.iter().map(f).collect();
+        idle();
+        return results;
+    }
+
+    // Workers take the next item until none are left
+    let next = AtomicUsize::new(0);
+    let done: Vec<Vec<(usize, R)>> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(item) = items.get(index) else {
+                            idle();
+                            break done;
+                        };
+                        done.push((index, f(item)));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().expect("worker thread panicked"))
+            .collect()
+    });
+
+    let mut results: Vec<Option<R>> = (0..items.len()).map(|_| None).collect();
+    for (index, result) in done.into_iter().flatten() {
+        results[index] = Some(result);
+    }
+    results
+        .into_iter()
+        .map(|result| result.expect("every item is processed"))
+        .collect()
 }
 
 /// Heuristic to determine if a file is likely a binary executable
@@ -337,10 +707,9 @@ fn is_likely_binary(path: &Path) -> bool {
         }
     }
 
-    // Check file size - skip very small files (< 1KB) and very large files (> 500MB)
+    // Check file size - skip very small files (< 1KB); large ones are mapped, not read
     if let Ok(metadata) = fs::metadata(path) {
-        let size = metadata.len();
-        if size < 1024 || size > 500 * 1024 * 1024 {
+        if metadata.len() < 1024 {
             return false;
         }
     }
@@ -355,8 +724,9 @@ fn is_likely_binary(path: &Path) -> bool {
                 || magic == [0xCE, 0xFA, 0xED, 0xFE] // 32-bit
                 || magic == [0xFE, 0xED, 0xFA, 0xCF] // 64-bit reverse
                 || magic == [0xFE, 0xED, 0xFA, 0xCE] // 32-bit reverse
-                || magic == [0xCA, 0xFE, 0xBA, 0xBE]
-            // Universal
+                || magic == [0xCA, 0xFE, 0xBA, 0xBE] // Universal
+                || magic == [0xCA, 0xFE, 0xBA, 0xBF]
+            // Universal, 64-bit offsets
             {
                 return true;
             }
@@ -364,6 +734,10 @@ fn is_likely_binary(path: &Path) -> bool {
             if magic[0..4] == [0x7F, b'E', b'L', b'F'] {
                 return true;
             }
+            // dyld shared cache (macOS, iOS)
+            if &magic == b"dyld" {
+                return true;
+            }
             // PE (Windows) - MZ header
             if magic[0..2] == [b'M', b'Z'] {
                 return true;
@@ -375,115 +749,716 @@ fn is_likely_binary(path: &Path) -> bool {
     path.extension().is_none()
 }
 
-/// Process a single binary and extract protos
-fn process_binary(cli: &Cli, binary_path: &Path, registry: &mut ProtoRegistry) -> Result<()> {
-    // Read the input file
-    trace!("Reading {}", binary_path.display());
-    let data = fs::read(binary_path)
+/// The results of scanning one file
+struct FileScan {
+    path: PathBuf,
+    results: Vec<ScanResult>,
+    /// Rejected candidates, when they are being collected
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl FileScan {
+    /// Add the results to those to extract, and the rejected candidates
+    /// to the registry
+    fn merge(self, scanned: &mut Vec<(PathBuf, Vec<ScanResult>)>, registry: &mut ProtoRegistry) {
+        let path = &self.path;
+        registry
+            .diagnostics
+            .extend(self.diagnostics.into_iter().map(|d| (path.clone(), d)));
+        scanned.push((self.path, self.results));
+    }
+}
+
+/// Scan a single binary for embedded descriptors
+///
+/// A dyld shared cache is scanned with its subcaches, one scan per file.
+/// Chunks of a large file are searched on the calling thread and the idle
+/// threads of `budget`.
+fn scan_binary(cli: &Cli, binary_path: &Path, budget: Arc<ThreadBudget>) -> Result<Vec<FileScan>> {
+    // Map the input file; results are slices of the map
+    trace!("Mapping {}", binary_path.display());
+    let data = map_file(binary_path)
         .with_context(|| format!("Failed to read input file: {}", binary_path.display()))?;
 
-    trace!("Read {} bytes from {}", data.len(), binary_path.display());
+    trace!("Mapped {} bytes from {}", data.len(), binary_path.display());
 
     // Configure and run scanner
-    let config = ScannerConfig::new().max_results(cli.max_descriptors);
+    let mut config = ScannerConfig::new()
+        .max_results(cli.max_descriptors)
+        .min_confidence(cli.min_confidence)
+        .budget(budget);
+    #[cfg(feature = "object-files")]
+    {
+        config.data_sections_only = cli.data_sections_only;
+    }
+    config
+        .suffixes
+        .extend(cli.suffixes.iter().map(|suffix| suffix.as_bytes().to_vec()));
     let scanner = Scanner::with_config(config);
-    let results = scanner
-        .scan(&data)
-        .with_context(|| format!("Failed to scan binary: {}", binary_path.display()))?;
+    #[cfg(feature = "object-files")]
+    {
+        if let Some(suffixes) = DyldCache::subcache_suffixes(&data) {
+            return Ok(scan_dyld_cache(cli, &scanner, binary_path, data, &suffixes));
+        }
+    }
+    let iter = scanner.scan_bytes(data);
+    Ok(vec![collect_scan(cli, binary_path.to_path_buf(), iter)])
+}
 
-    if results.is_empty() {
-        trace!("No descriptors found in {}", binary_path.display());
-        return Ok(());
+/// Scan the files of a dyld shared cache, attributing each descriptor to
+/// the image it lies in
+///
+/// If a subcache is missing or the cache doesn't parse, the main file and
+/// every subcache that could be read are scanned like any other binary
+/// instead.
+#[cfg(feature = "object-files")]
+fn scan_dyld_cache(
+    cli: &Cli,
+    scanner: &Scanner,
+    main: &Path,
+    data: Bytes,
+    suffixes: &[String],
+) -> Vec<FileScan> {
+    let flat = |files: Vec<(PathBuf, Bytes)>| {
+        files
+            .into_iter()
+            .map(|(path, data)| collect_scan(cli, path, scanner.scan_bytes(data)))
+            .collect()
+    };
+
+    let mut files = vec![(main.to_path_buf(), data)];
+    let mut missing = false;
+    for path in subcache_paths(main, suffixes) {
+        match map_file(&path) {
+            Ok(data) => files.push((path, data)),
+            Err(e) => {
+                warn!(
+                    "Failed to read dyld subcache {}: {}; scanning {} without image attribution",
+                    path.display(),
+                    e,
+                    main.display()
+                );
+                missing = true;
+            }
+        }
+    }
+    if missing {
+        return flat(files);
     }
 
-    debug!(
-        "Found {} potential descriptor(s) in {}",
-        results.len(),
-        binary_path.display()
+    let subcaches: Vec<&[u8]> = files[1..].iter().map(|(_, data)| &data[..]).collect();
+    let cache = match DyldCache::parse(&files[0].1, &subcaches) {
+        Ok(cache) => cache,
+        Err(e) => {
+            warn!(
+                "Failed to parse dyld shared cache {}: {}; scanning it without image attribution",
+                main.display(),
+                e
+            );
+            return flat(files);
+        }
+    };
+    info!(
+        "{} is a dyld shared cache of {} images in {} files",
+        main.display(),
+        cache.images().len(),
+        files.len()
     );
 
-    // Process each result
-    for (i, result) in results.iter().enumerate() {
-        trace!(
-            "Processing descriptor {} ({} bytes at offset {})",
-            i + 1,
-            result.data.len(),
-            result.range.start
+    files
+        .into_iter()
+        .enumerate()
+        .map(|(file, (path, data))| {
+            let iter = scanner.scan_dyld_file(&cache, file, data);
+            collect_scan(cli, path, iter)
+        })
+        .collect()
+}
+
+/// Returns the paths of the subcaches a main dyld shared cache file needs,
+/// next to it
+#[cfg(feature = "object-files")]
+fn subcache_paths(main: &Path, suffixes: &[String]) -> Vec<PathBuf> {
+    suffixes
+        .iter()
+        .map(|suffix| {
+            let mut path = main.as_os_str().to_owned();
+            path.push(suffix);
+            PathBuf::from(path)
+        })
+        .collect()
+}
+
+/// Returns the subcaches of a file if it is a main dyld shared cache file
+#[cfg(feature = "object-files")]
+fn dyld_subcaches(path: &Path) -> Vec<PathBuf> {
+    map_file(path)
+        .ok()
+        .and_then(|data| DyldCache::subcache_suffixes(&data))
+        .map(|suffixes| subcache_paths(path, &suffixes))
+        .unwrap_or_default()
+}
+
+/// Run a scan of one file to the end
+fn collect_scan(cli: &Cli, path: PathBuf, mut iter: ScanIter<'_>) -> FileScan {
+    if cli.diagnose() {
+        iter = iter.with_diagnostics();
+    }
+    let results: Vec<ScanResult> = iter.by_ref().collect();
+    let diagnostics = iter.take_diagnostics();
+
+    if results.is_empty() {
+        trace!("No descriptors found in {}", path.display());
+    } else {
+        debug!(
+            "Found {} potential descriptor(s) in {}",
+            results.len(),
+            path.display()
         );
+    }
 
-        // Try to reconstruct the proto definition
-        match ProtoReconstructor::from_bytes(&result.data) {
-            Ok(reconstructor) => {
-                let filename = reconstructor.filename();
+    FileScan {
+        path,
+        results,
+        diagnostics,
+    }
+}
 
-                // Skip non-.proto files
-                if !filename.ends_with(".proto") {
-                    trace!("Skipping non-.proto file: {}", filename);
-                    continue;
+/// Reconstruct and output the descriptors found in scanned binaries
+///
+/// All descriptors go into one session, so imports and custom options
+/// resolve across every binary in the run.
+fn extract_descriptors(
+    cli: &Cli,
+    scanned: Vec<(PathBuf, Vec<ScanResult>)>,
+    registry: &mut ProtoRegistry,
+) {
+    let mut session = ReconstructionSession::new();
+    let mut sources = Vec::new();
+    let mut index = 0;
+    for (binary_path, results) in &scanned {
+        for (i, result) in results.iter().enumerate() {
+            let binary = binary_path.as_path();
+            // Descriptors in a dyld shared cache come from its images
+            #[cfg(feature = "object-files")]
+            let binary = result.image.as_deref().map_or(binary, Path::new);
+            let candidate = Candidate {
+                index,
+                binary,
+                range: Some(result.range.clone()),
+                data: &result.data,
+                confidence: Some(result.confidence),
+                #[cfg(feature = "object-files")]
+                location: result.location.as_ref(),
+                #[cfg(feature = "object-files")]
+                cache: result.image.is_some().then_some(binary_path.as_path()),
+                slices: &result.slices,
+            };
+            index += 1;
+
+            trace!(
+                "Processing descriptor {} ({} bytes at offset {})",
+                i + 1,
+                result.data.len(),
+                result.range.start
+            );
+
+            let added = session.add(&result.data).or_else(|e| {
+                if !cli.salvage {
+                    return Err(e);
                 }
+                let report = session.add_salvaged(&result.data).map_err(|_| e)?;
+                info!(
+                    "Salvaged descriptor at offset {} in {}: {} dropped, {} repaired",
+                    result.range.start,
+                    binary_path.display(),
+                    report.dropped.len(),
+                    report.repaired.len()
+                );
+                Ok(())
+            });
+            match added {
+                Ok(()) => sources.push(candidate),
+                Err(e) => {
+                    trace!(
+                        "Failed to parse descriptor {} at offset {}: {}",
+                        i + 1,
+                        result.range.start,
+                        e
+                    );
+                    if cli.diagnose() {
+                        let diagnostic = Diagnostic::new(Stage::Decode, &e, &result.data, 0)
+                            .with_offset(result.range.start);
+                        registry.diagnostics.push((binary_path.clone(), diagnostic));
+                    }
+                    if cli.format.is_listing() {
+                        let mut record = candidate_record(&candidate);
+                        record["status"] = json!("error");
+                        record["error"] = json!(e.to_string());
+                        registry.records.push((candidate.index, record));
+                    }
+                }
+            }
+        }
+    }
 
-                let content = reconstructor.reconstruct();
-                let content_hash = ProtoRegistry::content_hash(&content);
+    let stubs = if cli.stub_missing_imports {
+        session.stubs()
+    } else {
+        Vec::new()
+    };
+    let verifier = cli.verify.then(|| {
+        let mut verifier = session.verifier();
+        for stub in &stubs {
+            verifier.add_file(stub.proto().clone());
+        }
+        verifier
+    });
+
+    // Render on the worker pool, then decide and write in scan order
+    let reconstructors = session.reconstructors();
+    let rendered = parallel_map(cli.jobs(), &reconstructors, |reconstructor| {
+        render(cli, reconstructor, verifier.as_ref())
+    });
+    for ((reconstructor, rendered), candidate) in reconstructors.iter().zip(rendered).zip(sources) {
+        process_descriptor(cli, &candidate, reconstructor, rendered, registry);
+    }
+    for stub in &stubs {
+        debug!("Stubbing missing import: {}", stub.filename());
+        let candidate = Candidate {
+            index,
+            binary: Path::new("stub"),
+            range: None,
+            data: &[],
+            confidence: None,
+            #[cfg(feature = "object-files")]
+            location: None,
+            #[cfg(feature = "object-files")]
+            cache: None,
+            slices: &[],
+        };
+        index += 1;
+        let rendered = render(cli, stub, None);
+        process_descriptor(cli, &candidate, stub, rendered, registry);
+    }
 
-                if cli.list_only {
-                    println!("{}", filename);
-                    continue;
-                }
+    #[cfg(feature = "extended-formats")]
+    write_descriptor_set(cli, registry);
+
+    if cli.format.is_listing() {
+        print_records(cli, registry);
+    }
+}
+
+/// Output a single reconstructed descriptor, recording the outcome for
+/// `--format json`
+fn process_descriptor(
+    cli: &Cli,
+    candidate: &Candidate<'_>,
+    reconstructor: &ProtoReconstructor,
+    rendered: Rendered,
+    registry: &mut ProtoRegistry,
+) {
+    if let (Some(range), Some(e)) = (&candidate.range, reconstructor.build_error()) {
+        if cli.diagnose() {
+            let diagnostic =
+                Diagnostic::new(Stage::Build, e, candidate.data, 0).with_offset(range.start);
+            registry
+                .diagnostics
+                .push((candidate.binary.to_path_buf(), diagnostic));
+        }
+    }
+
+    let outcome = output_descriptor(cli, candidate.binary, reconstructor, rendered, registry);
+    if !cli.format.is_listing() {
+        return;
+    }
+
+    let proto = reconstructor.proto();
+    let (messages, enums) = count_types(&proto.message_type, proto.enum_type.len());
+    let mut record = candidate_record(candidate);
+    record["status"] = json!(if candidate.range.is_none() {
+        "stub"
+    } else if reconstructor.salvage_report().is_some() {
+        "salvaged"
+    } else {
+        "parsed"
+    });
+    record["filename"] = json!(reconstructor.filename());
+    record["package"] = json!(proto.package());
+    record["syntax"] = json!(reconstructor.syntax().as_str());
+    record["messages"] = json!(messages);
+    record["enums"] = json!(enums);
+    record["services"] = json!(proto.service.len());
+    record["bundled"] = json!(reconstructor.is_bundled());
+    record["hash"] = json!(outcome.content_hash);
+    record["decision"] = json!(outcome.decision.as_str());
+    record["output"] = json!(outcome.output_path);
+    registry.records.push((candidate.index, record));
+}
+
+/// Starts the listing record of a candidate with where it was found
+fn candidate_record(candidate: &Candidate<'_>) -> Value {
+    let range = candidate.range.as_ref();
+    #[allow(unused_mut)]
+    let mut record = json!({
+        "binary": candidate.range.is_some().then_some(candidate.binary),
+        "offset": range.map(|r| r.start),
+        "end": range.map(|r| r.end),
+        "size": range.map(|r| r.len()),
+        // Rounded, as f32 scores widen to long decimals
+        "confidence": candidate
+            .confidence
+            .map(|score| (f64::from(score) * 100.0).round() / 100.0),
+    });
+
+    #[cfg(feature = "object-files")]
+    {
+        record["cache"] = json!(candidate.cache);
+        add_location(&mut record, candidate.location);
+    }
+    record["slices"] = json!((!candidate.slices.is_empty()).then(|| {
+        candidate
+            .slices
+            .iter()
+            .map(slice_record)
+            .collect::<Vec<_>>()
+    }));
+    record
+}
+
+/// Describes where a candidate was found in one slice of a universal binary
+fn slice_record(slice: &SliceMatch) -> Value {
+    #[allow(unused_mut)]
+    let mut record = json!({
+        "arch": slice.arch,
+        "offset": slice.range.start,
+        "end": slice.range.end,
+    });
+    #[cfg(feature = "object-files")]
+    add_location(&mut record, slice.location.as_ref());
+    record
+}
+
+/// Adds the section, address and nearest symbol of a record
+#[cfg(feature = "object-files")]
+fn add_location(record: &mut Value, location: Option<&zarja_core::Location>) {
+    let symbol = location.and_then(|l| l.symbol.as_ref());
+    record["section"] = json!(location.and_then(|l| l.section.as_ref()));
+    record["segment"] = json!(location.and_then(|l| l.segment.as_ref()));
+    // Hex, as in a disassembler
+    record["address"] = json!(location
+        .and_then(|l| l.address)
+        .map(|address| format!("{:#x}", address)));
+    record["symbol"] = json!(symbol.map(|s| &s.name));
+    record["symbol_offset"] = json!(symbol.map(|s| s.offset));
+}
+
+/// Counts messages (without map entries) and enums, including nested ones
+fn count_types(messages: &[prost_types::DescriptorProto], enums: usize) -> (usize, usize) {
+    messages
+        .iter()
+        .filter(|message| !message.options.as_ref().is_some_and(|o| o.map_entry()))
+        .fold((0, enums), |(m, e), message| {
+            let (nested, nested_enums) = count_types(&message.nested_type, message.enum_type.len());
+            (m + 1 + nested, e + nested_enums)
+        })
+}
+
+/// Print the candidate records in scan order
+fn print_records(cli: &Cli, registry: &mut ProtoRegistry) {
+    registry.records.sort_by_key(|(index, _)| *index);
+    let records = registry.records.drain(..).map(|(_, record)| record);
+    if matches!(cli.format, OutputFormat::Jsonl) {
+        for record in records {
+            println!("{}", record);
+        }
+    } else {
+        let records = Value::Array(records.collect());
+        println!("{:#}", records);
+    }
+}
+
+/// Render a reconstructed file as .proto source, verifying it if asked
+///
+/// This depends on no other file's outcome, so it runs on the worker pool.
+fn render(cli: &Cli, reconstructor: &ProtoReconstructor, verifier: Option<&Verifier>) -> Rendered {
+    let filename = reconstructor.filename();
+    let bundled = reconstructor.is_bundled();
+    if !filename.ends_with(".proto") || (bundled && cli.bundled == BundledMode::Skip) {
+        return Rendered {
+            content: None,
+            verified: None,
+        };
+    }
+
+    let mut content = reconstructor.reconstruct();
+    if bundled && cli.bundled == BundledMode::Mark {
+        content.insert_str(
+            0,
+            &format!("// zarja: identical to the bundled {}\n\n", filename),
+        );
+    }
+    let verified = verifier.map(|verifier| verify_descriptor(verifier, reconstructor, &content));
+    Rendered {
+        content: Some(content),
+        verified,
+    }
+}
+
+/// Output a single reconstructed descriptor
+fn output_descriptor(
+    cli: &Cli,
+    binary_path: &Path,
+    reconstructor: &ProtoReconstructor,
+    rendered: Rendered,
+    registry: &mut ProtoRegistry,
+) -> Outcome {
+    let filename = reconstructor.filename();
+
+    // Skip non-.proto files
+    if !filename.ends_with(".proto") {
+        trace!("Skipping non-.proto file: {}", filename);
+        return Outcome::skipped();
+    }
+
+    let bundled = reconstructor.is_bundled();
+    if bundled && cli.bundled == BundledMode::Skip {
+        debug!("Skipping bundled file: {}", filename);
+        return Outcome::skipped();
+    }
+    let marked = bundled && cli.bundled == BundledMode::Mark;
+    let Some(content) = rendered.content else {
+        return Outcome::skipped();
+    };
+
+    // The report goes to stderr when stdout carries `--format json` records
+    if let Some((report, faithful)) = rendered.verified {
+        if cli.format.is_listing() {
+            eprint!("{}", report);
+        } else {
+            print!("{}", report);
+        }
+        if !faithful {
+            registry.stats.verify_failed += 1;
+        }
+    }
+
+    if cli.list_only && !cli.format.is_listing() {
+        if marked {
+            println!("{} (bundled)", filename);
+        } else {
+            println!("{}", filename);
+        }
+        return Outcome::skipped();
+    }
 
-                match cli.format {
-                    OutputFormat::Filename => {
-                        println!("{}", filename);
+    match cli.format {
+        OutputFormat::Filename => {
+            println!("{}", filename);
+            Outcome::skipped()
+        }
+        OutputFormat::Proto | OutputFormat::Json | OutputFormat::Jsonl => {
+            write_output(cli, binary_path, filename, &content, None, registry)
+        }
+        #[cfg(feature = "extended-formats")]
+        OutputFormat::Binpb => {
+            registry.stats.total_found += 1;
+            if registry.descriptor_set.add(reconstructor) {
+                Outcome {
+                    decision: Decision::Written,
+                    content_hash: None,
+                    output_path: Some(cli.output.join("descriptors.binpb")),
+                }
+            } else {
+                debug!("Skipping {}: already in the descriptor set", filename);
+                registry.stats.duplicates_skipped += 1;
+                Outcome {
+                    decision: Decision::Duplicate,
+                    ..Outcome::skipped()
+                }
+            }
+        }
+        #[cfg(feature = "extended-formats")]
+        OutputFormat::Protojson | OutputFormat::Textproto => {
+            let (rendered, extension) = match cli.format {
+                OutputFormat::Protojson => (reconstructor.to_json(), "json"),
+                _ => (reconstructor.to_text_format(), "txtpb"),
+            };
+            match rendered {
+                Ok(mut content) => {
+                    if !content.ends_with('\n') {
+                        content.push('\n');
                     }
-                    OutputFormat::Proto => {
-                        // Register and get output path
-                        let output_path = registry.register(
-                            filename,
-                            &content,
-                            &content_hash,
-                            &cli.output,
-                            Some(binary_path),
-                            cli.conflict_strategy,
-                        );
-
-                        if let Some(output_path) = output_path {
-                            if cli.dry_run {
-                                println!("Would write: {}", output_path.display());
-                                if cli.verbose > 0 {
-                                    println!("---");
-                                    println!("{}", content);
-                                    println!("---");
-                                }
-                            } else {
-                                match write_proto_file(&output_path, &content, cli.force) {
-                                    Ok(()) => {
-                                        println!("Wrote {}", output_path.display());
-                                        registry.stats.written += 1;
-                                    }
-                                    Err(e) => {
-                                        error!("Failed to write {}: {}", output_path.display(), e);
-                                    }
-                                }
-                            }
-                        }
+                    write_output(
+                        cli,
+                        binary_path,
+                        filename,
+                        &content,
+                        Some(extension),
+                        registry,
+                    )
+                }
+                Err(e) => {
+                    error!("Failed to convert {}: {}", filename, e);
+                    Outcome {
+                        decision: Decision::Failed,
+                        ..Outcome::skipped()
                     }
                 }
             }
+        }
+    }
+}
+
+/// Register one output file and write it, or show it on a dry run
+///
+/// `extension` replaces `.proto` in the output path. The listing formats
+/// print nothing here, and with `--list-only` they only register the file.
+fn write_output(
+    cli: &Cli,
+    binary_path: &Path,
+    filename: &str,
+    content: &str,
+    extension: Option<&str>,
+    registry: &mut ProtoRegistry,
+) -> Outcome {
+    // Register and get output path
+    let content_hash = ProtoRegistry::content_hash(content);
+    let mut decision = registry.classify(filename, &content_hash, cli.conflict_strategy);
+    let output_path = registry.register(
+        filename,
+        content,
+        &content_hash,
+        &cli.output,
+        Some(binary_path),
+        cli.conflict_strategy,
+    );
+    let output_path = output_path.map(|mut output_path| {
+        if let Some(extension) = extension {
+            output_path.set_extension(extension);
+        }
+        output_path
+    });
+
+    let listing = cli.format.is_listing();
+    match &output_path {
+        Some(_) if listing && cli.list_only => {}
+        Some(output_path) if cli.dry_run => {
+            if !listing {
+                println!("Would write: {}", output_path.display());
+            }
+            if cli.verbose > 0 && !listing {
+                println!("---");
+                println!("{}", content);
+                println!("---");
+            }
+        }
+        Some(output_path) => match write_file(output_path, content.as_bytes(), cli.force) {
+            Ok(()) => {
+                if !listing {
+                    println!("Wrote {}", output_path.display());
+                }
+                registry.stats.written += 1;
+            }
             Err(e) => {
-                trace!(
-                    "Failed to parse descriptor {} at offset {}: {}",
-                    i + 1,
-                    result.range.start,
-                    e
-                );
+                error!("Failed to write {}: {}", output_path.display(), e);
+                decision = Decision::Failed;
+            }
+        },
+        None => {}
+    }
+
+    Outcome {
+        decision,
+        content_hash: Some(content_hash),
+        output_path,
+    }
+}
+
+/// Write the files collected for `--format binpb` as one descriptor set
+#[cfg(feature = "extended-formats")]
+fn write_descriptor_set(cli: &Cli, registry: &mut ProtoRegistry) {
+    let set = &registry.descriptor_set;
+    if set.is_empty() {
+        return;
+    }
+
+    let output_path = cli.output.join("descriptors.binpb");
+    if cli.dry_run {
+        println!(
+            "Would write: {} ({} files)",
+            output_path.display(),
+            set.len()
+        );
+        if cli.verbose > 0 {
+            for name in set.names() {
+                println!("  {}", name);
             }
         }
+        return;
     }
 
-    Ok(())
+    match write_file(&output_path, &set.encode(), cli.force) {
+        Ok(()) => {
+            println!("Wrote {} ({} files)", output_path.display(), set.len());
+            registry.stats.written += 1;
+        }
+        Err(e) => {
+            error!("Failed to write {}: {}", output_path.display(), e);
+        }
+    }
+}
+
+/// Write a fidelity report for one reconstructed file
+///
+/// Returns the report, and true if the file recompiles to its original
+/// descriptor.
+fn verify_descriptor(
+    verifier: &Verifier,
+    reconstructor: &ProtoReconstructor,
+    content: &str,
+) -> (String, bool) {
+    use std::fmt::Write as _;
+
+    let filename = reconstructor.filename();
+    let mut out = String::new();
+    let faithful = match verifier.verify(reconstructor, content) {
+        Ok(report) if report.is_faithful() => {
+            let _ = writeln!(
+                out,
+                "Verified {}: {:.1}% fidelity ({} values)",
+                filename,
+                report.fidelity() * 100.0,
+                report.compared
+            );
+            true
+        }
+        Ok(report) => {
+            let _ = writeln!(
+                out,
+                "Mismatch in {}: {:.1}% fidelity ({} of {} values differ)",
+                filename,
+                report.fidelity() * 100.0,
+                report.differences.len(),
+                report.compared
+            );
+            for difference in &report.differences {
+                let _ = writeln!(out, "  {}", difference);
+            }
+            false
+        }
+        Err(e) => {
+            let _ = writeln!(out, "Could not verify {}: {}", filename, e);
+            false
+        }
+    };
+
+    (out, faithful)
 }
 
-/// Write a proto file to disk with path traversal protection
-fn write_proto_file(output_path: &Path, content: &str, force: bool) -> Result<()> {
+/// Write an output file to disk with path traversal protection
+fn write_file(output_path: &Path, content: &[u8], force: bool) -> Result<()> {
     // Create parent directories
     if let Some(parent) = output_path.parent() {
         fs::create_dir_all(parent)
@@ -502,7 +1477,7 @@ fn write_proto_file(output_path: &Path, content: &str, force: bool) -> Result<()
     let mut file = fs::File::create(output_path)
         .with_context(|| format!("Failed to create file: {}", output_path.display()))?;
 
-    file.write_all(content.as_bytes())
+    file.write_all(content)
         .with_context(|| format!("Failed to write file: {}", output_path.display()))?;
 
     Ok(())
@@ -586,6 +1561,165 @@ mod tests {
         assert_eq!(registry.stats.conflicts_renamed, 1);
     }
 
+    #[test]
+    fn test_proto_registry_conflict_source_suffix() {
+        let mut registry = ProtoRegistry::new();
+        let temp_dir = TempDir::new().unwrap();
+        let strategy = ConflictStrategy::SourceSuffix;
+
+        registry.register("a.proto", "", "1111", temp_dir.path(), None, strategy);
+        // An image of a dyld shared cache is named after its framework
+        let image = Path::new("/System/Library/Frameworks/Foo.framework/Versions/A/Foo");
+        let path = registry.register(
+            "a.proto",
+            "",
+            "2222",
+            temp_dir.path(),
+            Some(image),
+            strategy,
+        );
+        assert!(path.unwrap().ends_with("a~from-Foo.proto"));
+    }
+
+    #[test]
+    fn test_classify() {
+        let mut registry = ProtoRegistry::new();
+        let temp_dir = TempDir::new().unwrap();
+        let strategy = ConflictStrategy::HashSuffix;
+
+        assert_eq!(
+            registry.classify("a.proto", "1111", strategy),
+            Decision::Written
+        );
+        registry.register("a.proto", "", "1111", temp_dir.path(), None, strategy);
+        assert_eq!(
+            registry.classify("a.proto", "1111", strategy),
+            Decision::Duplicate
+        );
+        assert_eq!(
+            registry.classify("a.proto", "2222", strategy),
+            Decision::Renamed
+        );
+        assert_eq!(
+            registry.classify("a.proto", "2222", ConflictStrategy::SkipConflicts),
+            Decision::Skipped
+        );
+    }
+
+    #[test]
+    fn test_candidate_record() {
+        let candidate = Candidate {
+            index: 0,
+            binary: Path::new("bin/server"),
+            range: Some(16..48),
+            data: &[],
+            confidence: Some(0.8),
+            #[cfg(feature = "object-files")]
+            location: None,
+            #[cfg(feature = "object-files")]
+            cache: None,
+            slices: &[],
+        };
+        let record = candidate_record(&candidate);
+        assert_eq!(record["binary"], "bin/server");
+        assert_eq!(record["offset"], 16);
+        assert_eq!(record["end"], 48);
+        assert_eq!(record["size"], 32);
+        assert_eq!(record["confidence"], 0.8);
+
+        let stub = Candidate {
+            range: None,
+            ..candidate
+        };
+        assert!(candidate_record(&stub)["binary"].is_null());
+        assert!(candidate_record(&stub)["slices"].is_null());
+    }
+
+    #[test]
+    fn test_candidate_record_slices() {
+        let slices = [
+            SliceMatch {
+                arch: "x86_64".to_string(),
+                range: 16..48,
+                #[cfg(feature = "object-files")]
+                location: None,
+            },
+            SliceMatch {
+                arch: "arm64".to_string(),
+                range: 32..64,
+                #[cfg(feature = "object-files")]
+                location: None,
+            },
+        ];
+        let candidate = Candidate {
+            index: 0,
+            binary: Path::new("bin/server"),
+            range: Some(16400..16432),
+            data: &[],
+            confidence: Some(1.0),
+            #[cfg(feature = "object-files")]
+            location: None,
+            #[cfg(feature = "object-files")]
+            cache: None,
+            slices: &slices,
+        };
+        let record = candidate_record(&candidate);
+        assert_eq!(record["offset"], 16400);
+        assert_eq!(record["slices"][0]["arch"], "x86_64");
+        assert_eq!(record["slices"][1]["arch"], "arm64");
+        assert_eq!(record["slices"][1]["offset"], 32);
+        assert_eq!(record["slices"][1]["end"], 64);
+    }
+
+    #[cfg(feature = "object-files")]
+    #[test]
+    fn test_candidate_record_location() {
+        let location = zarja_core::Location {
+            section: Some("__const".to_string()),
+            segment: Some("__TEXT".to_string()),
+            address: Some(0x1000_2f40),
+            symbol: Some(zarja_core::scanner::NearSymbol {
+                name: "_descriptor_table_protodef_foo_2eproto".to_string(),
+                offset: 0,
+            }),
+        };
+        let candidate = Candidate {
+            index: 0,
+            binary: Path::new("bin/server"),
+            range: Some(16..48),
+            data: &[],
+            confidence: Some(1.0),
+            location: Some(&location),
+            cache: None,
+            slices: &[],
+        };
+        let record = candidate_record(&candidate);
+        assert!(record["cache"].is_null());
+        assert_eq!(record["section"], "__const");
+        assert_eq!(record["segment"], "__TEXT");
+        assert_eq!(record["address"], "0x10002f40");
+        assert_eq!(record["symbol"], "_descriptor_table_protodef_foo_2eproto");
+        assert_eq!(record["symbol_offset"], 0);
+
+        let flat = Candidate {
+            location: None,
+            ..candidate
+        };
+        assert!(candidate_record(&flat)["address"].is_null());
+
+        let cached = Candidate {
+            binary: Path::new("/System/Library/Frameworks/Foo.framework/Versions/A/Foo"),
+            cache: Some(Path::new("dyld_shared_cache_arm64e.01")),
+            ..flat
+        };
+        let record = candidate_record(&cached);
+        assert_eq!(
+            record["binary"],
+            "/System/Library/Frameworks/Foo.framework/Versions/A/Foo"
+        );
+        assert_eq!(record["cache"], "dyld_shared_cache_arm64e.01");
+    }
+
     #[test]
     fn test_add_suffix() {
         assert_eq!(
@@ -615,6 +1749,113 @@ mod tests {
         assert!(!is_likely_binary(Path::new("/tmp/test.txt")));
         assert!(!is_likely_binary(Path::new("/tmp/test.json")));
         assert!(!is_likely_binary(Path::new("/tmp/test.proto")));
+
+        // Universal binaries with 32- and 64-bit offsets
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("app.bin");
+        for magic in [0xCAFE_BABE_u32, 0xCAFE_BABF] {
+            let mut data = magic.to_be_bytes().to_vec();
+            data.resize(2048, 0);
+            fs::write(&path, &data).unwrap();
+            assert!(is_likely_binary(&path));
+        }
+        fs::write(&path, [0u8; 2048]).unwrap();
+        assert!(!is_likely_binary(&path));
+    }
+
+    #[cfg(feature = "object-files")]
+    #[test]
+    fn test_dyld_cache_fallback() {
+        let put = |data: &mut Vec<u8>, at: usize, bytes: &[u8]| {
+            data[at..at + bytes.len()].copy_from_slice(bytes);
+        };
+        // A main cache file that needs a `.01` subcache, with a record
+        // after its header
+        let mut main = vec![0; 0x2000];
+        put(&mut main, 0, b"dyld_v1  x86_64\0");
+        put(&mut main, 0x10, &0x1d0_u32.to_le_bytes());
+        put(&mut main, 0x188, &0x300_u32.to_le_bytes());
+        put(&mut main, 0x18c, &1_u32.to_le_bytes());
+        put(&mut main, 0x300, &[2; 16]);
+        put(&mut main, 0x318, b".01");
+        let record = b"\x0a\x07a.proto\x12\x03foo\x62\x06proto3\0";
+        put(&mut main, 0x1000, record);
+        assert_eq!(DyldCache::subcache_suffixes(&main).unwrap(), [".01"]);
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("dyld_shared_cache_x86_64");
+        fs::write(&path, &main).unwrap();
+        let cli = Cli::parse_from(["zarja", "-f", path.to_str().unwrap()]);
+        let scan = || {
+            let scans = scan_binary(&cli, &path, ThreadBudget::new(0)).unwrap();
+            let found: Vec<_> = scans
+                .iter()
+                .map(|scan| {
+                    assert!(scan.results.iter().all(|r| r.image.is_none()));
+                    let starts: Vec<_> = scan.results.iter().map(|r| r.range.start).collect();
+                    (scan.path.clone(), starts)
+                })
+                .collect();
+            found
+        };
+
+        // The subcache is missing
+        assert_eq!(scan(), [(path.clone(), vec![0x1000])]);
+
+        // The subcache doesn't belong to the cache, and is scanned on its own
+        let sub = temp_dir.path().join("dyld_shared_cache_x86_64.01");
+        let mut data = vec![0; 0x1000];
+        put(
+            &mut data,
+            0x100,
+            b"\x0a\x07b.proto\x12\x03foo\x62\x06proto3\0",
+        );
+        fs::write(&sub, &data).unwrap();
+        assert_eq!(
+            scan(),
+            [(path.clone(), vec![0x1000]), (sub.clone(), vec![0x100])]
+        );
+
+        // Also when the directory is scanned
+        let output = TempDir::new().unwrap();
+        let cli = Cli::parse_from([
+            "zarja",
+            "-d",
+            temp_dir.path().to_str().unwrap(),
+            "-o",
+            output.path().to_str().unwrap(),
+        ]);
+        process_directory(&cli, temp_dir.path()).unwrap();
+        assert!(output.path().join("a.proto").exists());
+        assert!(output.path().join("b.proto").exists());
+    }
+
+    #[test]
+    fn test_parallel_map_keeps_order() {
+        let items: Vec<usize> = (0..1000).collect();
+        let expected: Vec<usize> = items.iter().map(|i| i * 2).collect();
+        for jobs in [1, 3, 16] {
+            assert_eq!(parallel_map(jobs, &items, |i| i * 2), expected);
+        }
+        assert!(parallel_map(4, &[] as &[usize], |i| *i).is_empty());
+    }
+
+    #[test]
+    fn test_parallel_map_reports_idle_workers() {
+        let items: Vec<usize> = (0..100).collect();
+        for jobs in [1, 4] {
+            let idle = AtomicUsize::new(0);
+            let doubled = parallel_map_then(
+                jobs,
+                &items,
+                |i| i * 2,
+                || {
+                    idle.fetch_add(1, Ordering::Relaxed);
+                },
+            );
+            assert_eq!(doubled[99], 198);
+            assert_eq!(idle.into_inner(), jobs);
+        }
     }
 
     #[test]
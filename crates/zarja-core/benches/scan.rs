@@ -0,0 +1,74 @@
+//! Scanner throughput.
+//!
+//! Target, release build on one x86-64 core: at least 2 GiB/s on `noise`
+//! (no suffixes) and 1 GiB/s on `dense` (a `.proto` string every 512
+//! bytes, as in debug info). A byte-by-byte search with backtracking did
+//! about 460 and 310 MiB/s. `dense/threads` scans the same input split
+//! into chunks over every core. Run with `cargo bench -p zarja-core`.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use prost::Message;
+use zarja_core::{ScanStrategy, Scanner, ScannerConfig};
+
+const LEN: usize = 64 * 1024 * 1024;
+
+/// Deterministic pseudo-random bytes (xorshift)
+fn noise(len: usize) -> Vec<u8> {
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+/// Noise with a file path every 512 bytes and a descriptor every 64 KiB
+fn dense(len: usize) -> Vec<u8> {
+    let mut data = noise(len);
+    let descriptor = prost_types::FileDescriptorProto {
+        name: Some("bench/v1/service.proto".to_string()),
+        package: Some("bench.v1".to_string()),
+        syntax: Some("proto3".to_string()),
+        ..Default::default()
+    }
+    .encode_to_vec();
+    // A zero tag ends the record
+    let descriptor = [descriptor, vec![0]].concat();
+    for at in (0..len - 512).step_by(512) {
+        let insert: &[u8] = if at % (64 * 1024) == 0 {
+            &descriptor
+        } else {
+            b"\0src/proto/bench/v1/service.proto\0"
+        };
+        data[at..at + insert.len()].copy_from_slice(insert);
+    }
+    data
+}
+
+fn bench_scan(c: &mut Criterion) {
+    let scanner = Scanner::with_config(ScannerConfig::new().threads(1));
+    let mut group = c.benchmark_group("scan");
+    group.throughput(Throughput::Bytes(LEN as u64));
+    group.sample_size(10);
+
+    let data = noise(LEN);
+    group.bench_function("noise", |b| b.iter(|| scanner.scan(&data).unwrap()));
+
+    let data = dense(LEN);
+    assert_eq!(scanner.scan(&data).unwrap().len(), LEN / (64 * 1024));
+    group.bench_function("dense", |b| b.iter(|| scanner.scan(&data).unwrap()));
+
+    let threaded = Scanner::new();
+    assert_eq!(threaded.scan(&data).unwrap().len(), LEN / (64 * 1024));
+    group.bench_function("dense/threads", |b| {
+        b.iter(|| threaded.scan(&data).unwrap())
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_scan);
+criterion_main!(benches);
@@ -0,0 +1,151 @@
+//! Diagnostics for rejected candidates.
+//!
+//! Most `.proto` byte sequences in a binary are not descriptors, and most
+//! rejections are expected. When a descriptor is missing from the output,
+//! the [`Diagnostic`]s collected by [`Scanner::scan_with_diagnostics`] and
+//! by callers that decode the results say where each candidate was dropped
+//! and why.
+//!
+//! [`Scanner::scan_with_diagnostics`]: crate::Scanner::scan_with_diagnostics
+
+use crate::error::Error;
+use std::fmt;
+
+/// Bytes shown before the offset in [`hexdump`]
+const CONTEXT_BEFORE: usize = 8;
+/// Bytes shown from the offset on in [`hexdump`]
+const CONTEXT_AFTER: usize = 24;
+
+/// The step at which a candidate was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Stage {
+    /// No record start was found before a `.proto` suffix
+    Backtrack,
+    /// The record's fields couldn't be consumed into a descriptor of
+    /// acceptable size
+    Consume,
+    /// The record scored below the confidence threshold; see
+    /// [`Confidence`](crate::scanner::Confidence)
+    Confidence,
+    /// The record didn't decode as a `FileDescriptorProto`
+    Decode,
+    /// The descriptor decoded but didn't resolve into a file descriptor;
+    /// it is still reconstructed, without resolved types
+    Build,
+}
+
+impl Stage {
+    /// Returns the lowercase name of the stage
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Backtrack => "backtrack",
+            Self::Consume => "consume",
+            Self::Confidence => "confidence",
+            Self::Decode => "decode",
+            Self::Build => "build",
+        }
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Why a candidate descriptor was rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Byte offset of the candidate in the input
+    pub offset: usize,
+    /// Where the candidate was rejected
+    pub stage: Stage,
+    /// Name of the [`Error`] variant
+    pub kind: &'static str,
+    /// The error message
+    pub message: String,
+    /// Hexdump of the bytes around the candidate, see [`hexdump`]
+    pub context: String,
+}
+
+impl Diagnostic {
+    /// Creates a diagnostic for a candidate at `offset` in `data`
+    pub fn new(stage: Stage, error: &Error, data: &[u8], offset: usize) -> Self {
+        Self {
+            offset,
+            stage,
+            kind: error.kind(),
+            message: error.to_string(),
+            context: hexdump(data, offset),
+        }
+    }
+
+    /// Sets the reported offset, for a `data` that starts partway into the
+    /// input (such as a scanned record)
+    pub fn with_offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at offset {}: {} [{}]",
+            self.stage, self.offset, self.message, self.context
+        )
+    }
+}
+
+/// Formats the bytes around `data[offset]` as hex, with that byte in
+/// brackets
+///
+/// ```
+/// use zarja_core::diagnostics::hexdump;
+///
+/// assert_eq!(hexdump(b"\x01\x0a\x12", 1), "01 [0a] 12");
+/// ```
+pub fn hexdump(data: &[u8], offset: usize) -> String {
+    let start = offset.saturating_sub(CONTEXT_BEFORE);
+    let end = offset.saturating_add(CONTEXT_AFTER).min(data.len());
+    (start..end)
+        .map(|i| {
+            if i == offset {
+                format!("[{:02x}]", data[i])
+            } else {
+                format!("{:02x}", data[i])
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hexdump_bounds() {
+        let data: Vec<u8> = (0..64).collect();
+        let dump = hexdump(&data, 10);
+        assert!(dump.starts_with("02 03"));
+        assert!(dump.contains("09 [0a] 0b"));
+        assert!(dump.ends_with("21"));
+        assert_eq!(hexdump(&data, 63), "37 38 39 3a 3b 3c 3d 3e [3f]");
+        assert_eq!(hexdump(&[], 0), "");
+    }
+
+    #[test]
+    fn test_diagnostic() {
+        let error = Error::invalid_wire_format(3, "truncated");
+        let diagnostic = Diagnostic::new(Stage::Consume, &error, b"\x0a\x05abc", 0);
+        assert_eq!(diagnostic.kind, "InvalidWireFormat");
+        assert_eq!(diagnostic.context, "[0a] 05 61 62 63");
+        assert_eq!(
+            diagnostic.to_string(),
+            "consume at offset 0: invalid protobuf wire format at offset 3: truncated \
+             [[0a] 05 61 62 63]"
+        );
+    }
+}
@@ -23,6 +23,16 @@ pub enum Error {
         source: std::io::Error,
     },
 
+    /// Failed to read from an input stream
+    #[error("failed to read input at offset {offset}: {source}")]
+    ReadInput {
+        /// Offset in the input where reading failed
+        offset: u64,
+        /// Underlying I/O error
+        #[source]
+        source: std::io::Error,
+    },
+
     /// Failed to write output file
     #[error("failed to write file '{path}': {source}")]
     FileWrite {
@@ -66,6 +76,32 @@ pub enum Error {
         offset: usize,
     },
 
+    /// Candidate record outside the scanner's size limits
+    #[error("record of {size} bytes at offset {offset} is not within {min}..={max} bytes")]
+    RecordSize {
+        /// Byte offset of the record
+        offset: usize,
+        /// Size of the record
+        size: usize,
+        /// Minimum accepted size
+        min: usize,
+        /// Maximum accepted size
+        max: usize,
+    },
+
+    /// Candidate record below the scanner's confidence threshold
+    #[error("record at offset {offset} scored {score:.2}, below {min:.2} ({checks})")]
+    LowConfidence {
+        /// Byte offset of the record
+        offset: usize,
+        /// Confidence score of the record
+        score: f32,
+        /// Minimum accepted score
+        min: f32,
+        /// Scores of the individual checks
+        checks: String,
+    },
+
     /// Failed to parse FileDescriptorProto
     #[error("failed to parse FileDescriptorProto: {0}")]
     DescriptorParse(#[from] prost::DecodeError),
@@ -94,6 +130,19 @@ pub enum Error {
         syntax: String,
     },
 
+    /// A dyld shared cache's headers don't parse
+    #[error("invalid dyld shared cache: {0}")]
+    InvalidDyldCache(String),
+
+    /// Reconstructed source failed to compile
+    #[error("failed to recompile '{file}': {details}")]
+    Recompile {
+        /// Name of the reconstructed file
+        file: String,
+        /// Compiler error
+        details: String,
+    },
+
     /// Generic internal error
     #[error("internal error: {0}")]
     Internal(String),
@@ -147,16 +196,56 @@ impl Error {
         Self::DescriptorBuild(msg.into())
     }
 
+    /// Creates a new invalid dyld shared cache error
+    pub fn invalid_dyld_cache(msg: impl Into<String>) -> Self {
+        Self::InvalidDyldCache(msg.into())
+    }
+
+    /// Creates a new recompile error
+    pub fn recompile(file: impl Into<String>, details: impl Into<String>) -> Self {
+        Self::Recompile {
+            file: file.into(),
+            details: details.into(),
+        }
+    }
+
     /// Creates a new internal error
     pub fn internal(msg: impl Into<String>) -> Self {
         Self::Internal(msg.into())
     }
 
+    /// Returns the name of the variant, e.g. `"InvalidWireFormat"`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::FileRead { .. } => "FileRead",
+            Self::ReadInput { .. } => "ReadInput",
+            Self::FileWrite { .. } => "FileWrite",
+            Self::DirectoryCreate { .. } => "DirectoryCreate",
+            Self::PathTraversal { .. } => "PathTraversal",
+            Self::InvalidWireFormat { .. } => "InvalidWireFormat",
+            Self::VarintDecode { .. } => "VarintDecode",
+            Self::RecordSize { .. } => "RecordSize",
+            Self::LowConfidence { .. } => "LowConfidence",
+            Self::DescriptorParse(_) => "DescriptorParse",
+            Self::DescriptorBuild(_) => "DescriptorBuild",
+            Self::NoDescriptorsFound => "NoDescriptorsFound",
+            Self::InvalidFieldNumber { .. } => "InvalidFieldNumber",
+            Self::UnsupportedSyntax { .. } => "UnsupportedSyntax",
+            Self::InvalidDyldCache(_) => "InvalidDyldCache",
+            Self::Recompile { .. } => "Recompile",
+            Self::Internal(_) => "Internal",
+        }
+    }
+
     /// Returns true if this is a recoverable error that should be skipped
     pub fn is_recoverable(&self) -> bool {
         matches!(
             self,
-            Self::DescriptorParse(_) | Self::DescriptorBuild(_) | Self::InvalidWireFormat { .. }
+            Self::DescriptorParse(_)
+                | Self::DescriptorBuild(_)
+                | Self::InvalidWireFormat { .. }
+                | Self::RecordSize { .. }
+                | Self::LowConfidence { .. }
         )
     }
 }
@@ -177,4 +266,10 @@ mod tests {
         assert!(Error::descriptor_build("test").is_recoverable());
         assert!(!Error::path_traversal("/test").is_recoverable());
     }
+
+    #[test]
+    fn test_kind() {
+        assert_eq!(Error::varint_decode(0).kind(), "VarintDecode");
+        assert_eq!(Error::descriptor_build("test").kind(), "DescriptorBuild");
+    }
 }
@@ -14,6 +14,7 @@
 //! - [`scanner`]: Binary scanning and wire format parsing
 //! - [`proto`]: Proto definition reconstruction
 //! - [`error`]: Error types and handling
+//! - [`diagnostics`]: Why candidates were rejected
 //!
 //! ## Example
 //!
@@ -48,14 +49,23 @@
 #![deny(unsafe_code)]
 #![warn(missing_docs, rust_2018_idioms, unreachable_pub)]
 
+pub mod diagnostics;
 pub mod error;
 pub mod proto;
 pub mod scanner;
 
 // Re-export primary types for convenience
+pub use diagnostics::{Diagnostic, Stage};
 pub use error::{Error, Result};
-pub use proto::{NullWriter, ProtoReconstructor, ProtoWriter, ReconstructorConfig, StatsWriter};
-pub use scanner::{ScanResult, ScanStrategy, Scanner, ScannerConfig};
+#[cfg(feature = "extended-formats")]
+pub use proto::DescriptorSet;
+pub use proto::{
+    ExtensionRegistry, NullWriter, ProtoReconstructor, ProtoWriter, ReconstructionSession,
+    ReconstructorConfig, StatsWriter, Verifier, VerifyReport, VisitContext,
+};
+pub use scanner::{Confidence, ScanResult, ScanStrategy, Scanner, ScannerConfig, SliceMatch};
+#[cfg(feature = "object-files")]
+pub use scanner::{DyldCache, Location, ObjectMap};
 
 /// Crate version for programmatic access
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -0,0 +1,99 @@
+//! Bundled descriptors.
+//!
+//! zarja embeds the well-known types and a few widely used libraries. They
+//! resolve imports that no recovered file provides, supply the definitions
+//! of common custom options, and identify recovered files that are plain
+//! copies of a library rather than part of the application.
+
+use crate::scanner::{fields, WireType};
+use prost::Message;
+use prost_types::{FileDescriptorProto, FileDescriptorSet};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Serialized `FileDescriptorSet` of the bundled files:
+///
+/// - the well-known types (`google/protobuf/*.proto`, including
+///   `descriptor.proto`, `compiler/plugin.proto` and the language feature
+///   files)
+/// - `google/api/annotations.proto` and `google/api/http.proto`
+/// - `google/rpc/status.proto`, `code.proto` and `error_details.proto`
+/// - `grpc/health/v1/health.proto`, `grpc/reflection/v1/reflection.proto`
+///   and `grpc/reflection/v1alpha/reflection.proto`
+/// - `validate/validate.proto` (protoc-gen-validate)
+///
+/// Regenerate with:
+///
+/// ```text
+/// protoc --include_imports -o descriptors/bundled.binpb \
+///     google/protobuf/{any,api,descriptor,duration,empty,field_mask}.proto \
+///     google/protobuf/{source_context,struct,timestamp,type,wrappers}.proto \
+///     google/protobuf/compiler/plugin.proto \
+///     google/protobuf/{cpp,java,go}_features.proto \
+///     google/api/{annotations,http}.proto \
+///     google/rpc/{status,code,error_details}.proto \
+///     grpc/health/v1/health.proto grpc/reflection/v1/reflection.proto \
+///     grpc/reflection/v1alpha/reflection.proto validate/validate.proto
+/// ```
+pub(crate) const BUNDLED: &[u8] = include_bytes!("../../descriptors/bundled.binpb");
+
+/// Decodes the bundled files
+pub(crate) fn files() -> Vec<FileDescriptorProto> {
+    // The bundled set is generated by protoc and always decodes
+    FileDescriptorSet::decode(BUNDLED)
+        .map(|set| set.file)
+        .unwrap_or_default()
+}
+
+/// Returns the serialized bundled file with the given name
+///
+/// The bytes are the ones protoc wrote, so they can be compared with a
+/// recovered descriptor directly.
+pub(crate) fn file(name: &str) -> Option<&'static [u8]> {
+    static FILES: OnceLock<HashMap<String, &'static [u8]>> = OnceLock::new();
+    FILES
+        .get_or_init(|| {
+            fields(BUNDLED)
+                .filter(|field| field.number == 1 && field.wire_type == WireType::Len)
+                .filter_map(|field| {
+                    let proto = FileDescriptorProto::decode(field.value).ok()?;
+                    Some((proto.name().to_string(), field.value))
+                })
+                .collect()
+        })
+        .get(name)
+        .copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::ProtoReconstructor;
+
+    #[test]
+    fn test_bundled_files() {
+        let names: Vec<String> = files().iter().map(|f| f.name().to_string()).collect();
+        for name in [
+            "google/protobuf/descriptor.proto",
+            "google/api/annotations.proto",
+            "google/rpc/status.proto",
+            "grpc/health/v1/health.proto",
+            "validate/validate.proto",
+        ] {
+            assert!(names.iter().any(|n| n == name), "{} is not bundled", name);
+            let data = file(name).unwrap();
+            assert_eq!(FileDescriptorProto::decode(data).unwrap().name(), name);
+        }
+        assert!(file("missing.proto").is_none());
+    }
+
+    #[test]
+    fn test_is_bundled() {
+        let data = file("google/api/http.proto").unwrap();
+        assert!(ProtoReconstructor::from_bytes(data).unwrap().is_bundled());
+
+        let mut proto = FileDescriptorProto::decode(data).unwrap();
+        proto.message_type[0].name = Some("Changed".to_string());
+        assert!(!ProtoReconstructor::from_proto(proto).unwrap().is_bundled());
+    }
+}
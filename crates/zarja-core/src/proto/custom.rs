@@ -0,0 +1,608 @@
+//! Custom options.
+//!
+//! Custom options such as `(google.api.http)` are extension fields of the
+//! `*Options` messages. `prost_types` drops them, so they are decoded from
+//! the raw option bytes kept by [`super::raw`] and resolved against an
+//! [`ExtensionRegistry`]: the extensions defined by every recovered file,
+//! plus a bundled set of commonly used option definitions.
+
+use super::bundled;
+use super::names::qualify;
+use super::options::is_standard;
+use super::raw::Element;
+use crate::scanner::{decode_varint, fields, WireType};
+use prost_types::field_descriptor_proto::{Label, Type};
+use prost_types::{
+    DescriptorProto, EnumDescriptorProto, FieldDescriptorProto, FileDescriptorProto,
+};
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+
+/// An extension field and its fully-qualified name
+#[derive(Debug, Clone)]
+struct Extension {
+    full_name: String,
+    field: FieldDescriptorProto,
+}
+
+/// Extension, message and enum definitions used to decode custom options
+///
+/// Names are stored fully-qualified without the leading dot.
+#[derive(Debug, Clone, Default)]
+pub struct ExtensionRegistry {
+    /// Extensions keyed by (extendee, field number)
+    extensions: HashMap<(String, u32), Extension>,
+    messages: HashMap<String, DescriptorProto>,
+    enums: HashMap<String, EnumDescriptorProto>,
+}
+
+impl ExtensionRegistry {
+    /// Creates an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a registry holding the bundled option definitions
+    pub fn bundled() -> Self {
+        let mut registry = Self::new();
+        for file in &bundled::files() {
+            registry.add_file(file);
+        }
+        registry
+    }
+
+    /// Returns a shared instance of [`Self::bundled`]
+    pub(crate) fn shared_bundled() -> Arc<Self> {
+        static SHARED: OnceLock<Arc<ExtensionRegistry>> = OnceLock::new();
+        SHARED.get_or_init(|| Arc::new(Self::bundled())).clone()
+    }
+
+    /// Adds every extension, message and enum defined in a file
+    pub fn add_file(&mut self, file: &FileDescriptorProto) {
+        let scope = file.package().to_string();
+        self.add_extensions(&scope, &file.extension);
+        for message in &file.message_type {
+            self.add_message(&scope, message);
+        }
+        for enum_type in &file.enum_type {
+            self.enums
+                .insert(qualify(&scope, enum_type.name()), enum_type.clone());
+        }
+    }
+
+    /// Returns the number of known extensions
+    pub fn extension_count(&self) -> usize {
+        self.extensions.len()
+    }
+
+    fn add_message(&mut self, scope: &str, message: &DescriptorProto) {
+        let name = qualify(scope, message.name());
+        self.add_extensions(&name, &message.extension);
+        for nested in &message.nested_type {
+            self.add_message(&name, nested);
+        }
+        for enum_type in &message.enum_type {
+            self.enums
+                .insert(qualify(&name, enum_type.name()), enum_type.clone());
+        }
+        self.messages.insert(name, message.clone());
+    }
+
+    fn add_extensions(&mut self, scope: &str, extensions: &[FieldDescriptorProto]) {
+        for field in extensions {
+            let extendee = field.extendee().trim_start_matches('.').to_string();
+            self.extensions.insert(
+                (extendee, field.number() as u32),
+                Extension {
+                    full_name: qualify(scope, field.name()),
+                    field: field.clone(),
+                },
+            );
+        }
+    }
+}
+
+/// Looks definitions up in several registries, first match wins
+pub(crate) struct Resolver<'a> {
+    registries: Vec<&'a ExtensionRegistry>,
+}
+
+impl<'a> Resolver<'a> {
+    pub(crate) fn new(registries: Vec<&'a ExtensionRegistry>) -> Self {
+        Self { registries }
+    }
+
+    fn extension(&self, extendee: &str, number: u32) -> Option<&'a Extension> {
+        let key = (extendee.to_string(), number);
+        self.registries.iter().find_map(|r| r.extensions.get(&key))
+    }
+
+    fn message(&self, type_name: &str) -> Option<&'a DescriptorProto> {
+        let name = type_name.trim_start_matches('.');
+        self.registries.iter().find_map(|r| r.messages.get(name))
+    }
+
+    fn enum_type(&self, type_name: &str) -> Option<&'a EnumDescriptorProto> {
+        let name = type_name.trim_start_matches('.');
+        self.registries.iter().find_map(|r| r.enums.get(name))
+    }
+}
+
+/// Rendered custom options of one element
+#[derive(Debug, Default)]
+pub(crate) struct CustomOptions {
+    /// `(name) = value` assignments
+    pub(crate) assignments: Vec<String>,
+    /// Descriptions of option data that could not be resolved, for comments
+    pub(crate) unresolved: Vec<String>,
+}
+
+/// Full name of the options message of each element
+fn options_message(element: Element) -> &'static str {
+    match element {
+        Element::File => "google.protobuf.FileOptions",
+        Element::Message => "google.protobuf.MessageOptions",
+        Element::Field => "google.protobuf.FieldOptions",
+        Element::Oneof => "google.protobuf.OneofOptions",
+        Element::Enum => "google.protobuf.EnumOptions",
+        Element::EnumValue => "google.protobuf.EnumValueOptions",
+        Element::Service => "google.protobuf.ServiceOptions",
+        Element::Method => "google.protobuf.MethodOptions",
+        Element::ExtensionRange => "google.protobuf.ExtensionRangeOptions",
+    }
+}
+
+/// Renders the custom options set in raw `*Options` bytes
+///
+/// Message values of options written as `option ...;` statements are
+/// rendered as multi-line aggregates indented with `indent`; values inside
+/// `[...]` field option lists stay on one line.
+pub(crate) fn custom_options(
+    element: Element,
+    options: Option<&[u8]>,
+    resolver: &Resolver<'_>,
+    indent: &str,
+) -> CustomOptions {
+    let mut result = CustomOptions::default();
+    let Some(options) = options else {
+        return result;
+    };
+
+    let multiline = !matches!(
+        element,
+        Element::Field | Element::EnumValue | Element::ExtensionRange
+    );
+    let extendee = options_message(element);
+    let mut renderer = Renderer {
+        resolver,
+        indent: multiline.then_some(indent),
+        unresolved: Vec::new(),
+    };
+
+    // Group occurrences by field number, keeping first-seen order
+    let mut order: Vec<u32> = Vec::new();
+    let mut occurrences: HashMap<u32, Vec<(WireType, &[u8])>> = HashMap::new();
+    for field in fields(options) {
+        if is_standard(element, field.number) {
+            continue;
+        }
+        if !occurrences.contains_key(&field.number) {
+            order.push(field.number);
+        }
+        occurrences
+            .entry(field.number)
+            .or_default()
+            .push((field.wire_type, field.value));
+    }
+
+    for number in order {
+        let values = &occurrences[&number];
+        let Some(extension) = resolver.extension(extendee, number) else {
+            for (wire_type, value) in values {
+                renderer.unresolved.push(format!(
+                    "{} field {} = {}",
+                    short_name(extendee),
+                    number,
+                    dump(*wire_type, value)
+                ));
+            }
+            continue;
+        };
+
+        let name = format!("({})", extension.full_name);
+        for value in renderer.render_field(&extension.field, values, &name, 0) {
+            result.assignments.push(format!("{} = {}", name, value));
+        }
+    }
+
+    result.unresolved = renderer.unresolved;
+    result
+}
+
+fn short_name(full_name: &str) -> &str {
+    full_name.rsplit('.').next().unwrap_or(full_name)
+}
+
+struct Renderer<'r, 'a> {
+    resolver: &'r Resolver<'a>,
+    /// Indentation unit for multi-line aggregates, `None` for single-line
+    indent: Option<&'r str>,
+    unresolved: Vec<String>,
+}
+
+impl Renderer<'_, '_> {
+    /// Renders every value of a field from its wire occurrences
+    ///
+    /// Singular scalars keep only the last value and singular messages
+    /// merge, as protobuf parsing would.
+    fn render_field(
+        &mut self,
+        field: &FieldDescriptorProto,
+        occurrences: &[(WireType, &[u8])],
+        path: &str,
+        depth: usize,
+    ) -> Vec<String> {
+        let repeated = field.label() == Label::Repeated;
+
+        if field.r#type() == Type::Message && !repeated {
+            let merged: Vec<u8> = occurrences
+                .iter()
+                .filter(|(wire_type, _)| *wire_type == WireType::Len)
+                .flat_map(|(_, value)| value.iter().copied())
+                .collect();
+            return vec![self.aggregate(field.type_name(), &merged, path, depth)];
+        }
+
+        let mut values = Vec::new();
+        for &(wire_type, value) in occurrences {
+            values.extend(self.render_value(field, wire_type, value, path, depth));
+        }
+        if !repeated && values.len() > 1 {
+            values.drain(..values.len() - 1);
+        }
+        values
+    }
+
+    fn render_value(
+        &mut self,
+        field: &FieldDescriptorProto,
+        wire_type: WireType,
+        value: &[u8],
+        path: &str,
+        depth: usize,
+    ) -> Vec<String> {
+        let rendered = match (field.r#type(), wire_type) {
+            (Type::Message, WireType::Len) => {
+                Some(vec![self.aggregate(field.type_name(), value, path, depth)])
+            }
+            // Strings are escaped byte-wise so non-UTF-8 payloads survive
+            (Type::String | Type::Bytes, WireType::Len) => {
+                Some(vec![format!("\"{}\"", escape_bytes(value))])
+            }
+            (ty, WireType::Len) if is_varint_type(ty) => {
+                // Packed repeated scalars
+                let mut values = Vec::new();
+                let mut rest = value;
+                while !rest.is_empty() {
+                    let Ok((v, len)) = decode_varint(rest) else {
+                        break;
+                    };
+                    values.push(self.varint(field, v));
+                    rest = &rest[len..];
+                }
+                Some(values)
+            }
+            (ty, WireType::Len) if fixed_width(ty).is_some() => {
+                let width = fixed_width(ty).unwrap_or(1);
+                Some(value.chunks_exact(width).map(|c| fixed(ty, c)).collect())
+            }
+            (ty, WireType::Varint) if is_varint_type(ty) => decode_varint(value)
+                .ok()
+                .map(|(v, _)| vec![self.varint(field, v)]),
+            (ty, WireType::I32) if fixed_width(ty) == Some(4) => Some(vec![fixed(ty, value)]),
+            (ty, WireType::I64) if fixed_width(ty) == Some(8) => Some(vec![fixed(ty, value)]),
+            _ => None,
+        };
+
+        rendered.unwrap_or_else(|| {
+            self.unresolved
+                .push(format!("{} = {}", path, dump(wire_type, value)));
+            Vec::new()
+        })
+    }
+
+    fn varint(&self, field: &FieldDescriptorProto, v: u64) -> String {
+        match field.r#type() {
+            Type::Int32 => (v as i32).to_string(),
+            Type::Int64 => (v as i64).to_string(),
+            Type::Uint32 => (v as u32).to_string(),
+            Type::Sint32 => ((v as u32 >> 1) as i32 ^ -((v & 1) as i32)).to_string(),
+            Type::Sint64 => ((v >> 1) as i64 ^ -((v & 1) as i64)).to_string(),
+            Type::Bool => (v != 0).to_string(),
+            Type::Enum => {
+                let number = v as i32;
+                self.resolver
+                    .enum_type(field.type_name())
+                    .and_then(|e| e.value.iter().find(|value| value.number() == number))
+                    .map(|value| value.name().to_string())
+                    .unwrap_or_else(|| number.to_string())
+            }
+            _ => v.to_string(),
+        }
+    }
+
+    /// Renders a message value in text format
+    fn aggregate(&mut self, type_name: &str, data: &[u8], path: &str, depth: usize) -> String {
+        let Some(message) = self.resolver.message(type_name) else {
+            self.unresolved.push(format!(
+                "{} = {} (unknown type {})",
+                path,
+                dump(WireType::Len, data),
+                type_name.trim_start_matches('.')
+            ));
+            return "{}".to_string();
+        };
+        let message_name = type_name.trim_start_matches('.');
+
+        // Group occurrences by field number, keeping first-seen order
+        let mut order: Vec<u32> = Vec::new();
+        let mut occurrences: HashMap<u32, Vec<(WireType, &[u8])>> = HashMap::new();
+        for field in fields(data) {
+            if !occurrences.contains_key(&field.number) {
+                order.push(field.number);
+            }
+            occurrences
+                .entry(field.number)
+                .or_default()
+                .push((field.wire_type, field.value));
+        }
+
+        let mut entries = Vec::new();
+        for number in order {
+            let values = &occurrences[&number];
+            let (name, field) =
+                if let Some(field) = message.field.iter().find(|f| f.number() == number as i32) {
+                    (field.name().to_string(), field)
+                } else if let Some(extension) = self.resolver.extension(message_name, number) {
+                    (format!("[{}]", extension.full_name), &extension.field)
+                } else {
+                    for (wire_type, value) in values {
+                        self.unresolved.push(format!(
+                            "{} field {} = {}",
+                            path,
+                            number,
+                            dump(*wire_type, value)
+                        ));
+                    }
+                    continue;
+                };
+
+            let field_path = format!("{}.{}", path, name);
+            let is_message = field.r#type() == Type::Message;
+            for value in self.render_field(field, values, &field_path, depth + 1) {
+                if is_message {
+                    entries.push(format!("{} {}", name, value));
+                } else {
+                    entries.push(format!("{}: {}", name, value));
+                }
+            }
+        }
+
+        if entries.is_empty() {
+            return "{}".to_string();
+        }
+
+        match self.indent {
+            Some(indent) => {
+                let inner = indent.repeat(depth + 1);
+                let mut out = String::from("{\n");
+                for entry in entries {
+                    out.push_str(&inner);
+                    out.push_str(&entry);
+                    out.push('\n');
+                }
+                out.push_str(&indent.repeat(depth));
+                out.push('}');
+                out
+            }
+            None => format!("{{ {} }}", entries.join(", ")),
+        }
+    }
+}
+
+fn is_varint_type(ty: Type) -> bool {
+    matches!(
+        ty,
+        Type::Int32
+            | Type::Int64
+            | Type::Uint32
+            | Type::Uint64
+            | Type::Sint32
+            | Type::Sint64
+            | Type::Bool
+            | Type::Enum
+    )
+}
+
+fn fixed_width(ty: Type) -> Option<usize> {
+    match ty {
+        Type::Fixed32 | Type::Sfixed32 | Type::Float => Some(4),
+        Type::Fixed64 | Type::Sfixed64 | Type::Double => Some(8),
+        _ => None,
+    }
+}
+
+/// Renders a little-endian fixed-width value
+fn fixed(ty: Type, bytes: &[u8]) -> String {
+    let mut buf = [0u8; 8];
+    let len = bytes.len().min(8);
+    buf[..len].copy_from_slice(&bytes[..len]);
+    let v32 = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+    let v64 = u64::from_le_bytes(buf);
+
+    match ty {
+        Type::Fixed32 => v32.to_string(),
+        Type::Sfixed32 => (v32 as i32).to_string(),
+        Type::Float => format_float(f32::from_bits(v32) as f64),
+        Type::Fixed64 => v64.to_string(),
+        Type::Sfixed64 => (v64 as i64).to_string(),
+        _ => format_float(f64::from_bits(v64)),
+    }
+}
+
+/// Formats a float the way text format spells it
+fn format_float(v: f64) -> String {
+    if v.is_nan() {
+        "nan".to_string()
+    } else if v.is_infinite() {
+        if v > 0.0 { "inf" } else { "-inf" }.to_string()
+    } else {
+        v.to_string()
+    }
+}
+
+/// Escapes arbitrary bytes for a text-format string literal
+pub(crate) fn escape_bytes(bytes: &[u8]) -> String {
+    let mut result = String::with_capacity(bytes.len());
+    for &b in bytes {
+        match b {
+            b'\\' => result.push_str("\\\\"),
+            b'"' => result.push_str("\\\""),
+            b'\n' => result.push_str("\\n"),
+            b'\r' => result.push_str("\\r"),
+            b'\t' => result.push_str("\\t"),
+            0x20..=0x7E => result.push(b as char),
+            _ => result.push_str(&format!("\\{:03o}", b)),
+        }
+    }
+    result
+}
+
+/// Describes raw wire data the way `protoc --decode_raw` would
+pub(crate) fn dump(wire_type: WireType, value: &[u8]) -> String {
+    match wire_type {
+        WireType::Varint => decode_varint(value)
+            .map(|(v, _)| v.to_string())
+            .unwrap_or_default(),
+        WireType::I32 | WireType::I64 => {
+            let hex: String = value.iter().rev().map(|b| format!("{:02x}", b)).collect();
+            format!("0x{}", hex)
+        }
+        WireType::Len => {
+            // Prefer a nested message when the payload parses completely
+            let parsed: Vec<_> = fields(value).collect();
+            let consumed: usize = parsed.iter().map(|f| f.len).sum();
+            if !value.is_empty() && consumed == value.len() {
+                let inner: Vec<String> = parsed
+                    .iter()
+                    .map(|f| format!("{}: {}", f.number, dump(f.wire_type, f.value)))
+                    .collect();
+                format!("{{ {} }}", inner.join(", "))
+            } else {
+                format!("\"{}\"", escape_bytes(value))
+            }
+        }
+        WireType::StartGroup | WireType::EndGroup => "<group>".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::raw::tests::{len_field, varint_field};
+
+    #[test]
+    fn test_bundled_registry() {
+        let registry = ExtensionRegistry::bundled();
+        assert!(registry
+            .extensions
+            .contains_key(&("google.protobuf.MethodOptions".to_string(), 72295728)));
+        assert!(registry.messages.contains_key("google.api.HttpRule"));
+        assert!(registry
+            .extensions
+            .contains_key(&("google.protobuf.FieldOptions".to_string(), 1071)));
+    }
+
+    #[test]
+    fn test_http_rule_option() {
+        let registry = ExtensionRegistry::bundled();
+        let resolver = Resolver::new(vec![&registry]);
+
+        // (google.api.http) = { get: "/v1/{name}" additional_bindings { post: "/v1" body: "*" } }
+        let binding = [len_field(4, b"/v1"), len_field(7, b"*")].concat();
+        let rule = [len_field(2, b"/v1/{name}"), len_field(11, &binding)].concat();
+        let options = [varint_field(33, 1), len_field(72295728, &rule)].concat();
+
+        let rendered = custom_options(Element::Method, Some(&options), &resolver, "  ");
+        assert_eq!(
+            rendered.assignments,
+            vec![
+                "(google.api.http) = {\n  get: \"/v1/{name}\"\n  additional_bindings {\n    \
+                 post: \"/v1\"\n    body: \"*\"\n  }\n}"
+            ]
+        );
+        assert!(rendered.unresolved.is_empty());
+    }
+
+    #[test]
+    fn test_single_line_field_option() {
+        let registry = ExtensionRegistry::bundled();
+        let resolver = Resolver::new(vec![&registry]);
+
+        // (validate.rules) = { string: { min_len: 1 } }
+        let rules = len_field(14, &varint_field(2, 1));
+        let options = len_field(1071, &rules);
+
+        let rendered = custom_options(Element::Field, Some(&options), &resolver, "  ");
+        assert_eq!(
+            rendered.assignments,
+            vec!["(validate.rules) = { string { min_len: 1 } }"]
+        );
+    }
+
+    #[test]
+    fn test_unresolved_option_dump() {
+        let registry = ExtensionRegistry::new();
+        let resolver = Resolver::new(vec![&registry]);
+
+        let options = [
+            varint_field(50000, 7),
+            len_field(50001, &varint_field(1, 2)),
+        ]
+        .concat();
+        let rendered = custom_options(Element::Message, Some(&options), &resolver, "  ");
+        assert!(rendered.assignments.is_empty());
+        assert_eq!(
+            rendered.unresolved,
+            vec![
+                "MessageOptions field 50000 = 7",
+                "MessageOptions field 50001 = { 1: 2 }"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scalar_encodings() {
+        let field = |ty: Type| FieldDescriptorProto {
+            r#type: Some(ty as i32),
+            ..Default::default()
+        };
+        let registry = ExtensionRegistry::new();
+        let resolver = Resolver::new(vec![&registry]);
+        let mut renderer = Renderer {
+            resolver: &resolver,
+            indent: None,
+            unresolved: Vec::new(),
+        };
+
+        assert_eq!(renderer.varint(&field(Type::Sint32), 3), "-2");
+        assert_eq!(renderer.varint(&field(Type::Int32), u64::MAX), "-1");
+        assert_eq!(fixed(Type::Float, &1.5f32.to_le_bytes()), "1.5");
+        assert_eq!(fixed(Type::Double, &f64::INFINITY.to_le_bytes()), "inf");
+        assert_eq!(escape_bytes(b"a\x00\xff"), "a\\000\\377");
+        assert_eq!(
+            renderer.render_value(&field(Type::String), WireType::Len, b"a\xff", "", 0),
+            vec!["\"a\\377\""]
+        );
+    }
+}
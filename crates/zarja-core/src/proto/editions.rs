@@ -0,0 +1,220 @@
+//! Protobuf Editions support.
+//!
+//! Editions files carry `syntax = "editions"` and an `edition` enum in the
+//! descriptor, and express what used to be syntax-level behaviour through
+//! `features` options. Neither is known to `prost_types`, so both are read
+//! from the raw descriptor bytes (see [`super::raw`]).
+
+use crate::scanner::fields;
+use std::fmt;
+
+/// Field number of `features` in each `*Options` message
+pub(crate) mod features_field {
+    /// `FileOptions.features`
+    pub(crate) const FILE: u32 = 50;
+    /// `MessageOptions.features`
+    pub(crate) const MESSAGE: u32 = 12;
+    /// `FieldOptions.features`
+    pub(crate) const FIELD: u32 = 21;
+    /// `OneofOptions.features`
+    pub(crate) const ONEOF: u32 = 1;
+    /// `EnumOptions.features`
+    pub(crate) const ENUM: u32 = 7;
+    /// `EnumValueOptions.features`
+    pub(crate) const ENUM_VALUE: u32 = 2;
+    /// `ServiceOptions.features`
+    pub(crate) const SERVICE: u32 = 34;
+    /// `MethodOptions.features`
+    pub(crate) const METHOD: u32 = 35;
+    /// `ExtensionRangeOptions.features`
+    pub(crate) const EXTENSION_RANGE: u32 = 50;
+}
+
+/// A protobuf edition
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Edition {
+    /// Edition 2023
+    #[default]
+    Edition2023,
+    /// Edition 2024
+    Edition2024,
+    /// An edition value this version does not know about
+    Other(i32),
+}
+
+impl Edition {
+    /// Converts the `google.protobuf.Edition` enum value
+    pub fn from_i32(value: i32) -> Self {
+        match value {
+            1000 => Edition::Edition2023,
+            1001 => Edition::Edition2024,
+            other => Edition::Other(other),
+        }
+    }
+}
+
+impl fmt::Display for Edition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Edition::Edition2023 => write!(f, "2023"),
+            Edition::Edition2024 => write!(f, "2024"),
+            Edition::Other(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// One feature of `google.protobuf.FeatureSet`
+struct Feature {
+    number: u32,
+    name: &'static str,
+    /// `(value, name)` pairs of the feature's enum
+    values: &'static [(i32, &'static str)],
+}
+
+/// The standard features, in `FeatureSet` field order
+const FEATURES: &[Feature] = &[
+    Feature {
+        number: 1,
+        name: "field_presence",
+        values: &[(1, "EXPLICIT"), (2, "IMPLICIT"), (3, "LEGACY_REQUIRED")],
+    },
+    Feature {
+        number: 2,
+        name: "enum_type",
+        values: &[(1, "OPEN"), (2, "CLOSED")],
+    },
+    Feature {
+        number: 3,
+        name: "repeated_field_encoding",
+        values: &[(1, "PACKED"), (2, "EXPANDED")],
+    },
+    Feature {
+        number: 4,
+        name: "utf8_validation",
+        values: &[(2, "VERIFY"), (3, "NONE")],
+    },
+    Feature {
+        number: 5,
+        name: "message_encoding",
+        values: &[(1, "LENGTH_PREFIXED"), (2, "DELIMITED")],
+    },
+    Feature {
+        number: 6,
+        name: "json_format",
+        values: &[(1, "ALLOW"), (2, "LEGACY_BEST_EFFORT")],
+    },
+    Feature {
+        number: 7,
+        name: "enforce_naming_style",
+        values: &[(1, "STYLE2024"), (2, "STYLE_LEGACY")],
+    },
+];
+
+/// `FeatureSet.field_presence = LEGACY_REQUIRED`
+pub(crate) const LEGACY_REQUIRED: (&str, &str) = ("field_presence", "LEGACY_REQUIRED");
+
+/// `FeatureSet.message_encoding = DELIMITED`
+pub(crate) const DELIMITED: (&str, &str) = ("message_encoding", "DELIMITED");
+
+/// Explicitly set standard features, in field order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct FeatureSet {
+    /// `(feature name, value name)` pairs
+    pub(crate) values: Vec<(&'static str, String)>,
+}
+
+impl FeatureSet {
+    /// Extracts the `features` field from raw `*Options` bytes
+    pub(crate) fn from_options(options: Option<&[u8]>, features_field: u32) -> Self {
+        let mut set = Self::default();
+        let Some(options) = options else {
+            return set;
+        };
+
+        // The features message may be split across several occurrences
+        let mut bytes = Vec::new();
+        for field in fields(options) {
+            if field.number == features_field {
+                if let Some(payload) = field.as_bytes() {
+                    bytes.extend_from_slice(payload);
+                }
+            }
+        }
+
+        for feature in FEATURES {
+            // Last occurrence wins for scalar fields
+            let value = fields(&bytes)
+                .filter(|f| f.number == feature.number)
+                .filter_map(|f| f.as_varint())
+                .last();
+
+            if let Some(value) = value {
+                let value = value as i32;
+                let name = feature
+                    .values
+                    .iter()
+                    .find(|(v, _)| *v == value)
+                    .map(|(_, n)| n.to_string())
+                    .unwrap_or_else(|| value.to_string());
+                set.values.push((feature.name, name));
+            }
+        }
+
+        set
+    }
+
+    /// Returns true if the given feature is set to the given value
+    pub(crate) fn contains(&self, (name, value): (&str, &str)) -> bool {
+        self.values.iter().any(|(n, v)| *n == name && v == value)
+    }
+
+    /// Adds a feature value unless that feature is already set
+    pub(crate) fn insert(&mut self, (name, value): (&'static str, &str)) {
+        if !self.values.iter().any(|(n, _)| *n == name) {
+            self.values.push((name, value.to_string()));
+        }
+    }
+
+    /// Returns `features.x = Y` option assignments
+    pub(crate) fn assignments(&self) -> Vec<String> {
+        self.values
+            .iter()
+            .map(|(name, value)| format!("features.{} = {}", name, value))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::raw::tests::{len_field, varint_field};
+
+    #[test]
+    fn test_edition_display() {
+        assert_eq!(Edition::from_i32(1000).to_string(), "2023");
+        assert_eq!(Edition::from_i32(1001).to_string(), "2024");
+        assert_eq!(Edition::from_i32(42), Edition::Other(42));
+    }
+
+    #[test]
+    fn test_feature_set_from_options() {
+        let features = [varint_field(1, 2), varint_field(5, 2), varint_field(4, 9)].concat();
+        let options = [
+            varint_field(3, 1),
+            len_field(features_field::FIELD, &features),
+        ]
+        .concat();
+
+        let set = FeatureSet::from_options(Some(&options), features_field::FIELD);
+        assert_eq!(
+            set.assignments(),
+            vec![
+                "features.field_presence = IMPLICIT",
+                "features.utf8_validation = 9",
+                "features.message_encoding = DELIMITED",
+            ]
+        );
+        assert!(set.contains(DELIMITED));
+        assert!(!set.contains(LEGACY_REQUIRED));
+    }
+}
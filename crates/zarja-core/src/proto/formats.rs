@@ -0,0 +1,334 @@
+//! Descriptor output formats.
+//!
+//! Besides `.proto` source, recovered descriptors can be written as a binary
+//! `FileDescriptorSet` (what `protoc --descriptor_set_out` writes and
+//! `--descriptor_set_in` reads), as protojson, or in the protobuf text
+//! format. JSON and text output are produced by prost-reflect; custom
+//! options that resolve against the set or the bundled files are written as
+//! extensions (`"[google.api.http]"`), the rest are left out.
+
+use super::session::pool_with_bundled;
+use super::ProtoReconstructor;
+use crate::error::{Error, Result};
+use prost::Message;
+use prost_reflect::text_format::FormatOptions;
+use prost_reflect::{DescriptorPool, DynamicMessage};
+use prost_types::FileDescriptorProto;
+use std::collections::{HashMap, HashSet};
+
+/// A file in a [`DescriptorSet`]
+#[derive(Debug, Clone)]
+struct Entry {
+    name: String,
+    dependency: Vec<String>,
+    /// The descriptor as it was serialized, with fields `prost_types` drops
+    data: Vec<u8>,
+}
+
+/// A set of recovered descriptors, written out as one `FileDescriptorSet`
+///
+/// Files are kept as they were serialized, so fields that `prost_types`
+/// doesn't know (such as editions features) survive. The set is written
+/// with every file after the files it imports, as protoc requires of
+/// `--descriptor_set_in`.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use zarja_core::{ReconstructionSession, ScanStrategy, Scanner};
+///
+/// let data = std::fs::read("./binary")?;
+/// let mut session = ReconstructionSession::new();
+/// for result in Scanner::new().scan(&data)? {
+///     session.add(result.as_bytes())?;
+/// }
+///
+/// std::fs::write("descriptors.binpb", session.descriptor_set().encode())?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DescriptorSet {
+    /// Files in the order they were added
+    files: Vec<Entry>,
+    names: HashSet<String>,
+}
+
+impl DescriptorSet {
+    /// Creates an empty set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a reconstructor's descriptor
+    ///
+    /// Returns false, and adds nothing, if the set already has a file of
+    /// the same name.
+    pub fn add(&mut self, reconstructor: &ProtoReconstructor) -> bool {
+        let proto = reconstructor.proto();
+        self.insert(proto.name(), &proto.dependency, reconstructor.data())
+    }
+
+    /// Adds a serialized `FileDescriptorProto`, skipping bytes that don't
+    /// decode
+    pub(crate) fn add_bytes(&mut self, data: &[u8]) -> bool {
+        match FileDescriptorProto::decode(data) {
+            Ok(proto) => self.insert(proto.name(), &proto.dependency, data),
+            Err(_) => false,
+        }
+    }
+
+    fn insert(&mut self, name: &str, dependency: &[String], data: &[u8]) -> bool {
+        if !self.names.insert(name.to_string()) {
+            return false;
+        }
+        self.files.push(Entry {
+            name: name.to_string(),
+            dependency: dependency.to_vec(),
+            data: data.to_vec(),
+        });
+        true
+    }
+
+    /// Returns the number of files in the set
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Returns true if the set has no files
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Returns the file names in output order
+    pub fn names(&self) -> Vec<&str> {
+        self.ordered().map(|entry| entry.name.as_str()).collect()
+    }
+
+    /// Returns the files with each one after its imports
+    ///
+    /// Files otherwise keep the order they were added in. Imports missing
+    /// from the set are ignored, and an import cycle is broken at the file
+    /// that closes it.
+    fn ordered(&self) -> impl Iterator<Item = &Entry> {
+        let index: HashMap<&str, usize> = self
+            .files
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| (entry.name.as_str(), i))
+            .collect();
+
+        let mut order = Vec::with_capacity(self.files.len());
+        let mut seen = vec![false; self.files.len()];
+        for root in 0..self.files.len() {
+            // Depth-first, emitting a file once all of its imports are out
+            let mut stack = vec![(root, 0)];
+            while let Some((i, next)) = stack.pop() {
+                if next == 0 {
+                    if seen[i] {
+                        continue;
+                    }
+                    seen[i] = true;
+                }
+                let deps = &self.files[i].dependency;
+                match deps[next.min(deps.len())..]
+                    .iter()
+                    .position(|dep| index.get(dep.as_str()).is_some_and(|&d| !seen[d]))
+                {
+                    Some(offset) => {
+                        let dep = index[deps[next + offset].as_str()];
+                        stack.push((i, next + offset + 1));
+                        stack.push((dep, 0));
+                    }
+                    None => order.push(i),
+                }
+            }
+        }
+
+        order.into_iter().map(|i| &self.files[i])
+    }
+
+    /// Encodes the set as a serialized `FileDescriptorSet` (`.binpb`)
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for entry in self.ordered() {
+            prost::encoding::bytes::encode(1, &entry.data, &mut buf);
+        }
+        buf
+    }
+
+    /// Writes the set as protojson
+    pub fn to_json(&self) -> Result<String> {
+        to_json(&self.message()?)
+    }
+
+    /// Writes the set in the protobuf text format
+    pub fn to_text_format(&self) -> Result<String> {
+        Ok(to_text_format(&self.message()?))
+    }
+
+    fn message(&self) -> Result<DynamicMessage> {
+        let pool = pool_with_bundled(
+            self.files
+                .iter()
+                .filter_map(|entry| FileDescriptorProto::decode(entry.data.as_slice()).ok()),
+        );
+        decode(&pool, "google.protobuf.FileDescriptorSet", &self.encode())
+    }
+}
+
+impl ProtoReconstructor {
+    /// Writes the descriptor as protojson
+    ///
+    /// Custom options resolve against the file's pool, for reconstructors
+    /// from a [`ReconstructionSession`](super::ReconstructionSession), or
+    /// against the file itself and the bundled files.
+    pub fn to_json(&self) -> Result<String> {
+        to_json(&self.message()?)
+    }
+
+    /// Writes the descriptor in the protobuf text format
+    ///
+    /// Custom options resolve as for [`Self::to_json`].
+    pub fn to_text_format(&self) -> Result<String> {
+        Ok(to_text_format(&self.message()?))
+    }
+
+    fn message(&self) -> Result<DynamicMessage> {
+        const NAME: &str = "google.protobuf.FileDescriptorProto";
+        let pool = match self.file_descriptor() {
+            Some(file) if file.parent_pool().get_message_by_name(NAME).is_some() => {
+                file.parent_pool().clone()
+            }
+            _ => pool_with_bundled([self.proto().clone()]),
+        };
+        decode(&pool, NAME, self.data())
+    }
+}
+
+fn decode(pool: &DescriptorPool, name: &str, data: &[u8]) -> Result<DynamicMessage> {
+    let descriptor = pool
+        .get_message_by_name(name)
+        .ok_or_else(|| Error::internal(format!("{} missing from descriptor pool", name)))?;
+    Ok(DynamicMessage::decode(descriptor, data)?)
+}
+
+fn to_json(message: &DynamicMessage) -> Result<String> {
+    serde_json::to_string_pretty(message)
+        .map_err(|e| Error::internal(format!("failed to serialize JSON: {}", e)))
+}
+
+fn to_text_format(message: &DynamicMessage) -> String {
+    message.to_text_format_with_options(&FormatOptions::new().pretty(true))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::ReconstructionSession;
+    use prost_types::FileDescriptorSet;
+
+    fn file(name: &str, deps: &[&str]) -> Vec<u8> {
+        FileDescriptorProto {
+            name: Some(name.to_string()),
+            package: Some("test".to_string()),
+            dependency: deps.iter().map(|d| d.to_string()).collect(),
+            syntax: Some("proto3".to_string()),
+            ..Default::default()
+        }
+        .encode_to_vec()
+    }
+
+    #[test]
+    fn test_topological_order() {
+        let mut session = ReconstructionSession::new();
+        session
+            .add(&file("a.proto", &["b.proto", "c.proto"]))
+            .unwrap();
+        session
+            .add(&file("b.proto", &["c.proto", "missing.proto"]))
+            .unwrap();
+        session.add(&file("c.proto", &[])).unwrap();
+        session.add(&file("d.proto", &["e.proto"])).unwrap();
+        session.add(&file("e.proto", &["d.proto"])).unwrap();
+        // Later copies of a name are dropped
+        session.add(&file("c.proto", &["a.proto"])).unwrap();
+
+        let set = session.descriptor_set();
+        let expected = ["c.proto", "b.proto", "a.proto", "e.proto", "d.proto"];
+        assert_eq!(set.names(), expected);
+
+        let decoded = FileDescriptorSet::decode(set.encode().as_slice()).unwrap();
+        let names: Vec<&str> = decoded.file.iter().map(|f| f.name()).collect();
+        assert_eq!(names, expected);
+        assert!(decoded.file[0].dependency.is_empty());
+    }
+
+    #[test]
+    fn test_json_and_text_format() {
+        let data = crate::proto::bundled::file("google/api/annotations.proto").unwrap();
+        let reconstructor = ProtoReconstructor::from_bytes(data).unwrap();
+
+        let json = reconstructor.to_json().unwrap();
+        assert!(json.contains(r#""name": "google/api/annotations.proto""#));
+        assert!(json.contains(r#""extendee": ".google.protobuf.MethodOptions""#));
+
+        let text = reconstructor.to_text_format().unwrap();
+        assert!(text.contains(r#"name: "google/api/annotations.proto""#));
+        assert!(text.contains("TYPE_MESSAGE"));
+
+        let mut set = DescriptorSet::new();
+        assert!(set.add(&reconstructor));
+        assert!(!set.add(&reconstructor));
+        let json = set.to_json().unwrap();
+        assert!(json.starts_with("{\n  \"file\": ["));
+        let text = set.to_text_format().unwrap();
+        assert!(text.starts_with("file: [{"));
+    }
+
+    #[test]
+    fn test_custom_options_as_extensions() {
+        use prost::encoding::bytes::encode;
+        use prost_types::{MethodDescriptorProto, ServiceDescriptorProto};
+
+        // rpc Check(Empty) returns (Empty) { option (google.api.http) = { get: "/v1" }; }
+        // prost_types drops extensions, so the options are appended as raw bytes
+        let mut options = Vec::new();
+        encode(72295728, &[&[0x12, 3][..], b"/v1"].concat(), &mut options);
+        let mut method = MethodDescriptorProto {
+            name: Some("Check".to_string()),
+            input_type: Some(".google.protobuf.Empty".to_string()),
+            output_type: Some(".google.protobuf.Empty".to_string()),
+            ..Default::default()
+        }
+        .encode_to_vec();
+        encode(4, &options, &mut method);
+        let mut service = ServiceDescriptorProto {
+            name: Some("Health".to_string()),
+            ..Default::default()
+        }
+        .encode_to_vec();
+        encode(2, &method, &mut service);
+        let mut data = file(
+            "health.proto",
+            &[
+                "google/api/annotations.proto",
+                "google/protobuf/empty.proto",
+            ],
+        );
+        encode(6, &service, &mut data);
+
+        let mut session = ReconstructionSession::new();
+        session.add(&data).unwrap();
+        let reconstructor = &session.reconstructors()[0];
+        let json = reconstructor.to_json().unwrap();
+        assert!(json.contains(r#""[google.api.http]": {"#), "{}", json);
+        assert!(json.contains(r#""get": "/v1""#));
+        let text = reconstructor.to_text_format().unwrap();
+        assert!(text.contains("[google.api.http]"), "{}", text);
+        assert!(session
+            .descriptor_set()
+            .to_json()
+            .unwrap()
+            .contains("[google.api.http]"));
+    }
+}
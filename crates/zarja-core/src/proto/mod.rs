@@ -9,23 +9,57 @@
 //!
 //! 1. Parses raw bytes into a `FileDescriptorProto`
 //! 2. Builds a resolved `FileDescriptor` using prost-reflect
-//! 3. Writes out the `.proto` source using the [`ProtoWriter`] trait
+//! 3. Walks the file's elements in source order, driving a [`ProtoWriter`];
+//!    the default writer produces `.proto` source
 //!
 //! ## Extensibility
 //!
 //! The [`ProtoWriter`] trait allows customization of how proto elements are written.
-//! This can be used for alternative output formats (JSON, documentation, etc.).
-
+//! [`ProtoReconstructor::visit`] drives any implementation through the same
+//! traversal, for alternative output formats (JSON, documentation, etc.).
+//!
+//! With the `extended-formats` feature, descriptors can also be written as a
+//! binary `FileDescriptorSet` ([`DescriptorSet`]), as protojson or in the
+//! protobuf text format.
+
+mod bundled;
+mod custom;
+mod editions;
+#[cfg(feature = "extended-formats")]
+mod formats;
+mod names;
+mod options;
+mod raw;
+mod salvage;
+mod session;
+mod source;
+mod stubs;
+mod verify;
+mod visit;
 mod writer;
 
 use crate::error::{Error, Result};
 use crate::MAX_FIELD_NUMBER;
+use custom::{CustomOptions, Resolver};
+use editions::{features_field, FeatureSet, DELIMITED, LEGACY_REQUIRED};
+use names::SymbolTable;
 use prost::Message;
 use prost_reflect::{DescriptorPool, FileDescriptor};
 use prost_types::FileDescriptorProto;
+use raw::{Element, RawDescriptor};
+use source::{Block, Comments, SourceInfo};
 use std::fmt::Write as FmtWrite;
-
-pub use writer::{NullWriter, ProtoWriter, StatsWriter};
+use std::sync::Arc;
+use visit::{is_proto3_optional, Walker};
+
+pub use custom::ExtensionRegistry;
+pub use editions::Edition;
+#[cfg(feature = "extended-formats")]
+pub use formats::DescriptorSet;
+pub use salvage::SalvageReport;
+pub use session::ReconstructionSession;
+pub use verify::{Difference, Verifier, VerifyReport};
+pub use writer::{NullWriter, ProtoWriter, StatsWriter, VisitContext};
 
 /// Configuration for proto reconstruction
 #[derive(Debug, Clone)]
@@ -34,8 +68,15 @@ pub struct ReconstructorConfig {
     pub indent_str: String,
     /// Include source code comments if available
     pub include_comments: bool,
-    /// Sort fields by number
+    /// Sort fields by number instead of keeping declaration order
     pub sort_fields: bool,
+    /// Write type references in the shortest form that resolves to the
+    /// same type, instead of fully qualified
+    pub shorten_type_names: bool,
+    /// Only group consecutive extensions of one extendee into an `extend`
+    /// block, so that recompiling the output keeps the extensions in their
+    /// original order
+    pub keep_extension_order: bool,
 }
 
 impl Default for ReconstructorConfig {
@@ -44,6 +85,8 @@ impl Default for ReconstructorConfig {
             indent_str: "  ".to_string(),
             include_comments: true,
             sort_fields: false,
+            shorten_type_names: true,
+            keep_extension_order: false,
         }
     }
 }
@@ -71,6 +114,18 @@ impl ReconstructorConfig {
         self.sort_fields = sort;
         self
     }
+
+    /// Sets whether to shorten type references
+    pub fn shorten_type_names(mut self, shorten: bool) -> Self {
+        self.shorten_type_names = shorten;
+        self
+    }
+
+    /// Sets whether `extend` blocks keep the extensions' order
+    pub fn keep_extension_order(mut self, keep: bool) -> Self {
+        self.keep_extension_order = keep;
+        self
+    }
 }
 
 /// Proto syntax version
@@ -80,6 +135,8 @@ pub enum ProtoSyntax {
     Proto2,
     /// Proto3 syntax
     Proto3,
+    /// Protobuf Editions (`syntax = "editions"` plus an `edition` field)
+    Editions,
 }
 
 impl ProtoSyntax {
@@ -88,6 +145,7 @@ impl ProtoSyntax {
         match self {
             ProtoSyntax::Proto2 => "proto2",
             ProtoSyntax::Proto3 => "proto3",
+            ProtoSyntax::Editions => "editions",
         }
     }
 }
@@ -99,6 +157,7 @@ impl TryFrom<&str> for ProtoSyntax {
         match value {
             "" | "proto2" => Ok(ProtoSyntax::Proto2),
             "proto3" => Ok(ProtoSyntax::Proto3),
+            "editions" => Ok(ProtoSyntax::Editions),
             _ => Err(Error::UnsupportedSyntax {
                 syntax: value.to_string(),
             }),
@@ -111,8 +170,22 @@ impl TryFrom<&str> for ProtoSyntax {
 pub struct ProtoReconstructor {
     /// The raw FileDescriptorProto
     proto: FileDescriptorProto,
+    /// The descriptor as it was serialized
+    data: Vec<u8>,
     /// The resolved file descriptor
     descriptor: Option<FileDescriptor>,
+    /// Why the file descriptor didn't resolve
+    build_error: Option<Error>,
+    /// What was lost, for a descriptor recovered by [`Self::salvage`]
+    salvage: Option<SalvageReport>,
+    /// Data from the original bytes that `prost_types` does not retain
+    raw: RawDescriptor,
+    /// Extensions defined by this file, for its own custom options
+    local_extensions: ExtensionRegistry,
+    /// Extensions defined by the other files of a session
+    session_extensions: Option<Arc<ExtensionRegistry>>,
+    /// Extensions defined elsewhere
+    extensions: Arc<ExtensionRegistry>,
     /// Configuration
     config: ReconstructorConfig,
 }
@@ -121,19 +194,68 @@ impl ProtoReconstructor {
     /// Creates a new reconstructor from raw bytes
     pub fn from_bytes(data: &[u8]) -> Result<Self> {
         let proto = FileDescriptorProto::decode(data)?;
-        Self::from_proto(proto)
+        Ok(Self::from_parts(proto, data.to_vec(), None))
     }
 
     /// Creates a new reconstructor from a FileDescriptorProto
+    ///
+    /// Fields unknown to `prost_types` (such as the edition and `features`
+    /// options) are already gone from a decoded proto; use [`Self::from_bytes`]
+    /// to keep them.
     pub fn from_proto(proto: FileDescriptorProto) -> Result<Self> {
-        // Try to build a resolved descriptor
-        let descriptor = Self::build_descriptor(&proto).ok();
+        let data = proto.encode_to_vec();
+        Ok(Self::from_parts(proto, data, None))
+    }
+
+    /// Creates a reconstructor from whatever decodes in a corrupted or
+    /// truncated descriptor
+    ///
+    /// Each top-level field (the name, package, every message, enum,
+    /// service and extension, ...) is decoded on its own. Invalid UTF-8 is
+    /// replaced, malformed bytes at the end of a sub-message are cut off,
+    /// and a last field cut short keeps the part that decodes; fields that
+    /// still don't decode are dropped. Unless the
+    /// descriptor decodes as it is, the reconstruction opens with a comment
+    /// listing what was dropped or repaired (see [`Self::salvage_report`]).
+    ///
+    /// Fails if nothing but the file name can be recovered.
+    pub fn salvage(data: &[u8]) -> Result<Self> {
+        let (data, report) = salvage::salvage(data)?;
+        let report = Some(report).filter(|report| !report.is_empty());
+        Ok(Self::from_bytes(&data)?.with_salvage(report))
+    }
+
+    /// Creates a reconstructor, resolving the file on its own unless
+    /// `descriptor` was already resolved elsewhere, e.g. in a shared pool
+    fn from_parts(
+        proto: FileDescriptorProto,
+        data: Vec<u8>,
+        descriptor: Option<FileDescriptor>,
+    ) -> Self {
+        let (descriptor, build_error) = match descriptor {
+            Some(descriptor) => (Some(descriptor), None),
+            // Try to build a resolved descriptor
+            None => match Self::build_descriptor(&proto) {
+                Ok(descriptor) => (Some(descriptor), None),
+                Err(e) => (None, Some(e)),
+            },
+        };
+
+        let mut local_extensions = ExtensionRegistry::new();
+        local_extensions.add_file(&proto);
 
-        Ok(Self {
+        Self {
+            raw: RawDescriptor::parse(&data),
             proto,
+            data,
             descriptor,
+            build_error,
+            salvage: None,
+            local_extensions,
+            session_extensions: None,
+            extensions: ExtensionRegistry::shared_bundled(),
             config: ReconstructorConfig::default(),
-        })
+        }
     }
 
     /// Creates a new reconstructor with custom config
@@ -142,8 +264,37 @@ impl ProtoReconstructor {
         self
     }
 
+    /// Marks the reconstructor as partial
+    pub(crate) fn with_salvage(mut self, report: Option<SalvageReport>) -> Self {
+        self.salvage = report;
+        self
+    }
+
+    /// Consults the extensions of a session before [`Self::with_extensions`]
+    pub(crate) fn with_session_extensions(mut self, extensions: Arc<ExtensionRegistry>) -> Self {
+        self.session_extensions = Some(extensions);
+        self
+    }
+
+    /// Sets the registry used to resolve custom options
+    ///
+    /// Defaults to [`ExtensionRegistry::bundled`]. Extensions defined in the
+    /// file itself are always consulted first.
+    pub fn with_extensions(mut self, extensions: Arc<ExtensionRegistry>) -> Self {
+        self.extensions = extensions;
+        self
+    }
+
     /// Try to build a resolved FileDescriptor
     fn build_descriptor(proto: &FileDescriptorProto) -> Result<FileDescriptor> {
+        // prost-reflect only understands proto2/proto3 and panics while
+        // reporting the error for anything else
+        if !matches!(proto.syntax(), "" | "proto2" | "proto3") {
+            return Err(Error::UnsupportedSyntax {
+                syntax: proto.syntax().to_string(),
+            });
+        }
+
         // Create a FileDescriptorSet with just our file
         let fds = prost_types::FileDescriptorSet {
             file: vec![proto.clone()],
@@ -193,6 +344,19 @@ impl ProtoReconstructor {
         ProtoSyntax::try_from(self.proto.syntax()).unwrap_or(ProtoSyntax::Proto2)
     }
 
+    /// Returns the edition of an editions file
+    ///
+    /// Files that declare `syntax = "editions"` without an `edition` field
+    /// are treated as edition 2023.
+    pub fn edition(&self) -> Option<Edition> {
+        match self.syntax() {
+            ProtoSyntax::Editions => {
+                Some(self.raw.edition.map(Edition::from_i32).unwrap_or_default())
+            }
+            _ => None,
+        }
+    }
+
     /// Returns the resolved file descriptor if available
     ///
     /// The descriptor may not be available if the proto has unresolvable dependencies.
@@ -200,11 +364,48 @@ impl ProtoReconstructor {
         self.descriptor.as_ref()
     }
 
+    /// Returns why the file descriptor couldn't be resolved
+    ///
+    /// `None` when [`Self::file_descriptor`] is available.
+    pub fn build_error(&self) -> Option<&Error> {
+        self.build_error.as_ref()
+    }
+
+    /// Returns what was dropped or repaired, for a partial descriptor from
+    /// [`Self::salvage`]
+    pub fn salvage_report(&self) -> Option<&SalvageReport> {
+        self.salvage.as_ref()
+    }
+
     /// Returns the raw FileDescriptorProto
     pub fn proto(&self) -> &FileDescriptorProto {
         &self.proto
     }
 
+    /// Returns the serialized descriptor
+    pub(crate) fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns true if the descriptor is byte for byte the bundled file of
+    /// the same name
+    ///
+    /// Such files are copies of a well-known or common library (the
+    /// well-known types, `google/api`, `google/rpc`, gRPC health and
+    /// reflection, protoc-gen-validate) rather than part of the application.
+    pub fn is_bundled(&self) -> bool {
+        bundled::file(self.filename()) == Some(self.data.as_slice())
+    }
+
+    /// Checks that the reconstructed source compiles back to the original
+    /// descriptor
+    ///
+    /// Imports are resolved against the bundled definitions only; use
+    /// [`ReconstructionSession::verifier`] to resolve them across files.
+    pub fn verify(&self) -> Result<VerifyReport> {
+        Verifier::new().verify(self, &self.reconstruct())
+    }
+
     /// Reconstruct the proto definition as a string
     pub fn reconstruct(&self) -> String {
         let mut output = String::new();
@@ -214,137 +415,237 @@ impl ProtoReconstructor {
 
     /// Write the reconstructed proto to a writer
     pub fn write_to(&self, w: &mut impl FmtWrite) -> std::fmt::Result {
-        let mut writer = DefaultProtoWriter::new(w, &self.config);
-        writer.write_file(&self.proto, self.syntax())
+        if let Some(report) = &self.salvage {
+            writeln!(w, "{}", report)?;
+        }
+        let resolver = Resolver::new(
+            std::iter::once(&self.local_extensions)
+                .chain(self.session_extensions.as_deref())
+                .chain(std::iter::once(&*self.extensions))
+                .collect(),
+        );
+        let mut writer = DefaultProtoWriter::new(
+            w,
+            self,
+            resolver,
+            self.descriptor.as_ref(),
+            SymbolTable::new(&self.proto, self.descriptor.as_ref()),
+            SourceInfo::new(&self.proto),
+        );
+        self.visit(&mut writer)
+    }
+
+    /// Drives a [`ProtoWriter`] through the file's elements
+    ///
+    /// Elements are visited in the order [`Self::reconstruct`] writes them;
+    /// `reconstruct` is itself a `ProtoWriter` producing `.proto` source.
+    pub fn visit<P: ProtoWriter + ?Sized>(&self, writer: &mut P) -> std::fmt::Result {
+        let source = SourceInfo::new(&self.proto);
+        Walker::new(&self.config, source, self.syntax()).walk_file(writer, &self.proto)
     }
 }
 
 /// Default implementation of ProtoWriter
 struct DefaultProtoWriter<'a, W: FmtWrite> {
     writer: &'a mut W,
-    config: &'a ReconstructorConfig,
+    /// The file being written
+    file: &'a ProtoReconstructor,
+    resolver: Resolver<'a>,
+    /// Resolved descriptor of the file, if available
+    descriptor: Option<&'a FileDescriptor>,
+    /// Symbols that type references may resolve to
+    symbols: SymbolTable,
+    /// Declaration positions, if the descriptor kept them
+    source: SourceInfo,
+    /// Fully-qualified name of the scope being written
+    scope: String,
     indent_level: usize,
+    /// `SourceCodeInfo`-style path of the element being written
+    path: Vec<i32>,
+    /// Start of the source block of the last `extend` block or extension
+    /// written
+    extend_block: Option<(i32, i32)>,
 }
 
 impl<'a, W: FmtWrite> DefaultProtoWriter<'a, W> {
-    fn new(writer: &'a mut W, config: &'a ReconstructorConfig) -> Self {
+    fn new(
+        writer: &'a mut W,
+        file: &'a ProtoReconstructor,
+        resolver: Resolver<'a>,
+        descriptor: Option<&'a FileDescriptor>,
+        symbols: SymbolTable,
+        source: SourceInfo,
+    ) -> Self {
         Self {
             writer,
-            config,
+            file,
+            resolver,
+            descriptor,
+            symbols,
+            source,
+            scope: String::new(),
             indent_level: 0,
+            path: Vec::new(),
+            extend_block: None,
         }
     }
 
-    fn indent(&mut self) {
-        self.indent_level += 1;
-    }
-
-    fn dedent(&mut self) {
-        self.indent_level = self.indent_level.saturating_sub(1);
+    /// Moves to the element a hook was called for
+    fn at(&mut self, cx: &VisitContext<'_>) {
+        self.path.clear();
+        self.path.extend_from_slice(cx.path());
+        self.scope.clear();
+        self.scope.push_str(cx.scope());
     }
 
-    fn write_indent(&mut self) -> std::fmt::Result {
-        for _ in 0..self.indent_level {
-            write!(self.writer, "{}", self.config.indent_str)?;
+    /// Formats a type reference for the current scope
+    fn type_ref(&self, type_name: &str) -> String {
+        if self.file.config.shorten_type_names {
+            self.symbols.shortest(type_name, &self.scope)
+        } else {
+            type_name.to_string()
         }
-        Ok(())
     }
 
-    fn writeln(&mut self, s: &str) -> std::fmt::Result {
-        self.write_indent()?;
-        writeln!(self.writer, "{}", s)
+    /// Returns the explicitly set features of the current element
+    fn features(&self, features_field: u32) -> FeatureSet {
+        FeatureSet::from_options(self.file.raw.options(&self.path), features_field)
     }
 
-    fn write_file(
-        &mut self,
-        proto: &FileDescriptorProto,
-        syntax: ProtoSyntax,
-    ) -> std::fmt::Result {
-        // Syntax declaration
-        writeln!(self.writer, "syntax = \"{}\";", syntax.as_str())?;
-        writeln!(self.writer)?;
+    /// Returns the standard options set on the current element
+    fn standard_options(&self, element: Element) -> Vec<String> {
+        options::standard_options(element, self.file.raw.options(&self.path))
+    }
 
-        // Package
-        if !proto.package().is_empty() {
-            writeln!(self.writer, "package {};", proto.package())?;
-            writeln!(self.writer)?;
-        }
+    /// Returns the custom options set on the current element
+    fn custom_options(&self, element: Element) -> CustomOptions {
+        custom::custom_options(
+            element,
+            self.file.raw.options(&self.path),
+            &self.resolver,
+            &self.file.config.indent_str,
+        )
+    }
 
-        // File options
-        self.write_file_options(proto)?;
+    /// Returns the standard options, features and custom options of the
+    /// current element
+    fn element_options(&self, element: Element, features_field: u32) -> Vec<String> {
+        let mut options = self.standard_options(element);
+        options.extend(self.features(features_field).assignments());
+        options.extend(self.custom_options(element).assignments);
+        options
+    }
 
-        // Imports
-        self.write_imports(proto)?;
+    /// Writes `option x = y;` lines for the current element
+    fn write_element_options(&mut self, element: Element, features_field: u32) -> std::fmt::Result {
+        for assignment in self.element_options(element, features_field) {
+            self.writeln(&format!("option {};", assignment))?;
+        }
+        self.write_unresolved_options(element)
+    }
 
-        // Services
-        for service in &proto.service {
-            self.write_service(service)?;
+    /// Writes comments describing option data that could not be decoded
+    fn write_unresolved_options(&mut self, element: Element) -> std::fmt::Result {
+        for unresolved in self.custom_options(element).unresolved {
+            self.writeln(&format!("// unresolved option: {}", unresolved))?;
         }
+        Ok(())
+    }
 
-        // Messages
-        for message in &proto.message_type {
-            self.write_message(message, syntax)?;
+    /// Returns the source comments of the current element, if enabled
+    fn comments(&self) -> Option<Comments> {
+        if !self.file.config.include_comments {
+            return None;
         }
+        self.source.comments(&self.path).cloned()
+    }
+
+    /// Writes the detached and leading comments of the current element
+    fn write_leading_comments(&mut self) -> std::fmt::Result {
+        let comments = self.comments();
+        self.write_leading(comments.as_ref())
+    }
 
-        // Enums
-        for enum_type in &proto.enum_type {
-            self.write_enum(enum_type)?;
+    /// Writes detached and leading comments
+    fn write_leading(&mut self, comments: Option<&Comments>) -> std::fmt::Result {
+        let Some(comments) = comments else {
+            return Ok(());
+        };
+        for detached in &comments.detached {
+            self.write_comment(detached)?;
+            writeln!(self.writer)?;
+        }
+        if let Some(leading) = &comments.leading {
+            self.write_comment(leading)?;
         }
+        Ok(())
+    }
+
+    /// Ends the current line with the current element's trailing comment
+    ///
+    /// protoc only attaches a single same-line comment as trailing, so
+    /// longer ones go on the following lines, closed by a blank line.
+    fn end_line(&mut self) -> std::fmt::Result {
+        let trailing = self.comments().and_then(|c| c.trailing);
+        self.end_line_with(trailing.as_deref())
+    }
 
-        // Extensions (top-level)
-        for extension in &proto.extension {
-            self.write_extension(extension, syntax)?;
+    /// Ends the current line with a trailing comment
+    fn end_line_with(&mut self, trailing: Option<&str>) -> std::fmt::Result {
+        let Some(trailing) = trailing else {
+            return writeln!(self.writer);
+        };
+        match Comments::lines(trailing)[..] {
+            [line] => writeln!(self.writer, " //{}", line),
+            _ => {
+                writeln!(self.writer)?;
+                self.write_comment(trailing)?;
+                writeln!(self.writer)
+            }
         }
+    }
 
+    /// Writes comment text as `//` lines
+    fn write_comment(&mut self, text: &str) -> std::fmt::Result {
+        for line in Comments::lines(text) {
+            self.writeln(&format!("//{}", line))?;
+        }
         Ok(())
     }
 
-    fn write_file_options(&mut self, proto: &FileDescriptorProto) -> std::fmt::Result {
-        let Some(opts) = &proto.options else {
-            return Ok(());
-        };
+    fn indent(&mut self) {
+        self.indent_level += 1;
+    }
 
-        let mut wrote_option = false;
+    fn dedent(&mut self) {
+        self.indent_level = self.indent_level.saturating_sub(1);
+    }
 
-        // Write known options
-        macro_rules! write_string_option {
-            ($name:expr, $value:expr) => {
-                if let Some(v) = $value {
-                    if !v.is_empty() {
-                        writeln!(self.writer, "option {} = \"{}\";", $name, escape_string(v))?;
-                        wrote_option = true;
-                    }
-                }
-            };
+    fn write_indent(&mut self) -> std::fmt::Result {
+        for _ in 0..self.indent_level {
+            write!(self.writer, "{}", self.file.config.indent_str)?;
         }
+        Ok(())
+    }
 
-        macro_rules! write_bool_option {
-            ($name:expr, $value:expr) => {
-                if let Some(v) = $value {
-                    writeln!(self.writer, "option {} = {};", $name, v)?;
-                    wrote_option = true;
-                }
-            };
+    fn writeln(&mut self, s: &str) -> std::fmt::Result {
+        for line in s.lines() {
+            self.write_indent()?;
+            writeln!(self.writer, "{}", line)?;
         }
+        Ok(())
+    }
 
-        write_string_option!("java_package", opts.java_package.as_ref());
-        write_string_option!("java_outer_classname", opts.java_outer_classname.as_ref());
-        write_bool_option!("java_multiple_files", opts.java_multiple_files);
-        write_bool_option!("java_string_check_utf8", opts.java_string_check_utf8);
-        write_string_option!("go_package", opts.go_package.as_ref());
-        write_bool_option!("cc_enable_arenas", opts.cc_enable_arenas);
-        write_string_option!("objc_class_prefix", opts.objc_class_prefix.as_ref());
-        write_string_option!("csharp_namespace", opts.csharp_namespace.as_ref());
-        write_string_option!("swift_prefix", opts.swift_prefix.as_ref());
-        write_string_option!("php_class_prefix", opts.php_class_prefix.as_ref());
-        write_string_option!("php_namespace", opts.php_namespace.as_ref());
-        write_string_option!("php_metadata_namespace", opts.php_metadata_namespace.as_ref());
-        write_string_option!("ruby_package", opts.ruby_package.as_ref());
-
-        if wrote_option {
-            writeln!(self.writer)?;
+    fn write_file_options(&mut self) -> std::fmt::Result {
+        let options = self.element_options(Element::File, features_field::FILE);
+        let unresolved = self.custom_options(Element::File).unresolved;
+        if options.is_empty() && unresolved.is_empty() {
+            return Ok(());
         }
 
+        self.write_element_options(Element::File, features_field::FILE)?;
+        writeln!(self.writer)?;
+
         Ok(())
     }
 
@@ -367,448 +668,650 @@ impl<'a, W: FmtWrite> DefaultProtoWriter<'a, W> {
             } else {
                 ""
             };
-            writeln!(self.writer, "import {}\"{}\";", modifier, dep)?;
+            // `dependency` is field 3 of the file
+            self.path.extend([3, i as i32]);
+            self.write_leading_comments()?;
+            write!(self.writer, "import {}\"{}\";", modifier, dep)?;
+            self.end_line()?;
+            self.path.truncate(self.path.len() - 2);
         }
 
         writeln!(self.writer)?;
         Ok(())
     }
 
-    fn write_service(&mut self, service: &prost_types::ServiceDescriptorProto) -> std::fmt::Result {
-        writeln!(self.writer, "service {} {{", service.name())?;
-        self.indent();
-
-        for method in &service.method {
-            self.write_method(method)?;
+    fn is_map_field(
+        &self,
+        field: &prost_types::FieldDescriptorProto,
+        message: &prost_types::DescriptorProto,
+    ) -> bool {
+        if field.label() != prost_types::field_descriptor_proto::Label::Repeated {
+            return false;
+        }
+        if field.r#type() != prost_types::field_descriptor_proto::Type::Message {
+            return false;
         }
 
-        self.dedent();
-        writeln!(self.writer, "}}")?;
-        writeln!(self.writer)?;
-        Ok(())
+        self.map_entry(field, message).is_some()
     }
 
-    fn write_method(&mut self, method: &prost_types::MethodDescriptorProto) -> std::fmt::Result {
-        let client_streaming = method.client_streaming.unwrap_or(false);
-        let server_streaming = method.server_streaming.unwrap_or(false);
-
-        let input = if client_streaming {
-            format!("stream {}", method.input_type())
-        } else {
-            method.input_type().to_string()
-        };
+    /// Returns the map entry type of a map field
+    ///
+    /// Uses the resolved descriptor when there is one, and otherwise matches
+    /// the field's type name against the message's nested types.
+    fn map_entry(
+        &self,
+        field: &prost_types::FieldDescriptorProto,
+        message: &prost_types::DescriptorProto,
+    ) -> Option<prost_types::DescriptorProto> {
+        let type_name = field.type_name();
 
-        let output = if server_streaming {
-            format!("stream {}", method.output_type())
-        } else {
-            method.output_type().to_string()
-        };
+        if let Some(descriptor) = self.descriptor {
+            let pool = descriptor.parent_pool();
+            if let Some(entry) = pool.get_message_by_name(type_name.trim_start_matches('.')) {
+                return entry
+                    .is_map_entry()
+                    .then(|| entry.descriptor_proto().clone());
+            }
+        }
 
-        self.write_indent()?;
-        writeln!(
-            self.writer,
-            "rpc {}({}) returns ({});",
-            method.name(),
-            input,
-            output
-        )?;
+        for nested in &message.nested_type {
+            let expected_name = format!(".{}", nested.name());
+            if type_name.ends_with(&expected_name) || type_name == nested.name() {
+                return nested
+                    .options
+                    .as_ref()
+                    .is_some_and(|o| o.map_entry.unwrap_or(false))
+                    .then(|| nested.clone());
+            }
+        }
 
-        Ok(())
+        None
     }
 
-    fn write_message(
+    fn write_map_field(
         &mut self,
+        field: &prost_types::FieldDescriptorProto,
         message: &prost_types::DescriptorProto,
-        syntax: ProtoSyntax,
     ) -> std::fmt::Result {
-        writeln!(self.writer, "message {} {{", message.name())?;
-        self.indent();
-
-        // Reserved ranges and names
-        self.write_reserved(message)?;
-
-        // Nested messages
-        for nested in &message.nested_type {
-            // Skip map entry types (they're synthetic)
-            if nested.options.as_ref().map_or(false, |o| o.map_entry.unwrap_or(false)) {
-                continue;
+        if let Some(entry) = self.map_entry(field, message) {
+            let key_field = entry.field.iter().find(|f| f.number() == 1);
+            let value_field = entry.field.iter().find(|f| f.number() == 2);
+
+            if let (Some(key), Some(value)) = (key_field, value_field) {
+                write!(
+                    self.writer,
+                    "map<{}, {}> {} = {}",
+                    self.field_type_name(key),
+                    self.field_type_name(value),
+                    field.name(),
+                    field.number()
+                )?;
+                return Ok(());
             }
-            self.write_message(nested, syntax)?;
         }
 
-        // Nested enums
-        for enum_type in &message.enum_type {
-            self.write_enum(enum_type)?;
-        }
+        // Fallback: just write as a regular field
+        write!(
+            self.writer,
+            "{} {} = {}",
+            self.field_type_name(field),
+            field.name(),
+            field.number()
+        )?;
 
-        // Collect oneof field indices
-        let mut oneof_fields: std::collections::HashMap<i32, Vec<&prost_types::FieldDescriptorProto>> =
-            std::collections::HashMap::new();
+        Ok(())
+    }
+
+    fn field_label(
+        &self,
+        field: &prost_types::FieldDescriptorProto,
+        syntax: ProtoSyntax,
+        message: &prost_types::DescriptorProto,
+    ) -> &'static str {
+        use prost_types::field_descriptor_proto::Label;
 
-        for field in &message.field {
-            if let Some(oneof_index) = field.oneof_index {
-                // Check if this is a proto3 optional (has synthetic oneof)
-                if !Self::is_proto3_optional(field, message) {
-                    oneof_fields
-                        .entry(oneof_index)
-                        .or_default()
-                        .push(field);
+        match field.label() {
+            Label::Repeated => {
+                // Check if this is a map (maps don't get "repeated" label)
+                if self.is_map_field(field, message) {
+                    ""
+                } else {
+                    "repeated"
                 }
             }
-        }
-
-        // Write oneofs
-        for (i, oneof) in message.oneof_decl.iter().enumerate() {
-            if let Some(fields) = oneof_fields.get(&(i as i32)) {
-                if !fields.is_empty() {
-                    self.write_oneof(oneof, fields, syntax)?;
+            // Editions expresses presence through features instead
+            Label::Required if syntax == ProtoSyntax::Editions => "",
+            Label::Required => "required",
+            Label::Optional => {
+                match syntax {
+                    ProtoSyntax::Proto2 => "optional",
+                    ProtoSyntax::Editions => "",
+                    ProtoSyntax::Proto3 => {
+                        // In proto3, check if this is an explicit optional (has synthetic oneof)
+                        if is_proto3_optional(field, message) {
+                            "optional"
+                        } else {
+                            ""
+                        }
+                    }
                 }
             }
         }
+    }
 
-        // Write regular fields (excluding those in oneofs)
-        for field in &message.field {
-            let in_real_oneof = field.oneof_index.is_some()
-                && !Self::is_proto3_optional(field, message)
-                && oneof_fields.contains_key(&field.oneof_index.unwrap());
-
-            if !in_real_oneof {
-                self.write_field(field, syntax, message)?;
-            }
+    /// Type name for a field declaration; editions has no `group` keyword,
+    /// so delimited fields name their message type instead
+    fn value_type_name(
+        &self,
+        field: &prost_types::FieldDescriptorProto,
+        syntax: ProtoSyntax,
+    ) -> String {
+        if syntax == ProtoSyntax::Editions
+            && field.r#type() == prost_types::field_descriptor_proto::Type::Group
+        {
+            self.type_ref(field.type_name())
+        } else {
+            self.field_type_name(field)
         }
+    }
 
-        // Extensions
-        for extension in &message.extension {
-            self.write_extension(extension, syntax)?;
-        }
+    fn field_type_name(&self, field: &prost_types::FieldDescriptorProto) -> String {
+        use prost_types::field_descriptor_proto::Type;
 
-        // Extension ranges
-        for range in &message.extension_range {
-            self.write_indent()?;
-            let end = if range.end() == MAX_FIELD_NUMBER as i32 + 1 {
-                "max".to_string()
-            } else {
-                (range.end() - 1).to_string()
-            };
-            writeln!(self.writer, "extensions {} to {};", range.start(), end)?;
+        match field.r#type() {
+            Type::Double => "double".to_string(),
+            Type::Float => "float".to_string(),
+            Type::Int64 => "int64".to_string(),
+            Type::Uint64 => "uint64".to_string(),
+            Type::Int32 => "int32".to_string(),
+            Type::Fixed64 => "fixed64".to_string(),
+            Type::Fixed32 => "fixed32".to_string(),
+            Type::Bool => "bool".to_string(),
+            Type::String => "string".to_string(),
+            Type::Bytes => "bytes".to_string(),
+            Type::Uint32 => "uint32".to_string(),
+            Type::Sfixed32 => "sfixed32".to_string(),
+            Type::Sfixed64 => "sfixed64".to_string(),
+            Type::Sint32 => "sint32".to_string(),
+            Type::Sint64 => "sint64".to_string(),
+            Type::Group => "group".to_string(),
+            Type::Message | Type::Enum => self.type_ref(field.type_name()),
         }
-
-        self.dedent();
-        self.writeln("}")?;
-        writeln!(self.writer)?;
-
-        Ok(())
     }
 
-    fn is_proto3_optional(
+    fn write_field_options(
+        &mut self,
         field: &prost_types::FieldDescriptorProto,
-        message: &prost_types::DescriptorProto,
-    ) -> bool {
-        // In proto3, optional fields have a synthetic oneof
-        if let Some(oneof_index) = field.oneof_index {
-            if let Some(oneof) = message.oneof_decl.get(oneof_index as usize) {
-                // Synthetic oneofs have names starting with "_"
-                return oneof.name().starts_with('_');
-            }
-        }
-        false
-    }
+        syntax: ProtoSyntax,
+    ) -> std::fmt::Result {
+        let mut options = Vec::new();
 
-    fn write_reserved(&mut self, message: &prost_types::DescriptorProto) -> std::fmt::Result {
-        // Reserved names
-        if !message.reserved_name.is_empty() {
-            self.write_indent()?;
-            write!(self.writer, "reserved ")?;
-            for (i, name) in message.reserved_name.iter().enumerate() {
-                if i > 0 {
-                    write!(self.writer, ", ")?;
-                }
-                write!(self.writer, "\"{}\"", name)?;
+        // Default value (not allowed in proto3)
+        if syntax != ProtoSyntax::Proto3 {
+            if let Some(default) = &field.default_value {
+                use prost_types::field_descriptor_proto::Type;
+                let formatted = match field.r#type() {
+                    Type::String => format!("\"{}\"", escape_string(default)),
+                    Type::Bytes => format!("\"{}\"", escape_string(default)),
+                    Type::Enum => default.clone(),
+                    Type::Bool => default.clone(),
+                    _ => default.clone(),
+                };
+                options.push(format!("default = {}", formatted));
             }
-            writeln!(self.writer, ";")?;
         }
 
-        // Reserved ranges
-        if !message.reserved_range.is_empty() {
-            self.write_indent()?;
-            write!(self.writer, "reserved ")?;
-            for (i, range) in message.reserved_range.iter().enumerate() {
-                if i > 0 {
-                    write!(self.writer, ", ")?;
-                }
-                if range.start() == range.end() - 1 {
-                    write!(self.writer, "{}", range.start())?;
-                } else {
-                    let end = if range.end() == MAX_FIELD_NUMBER as i32 + 1 {
-                        "max".to_string()
-                    } else {
-                        (range.end() - 1).to_string()
-                    };
-                    write!(self.writer, "{} to {}", range.start(), end)?;
-                }
+        // JSON name if different from default
+        if let Some(json_name) = &field.json_name {
+            let default_json_name = to_lower_camel_case(field.name());
+            if json_name != &default_json_name {
+                options.push(format!("json_name = \"{}\"", json_name));
             }
-            writeln!(self.writer, ";")?;
         }
 
-        Ok(())
-    }
-
-    fn write_oneof(
-        &mut self,
-        oneof: &prost_types::OneofDescriptorProto,
-        fields: &[&prost_types::FieldDescriptorProto],
-        _syntax: ProtoSyntax,
-    ) -> std::fmt::Result {
-        self.write_indent()?;
-        writeln!(self.writer, "oneof {} {{", oneof.name())?;
-        self.indent();
+        // Standard field options
+        options.extend(self.standard_options(Element::Field));
 
-        for field in fields {
-            self.write_oneof_field(field)?;
+        // Editions features, including those implied by proto2-style
+        // descriptor values that editions can't spell directly
+        let mut features = self.features(features_field::FIELD);
+        if syntax == ProtoSyntax::Editions {
+            use prost_types::field_descriptor_proto::{Label, Type};
+            if field.label() == Label::Required && !features.contains(LEGACY_REQUIRED) {
+                features.insert(LEGACY_REQUIRED);
+            }
+            if field.r#type() == Type::Group && !features.contains(DELIMITED) {
+                features.insert(DELIMITED);
+            }
         }
+        options.extend(features.assignments());
 
-        self.dedent();
-        self.writeln("}")?;
+        options.extend(self.custom_options(Element::Field).assignments);
+
+        if !options.is_empty() {
+            write!(self.writer, " [{}]", options.join(", "))?;
+        }
 
         Ok(())
     }
 
-    fn write_oneof_field(&mut self, field: &prost_types::FieldDescriptorProto) -> std::fmt::Result {
-        self.write_indent()?;
-        writeln!(
-            self.writer,
-            "{} {} = {};",
-            self.field_type_name(field),
-            field.name(),
-            field.number()
-        )?;
-        Ok(())
+    /// Returns the start and comments of the `extend` block that declared
+    /// the current extension
+    fn extend_comments(&self) -> Option<Block> {
+        if !self.file.config.include_comments {
+            return None;
+        }
+        let start = self.source.start(&self.path)?;
+        self.source
+            .block(&self.path[..self.path.len() - 1], start)
+            .cloned()
     }
 
-    fn write_field(
+    /// Writes what precedes a field's type: comments, unresolved options,
+    /// indentation and label
+    fn write_field_start(
         &mut self,
         field: &prost_types::FieldDescriptorProto,
-        syntax: ProtoSyntax,
-        message: &prost_types::DescriptorProto,
+        cx: &VisitContext<'_>,
     ) -> std::fmt::Result {
+        // Extensions merged from a later block keep its comments
+        if field.extendee.is_some() {
+            let block = self.extend_comments();
+            let start = block.as_ref().map(|(start, _)| *start);
+            if start != self.extend_block {
+                self.write_leading(block.as_ref().map(|(_, comments)| comments))?;
+                self.extend_block = start;
+            }
+        }
+
+        self.write_leading_comments()?;
+        self.write_unresolved_options(Element::Field)?;
         self.write_indent()?;
 
-        // Determine field label
-        let label = self.field_label(field, syntax, message);
+        let label = self.label(field, cx);
         if !label.is_empty() {
             write!(self.writer, "{} ", label)?;
         }
-
-        // Check for map type
-        if self.is_map_field(field, message) {
-            self.write_map_field(field, message)?;
-        } else {
-            // Regular field
-            write!(
-                self.writer,
-                "{} {} = {}",
-                self.field_type_name(field),
-                field.name(),
-                field.number()
-            )?;
-
-            // Field options (default value, etc.)
-            self.write_field_options(field, syntax)?;
-
-            writeln!(self.writer, ";")?;
-        }
-
         Ok(())
     }
 
-    fn is_map_field(
+    /// Returns the label written before a field, extension or group
+    fn label(
         &self,
         field: &prost_types::FieldDescriptorProto,
-        message: &prost_types::DescriptorProto,
-    ) -> bool {
-        if field.label() != prost_types::field_descriptor_proto::Label::Repeated {
-            return false;
+        cx: &VisitContext<'_>,
+    ) -> &'static str {
+        use prost_types::field_descriptor_proto::Label;
+
+        let syntax = cx.syntax();
+        match cx.message() {
+            _ if field.extendee.is_some() => match field.label() {
+                Label::Repeated => "repeated",
+                Label::Required if syntax != ProtoSyntax::Editions => "required",
+                Label::Optional if syntax == ProtoSyntax::Proto2 => "optional",
+                _ => "",
+            },
+            // Members of a real oneof have no label
+            Some(message) if field.oneof_index.is_some() && !is_proto3_optional(field, message) => {
+                ""
+            }
+            Some(message) => self.field_label(field, syntax, message),
+            None => "",
         }
-        if field.r#type() != prost_types::field_descriptor_proto::Type::Message {
-            return false;
+    }
+}
+
+impl<W: FmtWrite> ProtoWriter for DefaultProtoWriter<'_, W> {
+    fn enter_file(
+        &mut self,
+        proto: &FileDescriptorProto,
+        cx: &VisitContext<'_>,
+    ) -> std::fmt::Result {
+        // Syntax or edition declaration
+        let syntax = cx.syntax();
+        if let Some(edition) = self.file.edition() {
+            self.path = vec![14];
+            self.write_leading_comments()?;
+            write!(self.writer, "edition = \"{}\";", edition)?;
+        } else {
+            self.path = vec![12];
+            self.write_leading_comments()?;
+            write!(self.writer, "syntax = \"{}\";", syntax.as_str())?;
         }
+        self.end_line()?;
+        writeln!(self.writer)?;
 
-        // Find the nested type
-        let type_name = field.type_name();
-        for nested in &message.nested_type {
-            let expected_name = format!(".{}", nested.name());
-            if type_name.ends_with(&expected_name) || type_name == nested.name() {
-                return nested.options.as_ref().map_or(false, |o| o.map_entry.unwrap_or(false));
-            }
+        // Package
+        if !proto.package().is_empty() {
+            self.path = vec![2];
+            self.write_leading_comments()?;
+            write!(self.writer, "package {};", proto.package())?;
+            self.end_line()?;
+            writeln!(self.writer)?;
         }
+        self.at(cx);
 
-        false
+        // File options
+        self.write_file_options()?;
+
+        // Imports
+        self.write_imports(proto)
     }
 
-    fn write_map_field(
+    fn enter_service(
         &mut self,
-        field: &prost_types::FieldDescriptorProto,
-        message: &prost_types::DescriptorProto,
+        service: &prost_types::ServiceDescriptorProto,
+        cx: &VisitContext<'_>,
     ) -> std::fmt::Result {
-        // Find the map entry type
-        let type_name = field.type_name();
-        for nested in &message.nested_type {
-            let expected_name = format!(".{}", nested.name());
-            if type_name.ends_with(&expected_name) || type_name == nested.name() {
-                if nested.options.as_ref().map_or(false, |o| o.map_entry.unwrap_or(false)) {
-                    // This is a map entry
-                    let key_field = nested.field.iter().find(|f| f.number() == 1);
-                    let value_field = nested.field.iter().find(|f| f.number() == 2);
-
-                    if let (Some(key), Some(value)) = (key_field, value_field) {
-                        writeln!(
-                            self.writer,
-                            "map<{}, {}> {} = {};",
-                            self.field_type_name(key),
-                            self.field_type_name(value),
-                            field.name(),
-                            field.number()
-                        )?;
-                        return Ok(());
-                    }
-                }
-            }
-        }
+        self.at(cx);
+        self.write_leading_comments()?;
+        write!(self.writer, "service {} {{", service.name())?;
+        self.indent();
+        self.end_line()?;
 
-        // Fallback: just write as a regular field
-        writeln!(
+        self.write_element_options(Element::Service, features_field::SERVICE)
+    }
+
+    fn exit_service(
+        &mut self,
+        _service: &prost_types::ServiceDescriptorProto,
+        _cx: &VisitContext<'_>,
+    ) -> std::fmt::Result {
+        self.dedent();
+        writeln!(self.writer, "}}")?;
+        writeln!(self.writer)
+    }
+
+    fn write_method(
+        &mut self,
+        method: &prost_types::MethodDescriptorProto,
+        cx: &VisitContext<'_>,
+    ) -> std::fmt::Result {
+        self.at(cx);
+        let client_streaming = method.client_streaming.unwrap_or(false);
+        let server_streaming = method.server_streaming.unwrap_or(false);
+
+        let input = if client_streaming {
+            format!("stream {}", self.type_ref(method.input_type()))
+        } else {
+            self.type_ref(method.input_type())
+        };
+
+        let output = if server_streaming {
+            format!("stream {}", self.type_ref(method.output_type()))
+        } else {
+            self.type_ref(method.output_type())
+        };
+
+        self.write_leading_comments()?;
+        self.write_indent()?;
+        write!(
             self.writer,
-            "{} {} = {};",
-            self.field_type_name(field),
-            field.name(),
-            field.number()
+            "rpc {}({}) returns ({})",
+            method.name(),
+            input,
+            output
         )?;
 
+        if self
+            .element_options(Element::Method, features_field::METHOD)
+            .is_empty()
+            && self.custom_options(Element::Method).unresolved.is_empty()
+        {
+            write!(self.writer, ";")?;
+            self.end_line()?;
+        } else {
+            write!(self.writer, " {{")?;
+            self.indent();
+            self.end_line()?;
+            self.write_element_options(Element::Method, features_field::METHOD)?;
+            self.dedent();
+            self.writeln("}")?;
+        }
+
         Ok(())
     }
 
-    fn field_label(
-        &self,
-        field: &prost_types::FieldDescriptorProto,
-        syntax: ProtoSyntax,
+    fn enter_message(
+        &mut self,
         message: &prost_types::DescriptorProto,
-    ) -> &'static str {
-        use prost_types::field_descriptor_proto::Label;
+        cx: &VisitContext<'_>,
+    ) -> std::fmt::Result {
+        self.at(cx);
+        // A group's opening line was written with its field
+        if cx.group().is_none() {
+            self.write_leading_comments()?;
+            self.write_indent()?;
+            write!(self.writer, "message {} {{", message.name())?;
+            self.indent();
+            self.end_line()?;
+        }
 
-        match field.label() {
-            Label::Repeated => {
-                // Check if this is a map (maps don't get "repeated" label)
-                if self.is_map_field(field, message) {
-                    ""
-                } else {
-                    "repeated"
-                }
-            }
-            Label::Required => "required",
-            Label::Optional => {
-                match syntax {
-                    ProtoSyntax::Proto2 => "optional",
-                    ProtoSyntax::Proto3 => {
-                        // In proto3, check if this is an explicit optional (has synthetic oneof)
-                        if Self::is_proto3_optional(field, message) {
-                            "optional"
-                        } else {
-                            ""
-                        }
-                    }
-                }
-            }
+        // Message options
+        self.write_element_options(Element::Message, features_field::MESSAGE)
+    }
+
+    fn exit_message(
+        &mut self,
+        _message: &prost_types::DescriptorProto,
+        cx: &VisitContext<'_>,
+    ) -> std::fmt::Result {
+        if cx.group().is_some() {
+            return Ok(());
         }
+        self.dedent();
+        self.writeln("}")?;
+        writeln!(self.writer)
     }
 
-    fn field_type_name(&self, field: &prost_types::FieldDescriptorProto) -> String {
-        use prost_types::field_descriptor_proto::Type;
+    fn write_field(
+        &mut self,
+        field: &prost_types::FieldDescriptorProto,
+        cx: &VisitContext<'_>,
+    ) -> std::fmt::Result {
+        self.at(cx);
+        self.write_field_start(field, cx)?;
 
-        match field.r#type() {
-            Type::Double => "double".to_string(),
-            Type::Float => "float".to_string(),
-            Type::Int64 => "int64".to_string(),
-            Type::Uint64 => "uint64".to_string(),
-            Type::Int32 => "int32".to_string(),
-            Type::Fixed64 => "fixed64".to_string(),
-            Type::Fixed32 => "fixed32".to_string(),
-            Type::Bool => "bool".to_string(),
-            Type::String => "string".to_string(),
-            Type::Bytes => "bytes".to_string(),
-            Type::Uint32 => "uint32".to_string(),
-            Type::Sfixed32 => "sfixed32".to_string(),
-            Type::Sfixed64 => "sfixed64".to_string(),
-            Type::Sint32 => "sint32".to_string(),
-            Type::Sint64 => "sint64".to_string(),
-            Type::Group => "group".to_string(),
-            Type::Message | Type::Enum => {
-                // Return the full type name
-                field.type_name().to_string()
+        // Check for map type
+        match cx.message() {
+            Some(message) if field.extendee.is_none() && self.is_map_field(field, message) => {
+                self.write_map_field(field, message)?;
+            }
+            _ => {
+                // Regular field
+                write!(
+                    self.writer,
+                    "{} {} = {}",
+                    self.value_type_name(field, cx.syntax()),
+                    field.name(),
+                    field.number()
+                )?;
             }
         }
+
+        // Field options (default value, etc.)
+        self.write_field_options(field, cx.syntax())?;
+
+        write!(self.writer, ";")?;
+        self.end_line()
     }
 
-    fn write_field_options(
+    /// Writes `group Name = N {` with the field's label, options and comments
+    fn enter_group(
         &mut self,
         field: &prost_types::FieldDescriptorProto,
-        syntax: ProtoSyntax,
+        cx: &VisitContext<'_>,
     ) -> std::fmt::Result {
-        let mut options = Vec::new();
+        self.at(cx);
+        self.write_field_start(field, cx)?;
+
+        // The body's name is the last component of the field's type
+        let name = field.type_name().rsplit('.').next().unwrap_or_default();
+        write!(self.writer, "group {} = {}", name, field.number())?;
+        self.write_field_options(field, cx.syntax())?;
+        write!(self.writer, " {{")?;
+        self.indent();
+        self.end_line()
+    }
 
-        // Default value (proto2 only)
-        if syntax == ProtoSyntax::Proto2 {
-            if let Some(default) = &field.default_value {
-                use prost_types::field_descriptor_proto::Type;
-                let formatted = match field.r#type() {
-                    Type::String => format!("\"{}\"", escape_string(default)),
-                    Type::Bytes => format!("\"{}\"", escape_string(default)),
-                    Type::Enum => default.clone(),
-                    Type::Bool => default.clone(),
-                    _ => default.clone(),
-                };
-                options.push(format!("default = {}", formatted));
-            }
-        }
+    fn exit_group(
+        &mut self,
+        _field: &prost_types::FieldDescriptorProto,
+        _cx: &VisitContext<'_>,
+    ) -> std::fmt::Result {
+        self.dedent();
+        self.writeln("}")
+    }
 
-        // JSON name if different from default
-        if let Some(json_name) = &field.json_name {
-            let default_json_name = to_lower_camel_case(field.name());
-            if json_name != &default_json_name {
-                options.push(format!("json_name = \"{}\"", json_name));
-            }
+    fn enter_oneof(
+        &mut self,
+        oneof: &prost_types::OneofDescriptorProto,
+        cx: &VisitContext<'_>,
+    ) -> std::fmt::Result {
+        self.at(cx);
+        self.write_leading_comments()?;
+        self.write_indent()?;
+        write!(self.writer, "oneof {} {{", oneof.name())?;
+        self.indent();
+        self.end_line()?;
+
+        self.write_element_options(Element::Oneof, features_field::ONEOF)
+    }
+
+    fn exit_oneof(
+        &mut self,
+        _oneof: &prost_types::OneofDescriptorProto,
+        _cx: &VisitContext<'_>,
+    ) -> std::fmt::Result {
+        self.dedent();
+        self.writeln("}")
+    }
+
+    fn enter_extend(
+        &mut self,
+        extendee: &str,
+        extensions: &[(usize, &prost_types::FieldDescriptorProto)],
+        cx: &VisitContext<'_>,
+    ) -> std::fmt::Result {
+        self.at(cx);
+        self.path.push(extensions[0].0 as i32);
+        let block = self.extend_comments();
+        self.at(cx);
+        self.extend_block = block.as_ref().map(|(start, _)| *start);
+        let comments = block.map(|(_, comments)| comments);
+
+        self.write_leading(comments.as_ref())?;
+        self.write_indent()?;
+        write!(self.writer, "extend {} {{", self.type_ref(extendee))?;
+        self.indent();
+        self.end_line_with(comments.and_then(|c| c.trailing).as_deref())
+    }
+
+    fn exit_extend(&mut self, _extendee: &str, _cx: &VisitContext<'_>) -> std::fmt::Result {
+        self.dedent();
+        self.writeln("}")?;
+        writeln!(self.writer)
+    }
+
+    fn write_extension_range(
+        &mut self,
+        range: &prost_types::descriptor_proto::ExtensionRange,
+        cx: &VisitContext<'_>,
+    ) -> std::fmt::Result {
+        self.at(cx);
+        self.write_leading_comments()?;
+        self.write_unresolved_options(Element::ExtensionRange)?;
+        self.write_indent()?;
+        let end = if range.end() == MAX_FIELD_NUMBER as i32 + 1 {
+            "max".to_string()
+        } else {
+            (range.end() - 1).to_string()
+        };
+        write!(self.writer, "extensions {} to {}", range.start(), end)?;
+        let options =
+            self.element_options(Element::ExtensionRange, features_field::EXTENSION_RANGE);
+        if !options.is_empty() {
+            write!(self.writer, " [{}]", options.join(", "))?;
         }
+        write!(self.writer, ";")?;
+        self.end_line()
+    }
 
-        // Packed option
-        if let Some(opts) = &field.options {
-            if let Some(packed) = opts.packed {
-                options.push(format!("packed = {}", packed));
-            }
-            if let Some(deprecated) = opts.deprecated {
-                if deprecated {
-                    options.push("deprecated = true".to_string());
+    fn write_reserved(
+        &mut self,
+        message: &prost_types::DescriptorProto,
+        _cx: &VisitContext<'_>,
+    ) -> std::fmt::Result {
+        // Reserved names
+        if !message.reserved_name.is_empty() {
+            self.write_indent()?;
+            write!(self.writer, "reserved ")?;
+            for (i, name) in message.reserved_name.iter().enumerate() {
+                if i > 0 {
+                    write!(self.writer, ", ")?;
                 }
+                write!(self.writer, "\"{}\"", name)?;
             }
+            writeln!(self.writer, ";")?;
         }
 
-        if !options.is_empty() {
-            write!(self.writer, " [{}]", options.join(", "))?;
+        // Reserved ranges
+        if !message.reserved_range.is_empty() {
+            self.write_indent()?;
+            write!(self.writer, "reserved ")?;
+            for (i, range) in message.reserved_range.iter().enumerate() {
+                if i > 0 {
+                    write!(self.writer, ", ")?;
+                }
+                if range.start() == range.end() - 1 {
+                    write!(self.writer, "{}", range.start())?;
+                } else {
+                    let end = if range.end() == MAX_FIELD_NUMBER as i32 + 1 {
+                        "max".to_string()
+                    } else {
+                        (range.end() - 1).to_string()
+                    };
+                    write!(self.writer, "{} to {}", range.start(), end)?;
+                }
+            }
+            writeln!(self.writer, ";")?;
         }
 
         Ok(())
     }
 
-    fn write_enum(&mut self, enum_type: &prost_types::EnumDescriptorProto) -> std::fmt::Result {
+    fn enter_enum(
+        &mut self,
+        enum_type: &prost_types::EnumDescriptorProto,
+        cx: &VisitContext<'_>,
+    ) -> std::fmt::Result {
+        self.at(cx);
+        self.write_leading_comments()?;
         self.write_indent()?;
-        writeln!(self.writer, "enum {} {{", enum_type.name())?;
+        write!(self.writer, "enum {} {{", enum_type.name())?;
         self.indent();
+        self.end_line()?;
 
-        // Check for allow_alias option
-        if let Some(opts) = &enum_type.options {
-            if opts.allow_alias.unwrap_or(false) {
-                self.writeln("option allow_alias = true;")?;
-            }
-        }
+        // Enum options
+        self.write_element_options(Element::Enum, features_field::ENUM)
+    }
 
+    fn exit_enum(
+        &mut self,
+        _enum_type: &prost_types::EnumDescriptorProto,
+        _cx: &VisitContext<'_>,
+    ) -> std::fmt::Result {
+        self.dedent();
+        self.writeln("}")?;
+        writeln!(self.writer)
+    }
+
+    fn write_enum_reserved(
+        &mut self,
+        enum_type: &prost_types::EnumDescriptorProto,
+        _cx: &VisitContext<'_>,
+    ) -> std::fmt::Result {
         // Reserved ranges
         if !enum_type.reserved_range.is_empty() {
             self.write_indent()?;
@@ -844,64 +1347,28 @@ impl<'a, W: FmtWrite> DefaultProtoWriter<'a, W> {
             writeln!(self.writer, ";")?;
         }
 
-        // Values
-        for value in &enum_type.value {
-            self.write_indent()?;
-            write!(self.writer, "{} = {}", value.name(), value.number())?;
-
-            // Value options
-            if let Some(opts) = &value.options {
-                if opts.deprecated.unwrap_or(false) {
-                    write!(self.writer, " [deprecated = true]")?;
-                }
-            }
-
-            writeln!(self.writer, ";")?;
-        }
-
-        self.dedent();
-        self.writeln("}")?;
-        writeln!(self.writer)?;
-
         Ok(())
     }
 
-    fn write_extension(
+    fn write_enum_value(
         &mut self,
-        extension: &prost_types::FieldDescriptorProto,
-        syntax: ProtoSyntax,
+        value: &prost_types::EnumValueDescriptorProto,
+        cx: &VisitContext<'_>,
     ) -> std::fmt::Result {
+        self.at(cx);
+        self.write_leading_comments()?;
+        self.write_unresolved_options(Element::EnumValue)?;
         self.write_indent()?;
-        writeln!(self.writer, "extend {} {{", extension.extendee())?;
-        self.indent();
-
-        self.write_indent()?;
+        write!(self.writer, "{} = {}", value.name(), value.number())?;
 
-        // Label
-        use prost_types::field_descriptor_proto::Label;
-        match extension.label() {
-            Label::Repeated => write!(self.writer, "repeated ")?,
-            Label::Required => write!(self.writer, "required ")?,
-            Label::Optional => {
-                if syntax == ProtoSyntax::Proto2 {
-                    write!(self.writer, "optional ")?;
-                }
-            }
+        // Value options
+        let options = self.element_options(Element::EnumValue, features_field::ENUM_VALUE);
+        if !options.is_empty() {
+            write!(self.writer, " [{}]", options.join(", "))?;
         }
 
-        writeln!(
-            self.writer,
-            "{} {} = {};",
-            self.field_type_name(extension),
-            extension.name(),
-            extension.number()
-        )?;
-
-        self.dedent();
-        self.writeln("}")?;
-        writeln!(self.writer)?;
-
-        Ok(())
+        write!(self.writer, ";")?;
+        self.end_line()
     }
 }
 
@@ -967,6 +1434,438 @@ mod tests {
         assert_eq!(ProtoSyntax::try_from("").unwrap(), ProtoSyntax::Proto2);
         assert_eq!(ProtoSyntax::try_from("proto2").unwrap(), ProtoSyntax::Proto2);
         assert_eq!(ProtoSyntax::try_from("proto3").unwrap(), ProtoSyntax::Proto3);
+        assert_eq!(ProtoSyntax::try_from("editions").unwrap(), ProtoSyntax::Editions);
         assert!(ProtoSyntax::try_from("proto4").is_err());
     }
+
+    #[test]
+    fn test_standard_options_rendering() {
+        use prost_types::method_options::IdempotencyLevel;
+        use prost_types::{
+            DescriptorProto, FieldDescriptorProto, FileOptions, MessageOptions,
+            MethodDescriptorProto, MethodOptions, ServiceDescriptorProto, ServiceOptions,
+        };
+
+        let file = FileDescriptorProto {
+            name: Some("test.proto".to_string()),
+            syntax: Some("proto3".to_string()),
+            options: Some(FileOptions {
+                java_package: Some("com.example".to_string()),
+                optimize_for: Some(3),
+                deprecated: Some(true),
+                cc_generic_services: Some(false),
+                ..Default::default()
+            }),
+            message_type: vec![DescriptorProto {
+                name: Some("M".to_string()),
+                field: vec![FieldDescriptorProto {
+                    name: Some("s".to_string()),
+                    number: Some(1),
+                    r#type: Some(9),
+                    options: Some(prost_types::FieldOptions {
+                        ctype: Some(1),
+                        jstype: Some(1),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }],
+                options: Some(MessageOptions {
+                    deprecated: Some(true),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+            service: vec![ServiceDescriptorProto {
+                name: Some("S".to_string()),
+                method: vec![MethodDescriptorProto {
+                    name: Some("Get".to_string()),
+                    input_type: Some(".M".to_string()),
+                    output_type: Some(".M".to_string()),
+                    options: Some(MethodOptions {
+                        idempotency_level: Some(IdempotencyLevel::NoSideEffects as i32),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }],
+                options: Some(ServiceOptions {
+                    deprecated: Some(true),
+                    ..Default::default()
+                }),
+            }],
+            ..Default::default()
+        };
+
+        let output = ProtoReconstructor::from_proto(file).unwrap().reconstruct();
+        assert!(output.contains(
+            "option java_package = \"com.example\";\n\
+             option optimize_for = LITE_RUNTIME;\n\
+             option cc_generic_services = false;\n\
+             option deprecated = true;\n"
+        ));
+        assert!(output.contains("message M {\n  option deprecated = true;\n"));
+        assert!(output.contains("string s = 1 [ctype = CORD, jstype = JS_STRING];"));
+        assert!(output.contains("service S {\n  option deprecated = true;\n"));
+        assert!(output.contains(
+            "  rpc Get(M) returns (M) {\n    option idempotency_level = NO_SIDE_EFFECTS;\n  }\n"
+        ));
+    }
+
+    #[test]
+    fn test_editions_reconstruction() {
+        use prost_types::field_descriptor_proto::{Label, Type};
+        use prost_types::FieldDescriptorProto;
+        use raw::tests::{len_field, varint_field};
+
+        let field = |name: &str, number, label: Label| FieldDescriptorProto {
+            name: Some(name.to_string()),
+            number: Some(number),
+            label: Some(label as i32),
+            r#type: Some(Type::Int32 as i32),
+            ..Default::default()
+        };
+        let explicit = [
+            field("a", 1, Label::Optional).encode_to_vec(),
+            len_field(8, &len_field(features_field::FIELD, &varint_field(1, 1))),
+        ]
+        .concat();
+        let message = [
+            len_field(1, b"M"),
+            len_field(2, &explicit),
+            len_field(2, &field("b", 2, Label::Required).encode_to_vec()),
+            len_field(2, &field("c", 3, Label::Repeated).encode_to_vec()),
+        ]
+        .concat();
+        let file = FileDescriptorProto {
+            name: Some("test.proto".to_string()),
+            package: Some("test".to_string()),
+            syntax: Some("editions".to_string()),
+            ..Default::default()
+        };
+        let data = [
+            file.encode_to_vec(),
+            len_field(4, &message),
+            len_field(8, &len_field(features_field::FILE, &varint_field(1, 2))),
+            varint_field(14, 1000),
+        ]
+        .concat();
+
+        let reconstructor = ProtoReconstructor::from_bytes(&data).unwrap();
+        assert_eq!(reconstructor.syntax(), ProtoSyntax::Editions);
+        assert_eq!(reconstructor.edition(), Some(Edition::Edition2023));
+
+        let output = reconstructor.reconstruct();
+        assert!(output.starts_with("edition = \"2023\";\n"));
+        assert!(output.contains("option features.field_presence = IMPLICIT;"));
+        assert!(output.contains("  int32 a = 1 [features.field_presence = EXPLICIT];"));
+        assert!(output.contains("  int32 b = 2 [features.field_presence = LEGACY_REQUIRED];"));
+        assert!(output.contains("  repeated int32 c = 3;"));
+        assert!(!output.contains("optional"));
+        assert!(!output.contains("required "));
+    }
+
+    #[test]
+    fn test_custom_options_reconstruction() {
+        use prost_types::field_descriptor_proto::Type;
+        use prost_types::{DescriptorProto, FieldDescriptorProto};
+        use raw::tests::{len_field, varint_field};
+
+        // The file defines one option itself; 50002 is defined nowhere
+        let file = FileDescriptorProto {
+            name: Some("test.proto".to_string()),
+            package: Some("test".to_string()),
+            syntax: Some("proto3".to_string()),
+            extension: vec![FieldDescriptorProto {
+                name: Some("table".to_string()),
+                number: Some(50001),
+                r#type: Some(Type::String as i32),
+                extendee: Some(".google.protobuf.MessageOptions".to_string()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let field = FieldDescriptorProto {
+            name: Some("id".to_string()),
+            number: Some(1),
+            r#type: Some(Type::Int32 as i32),
+            ..Default::default()
+        };
+        let message = [
+            DescriptorProto {
+                name: Some("M".to_string()),
+                ..Default::default()
+            }
+            .encode_to_vec(),
+            len_field(7, &len_field(50001, b"rows")),
+            len_field(
+                2,
+                &[field.encode_to_vec(), len_field(8, &varint_field(50002, 5))].concat(),
+            ),
+        ]
+        .concat();
+        let data = [file.encode_to_vec(), len_field(4, &message)].concat();
+
+        let output = ProtoReconstructor::from_bytes(&data).unwrap().reconstruct();
+        assert!(output.contains("message M {\n  option (test.table) = \"rows\";\n"));
+        assert!(output
+            .contains("  // unresolved option: FieldOptions field 50002 = 5\n  int32 id = 1;\n"));
+    }
+
+    #[test]
+    fn test_group_rendering() {
+        use prost_types::field_descriptor_proto::{Label, Type};
+        use prost_types::{DescriptorProto, FieldDescriptorProto};
+
+        let field =
+            |name: &str, number, label: Label, ty: Type, type_name: &str| FieldDescriptorProto {
+                name: Some(name.to_string()),
+                number: Some(number),
+                label: Some(label as i32),
+                r#type: Some(ty as i32),
+                type_name: (!type_name.is_empty()).then(|| type_name.to_string()),
+                ..Default::default()
+            };
+        let body = DescriptorProto {
+            name: Some("Result".to_string()),
+            field: vec![field("url", 2, Label::Required, Type::String, "")],
+            ..Default::default()
+        };
+        let file = FileDescriptorProto {
+            name: Some("test.proto".to_string()),
+            package: Some("test".to_string()),
+            message_type: vec![
+                DescriptorProto {
+                    name: Some("Search".to_string()),
+                    field: vec![field(
+                        "result",
+                        1,
+                        Label::Repeated,
+                        Type::Group,
+                        ".test.Search.Result",
+                    )],
+                    nested_type: vec![body.clone()],
+                    ..Default::default()
+                },
+                body,
+            ],
+            extension: vec![FieldDescriptorProto {
+                extendee: Some(".test.Search".to_string()),
+                ..field("result", 100, Label::Optional, Type::Group, ".test.Result")
+            }],
+            ..Default::default()
+        };
+
+        let output = ProtoReconstructor::from_proto(file).unwrap().reconstruct();
+        assert!(output.contains(
+            "message Search {\n  repeated group Result = 1 {\n    \
+             required string url = 2;\n  }\n}\n"
+        ));
+        assert!(output.contains(
+            "extend Search {\n  optional group Result = 100 {\n    \
+             required string url = 2;\n  }\n}\n"
+        ));
+        assert!(!output.contains("message Result"));
+    }
+
+    #[test]
+    fn test_extend_blocks() {
+        use prost_types::field_descriptor_proto::{Label, Type};
+        use prost_types::{FieldDescriptorProto, FieldOptions};
+
+        let extension = |name: &str, number, extendee: &str| FieldDescriptorProto {
+            name: Some(name.to_string()),
+            number: Some(number),
+            label: Some(Label::Optional as i32),
+            r#type: Some(Type::Int32 as i32),
+            extendee: Some(format!(".google.protobuf.{}", extendee)),
+            ..Default::default()
+        };
+        let file = FileDescriptorProto {
+            name: Some("test.proto".to_string()),
+            package: Some("test".to_string()),
+            syntax: Some("proto2".to_string()),
+            dependency: vec!["google/protobuf/descriptor.proto".to_string()],
+            extension: vec![
+                FieldDescriptorProto {
+                    default_value: Some("5".to_string()),
+                    options: Some(FieldOptions {
+                        deprecated: Some(true),
+                        ..Default::default()
+                    }),
+                    ..extension("a", 50001, "FieldOptions")
+                },
+                FieldDescriptorProto {
+                    label: Some(Label::Repeated as i32),
+                    options: Some(FieldOptions {
+                        packed: Some(true),
+                        ..Default::default()
+                    }),
+                    ..extension("b", 50002, "FieldOptions")
+                },
+                extension("c", 50003, "MessageOptions"),
+                extension("d", 50004, "FieldOptions"),
+            ],
+            ..Default::default()
+        };
+
+        let reconstructor = ProtoReconstructor::from_proto(file).unwrap();
+        let output = reconstructor.reconstruct();
+        assert!(output.contains(
+            "extend google.protobuf.FieldOptions {\n  \
+             optional int32 a = 50001 [default = 5, deprecated = true];\n  \
+             repeated int32 b = 50002 [packed = true];\n  \
+             optional int32 d = 50004;\n}\n\n\
+             extend google.protobuf.MessageOptions {\n  optional int32 c = 50003;\n}\n"
+        ));
+        assert_eq!(
+            output
+                .matches("extend google.protobuf.FieldOptions")
+                .count(),
+            1
+        );
+        // Grouping reorders the extensions without changing the descriptor
+        let report = reconstructor.verify().unwrap();
+        assert!(report.is_faithful(), "{:?}", report.differences);
+
+        // Runs of one extendee, in declaration order
+        let config = ReconstructorConfig::new().keep_extension_order(true);
+        let output = reconstructor.with_config(config).reconstruct();
+        assert!(output.contains(
+            "  repeated int32 b = 50002 [packed = true];\n}\n\n\
+             extend google.protobuf.MessageOptions {\n  optional int32 c = 50003;\n}\n\n\
+             extend google.protobuf.FieldOptions {\n  optional int32 d = 50004;\n}\n"
+        ));
+    }
+
+    #[test]
+    fn test_member_order() {
+        use prost_types::source_code_info::Location;
+        use prost_types::{
+            DescriptorProto, FieldDescriptorProto, OneofDescriptorProto, SourceCodeInfo,
+        };
+
+        let field = |name: &str, number, oneof_index| FieldDescriptorProto {
+            name: Some(name.to_string()),
+            number: Some(number),
+            r#type: Some(5),
+            oneof_index,
+            ..Default::default()
+        };
+        let mut file = FileDescriptorProto {
+            name: Some("test.proto".to_string()),
+            syntax: Some("proto3".to_string()),
+            message_type: vec![DescriptorProto {
+                name: Some("M".to_string()),
+                field: vec![
+                    field("a", 3, None),
+                    field("b", 1, None),
+                    field("c", 2, Some(0)),
+                    field("d", 5, Some(0)),
+                    field("e", 4, None),
+                ],
+                nested_type: vec![DescriptorProto {
+                    name: Some("Inner".to_string()),
+                    ..Default::default()
+                }],
+                oneof_decl: vec![OneofDescriptorProto {
+                    name: Some("choice".to_string()),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let order = |output: &str, names: &[&str]| {
+            let positions: Vec<usize> = names.iter().map(|n| output.find(n).unwrap()).collect();
+            assert!(positions.windows(2).all(|w| w[0] < w[1]), "{}", output);
+        };
+
+        // Declaration order, with protoc's layout for nested types
+        let output = ProtoReconstructor::from_proto(file.clone())
+            .unwrap()
+            .reconstruct();
+        order(
+            &output,
+            &["message Inner", "a = 3", "b = 1", "oneof choice", "e = 4"],
+        );
+
+        let config = ReconstructorConfig::new().sort_fields(true);
+        let output = ProtoReconstructor::from_proto(file.clone())
+            .unwrap()
+            .with_config(config)
+            .reconstruct();
+        order(&output, &["b = 1", "oneof choice", "a = 3", "e = 4"]);
+
+        // Source info places the nested message between fields
+        let location = |path: Vec<i32>, line| Location {
+            path,
+            span: vec![line, 2, 10],
+            ..Default::default()
+        };
+        file.source_code_info = Some(SourceCodeInfo {
+            location: vec![
+                location(vec![4, 0, 2, 0], 1),
+                location(vec![4, 0, 3, 0], 2),
+                location(vec![4, 0, 2, 1], 3),
+                location(vec![4, 0, 8, 0], 4),
+                location(vec![4, 0, 2, 4], 7),
+            ],
+        });
+        let output = ProtoReconstructor::from_proto(file).unwrap().reconstruct();
+        order(
+            &output,
+            &["a = 3", "message Inner", "b = 1", "oneof choice", "e = 4"],
+        );
+    }
+
+    #[test]
+    fn test_source_comments() {
+        use prost_types::source_code_info::Location;
+        use prost_types::{DescriptorProto, FieldDescriptorProto, SourceCodeInfo};
+
+        let location =
+            |path: Vec<i32>, leading: &str, trailing: &str, detached: &[&str]| Location {
+                path,
+                leading_comments: Some(leading.to_string()).filter(|c| !c.is_empty()),
+                trailing_comments: Some(trailing.to_string()).filter(|c| !c.is_empty()),
+                leading_detached_comments: detached.iter().map(|c| c.to_string()).collect(),
+                ..Default::default()
+            };
+        let file = FileDescriptorProto {
+            name: Some("test.proto".to_string()),
+            syntax: Some("proto3".to_string()),
+            message_type: vec![DescriptorProto {
+                name: Some("M".to_string()),
+                field: vec![FieldDescriptorProto {
+                    name: Some("id".to_string()),
+                    number: Some(1),
+                    r#type: Some(5),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            source_code_info: Some(SourceCodeInfo {
+                location: vec![
+                    location(vec![12], "", "", &[" Copyright\n"]),
+                    location(vec![4, 0], " A message.\n Second line.\n", " opened\n", &[]),
+                    location(vec![4, 0, 2, 0], "", " first\n second\n", &[]),
+                ],
+            }),
+            ..Default::default()
+        };
+
+        let output = ProtoReconstructor::from_proto(file.clone())
+            .unwrap()
+            .reconstruct();
+        assert!(output.starts_with("// Copyright\n\nsyntax = \"proto3\";\n"));
+        assert!(output.contains("// A message.\n// Second line.\nmessage M { // opened\n"));
+        assert!(output.contains("  int32 id = 1;\n  // first\n  // second\n\n}"));
+
+        let config = ReconstructorConfig::new().include_comments(false);
+        let output = ProtoReconstructor::from_proto(file)
+            .unwrap()
+            .with_config(config)
+            .reconstruct();
+        assert!(!output.contains("//"));
+    }
 }
@@ -0,0 +1,282 @@
+//! Type reference shortening.
+//!
+//! Descriptors store type references fully qualified (`.pkg.Outer.Inner`).
+//! protoc resolves a relative reference by looking its first component up
+//! in the referencing scope, then in each enclosing scope, and binds to the
+//! first symbol of that name it finds. A shortened reference is therefore
+//! only safe when that first match leads to the intended type.
+
+use prost_reflect::FileDescriptor;
+use prost_types::{DescriptorProto, EnumDescriptorProto, FileDescriptorProto};
+use std::collections::HashSet;
+
+/// Every symbol name a reference could bind to
+#[derive(Debug, Default)]
+pub(crate) struct SymbolTable {
+    /// Fully-qualified names without the leading dot
+    symbols: HashSet<String>,
+}
+
+impl SymbolTable {
+    /// Collects the symbols of a file and, when resolved, of its imports
+    ///
+    /// Symbols of unrecovered imports are unknown. Transitive imports are a
+    /// superset of what is visible, which can only make references longer
+    /// than needed.
+    pub(crate) fn new(proto: &FileDescriptorProto, descriptor: Option<&FileDescriptor>) -> Self {
+        let mut table = Self::default();
+        table.add_file(proto);
+        table.add_references(proto);
+
+        let mut visited = HashSet::new();
+        let mut queue: Vec<FileDescriptor> = descriptor
+            .map(|d| d.dependencies().collect())
+            .unwrap_or_default();
+        while let Some(file) = queue.pop() {
+            if visited.insert(file.name().to_string()) {
+                table.add_file(file.file_descriptor_proto());
+                queue.extend(file.dependencies());
+            }
+        }
+        table
+    }
+
+    fn add_file(&mut self, proto: &FileDescriptorProto) {
+        let package = proto.package();
+        // Each package component is a symbol of its own
+        let mut prefix = String::new();
+        for part in package.split('.').filter(|p| !p.is_empty()) {
+            prefix = qualify(&prefix, part);
+            self.symbols.insert(prefix.clone());
+        }
+
+        for message in &proto.message_type {
+            self.add_message(package, message);
+        }
+        for enum_type in &proto.enum_type {
+            self.add_enum(package, enum_type);
+        }
+        for extension in &proto.extension {
+            self.symbols.insert(qualify(package, extension.name()));
+        }
+        for service in &proto.service {
+            let name = qualify(package, service.name());
+            for method in &service.method {
+                self.symbols.insert(qualify(&name, method.name()));
+            }
+            self.symbols.insert(name);
+        }
+    }
+
+    /// Adds every scope a reference in the file points into
+    ///
+    /// The referenced types and their parents exist even when the file
+    /// defining them wasn't recovered.
+    fn add_references(&mut self, proto: &FileDescriptorProto) {
+        fn walk<'a>(message: &'a DescriptorProto, names: &mut Vec<&'a str>) {
+            for field in message.field.iter().chain(&message.extension) {
+                names.push(field.type_name());
+                names.push(field.extendee());
+            }
+            for nested in &message.nested_type {
+                walk(nested, names);
+            }
+        }
+
+        let mut names = Vec::new();
+        for message in &proto.message_type {
+            walk(message, &mut names);
+        }
+        for extension in &proto.extension {
+            names.push(extension.type_name());
+            names.push(extension.extendee());
+        }
+        for method in proto.service.iter().flat_map(|s| &s.method) {
+            names.push(method.input_type());
+            names.push(method.output_type());
+        }
+
+        for name in names {
+            let Some(name) = name.strip_prefix('.') else {
+                continue;
+            };
+            let mut prefix = String::new();
+            for part in name.split('.') {
+                prefix = qualify(&prefix, part);
+                self.symbols.insert(prefix.clone());
+            }
+        }
+    }
+
+    fn add_message(&mut self, scope: &str, message: &DescriptorProto) {
+        let name = qualify(scope, message.name());
+        for field in message.field.iter().chain(&message.extension) {
+            self.symbols.insert(qualify(&name, field.name()));
+        }
+        for oneof in &message.oneof_decl {
+            self.symbols.insert(qualify(&name, oneof.name()));
+        }
+        for nested in &message.nested_type {
+            self.add_message(&name, nested);
+        }
+        for enum_type in &message.enum_type {
+            self.add_enum(&name, enum_type);
+        }
+        self.symbols.insert(name);
+    }
+
+    fn add_enum(&mut self, scope: &str, enum_type: &EnumDescriptorProto) {
+        // Enum values are siblings of their enum, not children
+        for value in &enum_type.value {
+            self.symbols.insert(qualify(scope, value.name()));
+        }
+        self.symbols.insert(qualify(scope, enum_type.name()));
+    }
+
+    /// Returns the shortest reference to `type_name` that resolves to it
+    /// from `scope`
+    ///
+    /// Names without a leading dot are not fully qualified and are returned
+    /// unchanged. If every shorter form is shadowed, the fully-qualified
+    /// form is kept.
+    pub(crate) fn shortest(&self, type_name: &str, scope: &str) -> String {
+        let Some(target) = type_name.strip_prefix('.') else {
+            return type_name.to_string();
+        };
+
+        let parts: Vec<&str> = target.split('.').collect();
+        for start in (0..parts.len()).rev() {
+            let candidate = parts[start..].join(".");
+            if self.resolve(&candidate, scope).as_deref() == Some(target) {
+                return candidate;
+            }
+        }
+
+        type_name.to_string()
+    }
+
+    /// Resolves a relative reference the way protoc does, treating every
+    /// symbol as able to shadow
+    fn resolve(&self, name: &str, scope: &str) -> Option<String> {
+        let first = name.split('.').next().unwrap_or(name);
+        let mut scope = scope;
+        loop {
+            if self.symbols.contains(&qualify(scope, first)) {
+                return Some(qualify(scope, name));
+            }
+            if scope.is_empty() {
+                return None;
+            }
+            scope = scope.rsplit_once('.').map_or("", |(parent, _)| parent);
+        }
+    }
+}
+
+/// Joins a scope and a name
+pub(crate) fn qualify(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", scope, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost_types::{DescriptorProto, FieldDescriptorProto};
+
+    fn message(name: &str, nested: Vec<DescriptorProto>, fields: &[&str]) -> DescriptorProto {
+        DescriptorProto {
+            name: Some(name.to_string()),
+            nested_type: nested,
+            field: fields
+                .iter()
+                .map(|f| FieldDescriptorProto {
+                    name: Some(f.to_string()),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn table() -> SymbolTable {
+        let file = FileDescriptorProto {
+            package: Some("com.example".to_string()),
+            message_type: vec![
+                message("Outer", vec![message("Inner", vec![], &[])], &[]),
+                message("Other", vec![message("Outer", vec![], &[])], &["com"]),
+            ],
+            ..Default::default()
+        };
+        SymbolTable::new(&file, None)
+    }
+
+    #[test]
+    fn test_shortest_in_scope() {
+        let table = table();
+        assert_eq!(
+            table.shortest(".com.example.Outer.Inner", "com.example.Outer"),
+            "Inner"
+        );
+        assert_eq!(
+            table.shortest(".com.example.Outer.Inner", "com.example"),
+            "Outer.Inner"
+        );
+        assert_eq!(table.shortest(".com.example.Outer", "com.example"), "Outer");
+        assert_eq!(table.shortest("Relative", "com.example"), "Relative");
+    }
+
+    #[test]
+    fn test_shadowed_names_stay_qualified() {
+        let table = table();
+        // `Outer` means `Other.Outer` inside `Other`
+        assert_eq!(
+            table.shortest(".com.example.Outer.Inner", "com.example.Other"),
+            "example.Outer.Inner"
+        );
+        assert_eq!(
+            table.shortest(".com.example.Outer", "com.example.Other"),
+            "example.Outer"
+        );
+
+        // Inside `a.M`, both `X` and `a` are nested types
+        let file = FileDescriptorProto {
+            package: Some("a".to_string()),
+            message_type: vec![
+                message("X", vec![], &[]),
+                message(
+                    "M",
+                    vec![message("X", vec![], &[]), message("a", vec![], &[])],
+                    &[],
+                ),
+            ],
+            ..Default::default()
+        };
+        let table = SymbolTable::new(&file, None);
+        assert_eq!(table.shortest(".a.X", "a.M"), ".a.X");
+        assert_eq!(table.shortest(".a.M.X", "a.M"), "X");
+        assert_eq!(table.shortest(".a.X", "a"), "X");
+
+        // Types from unrecovered imports
+        let file = FileDescriptorProto {
+            package: Some("my.pkg".to_string()),
+            message_type: vec![DescriptorProto {
+                name: Some("M".to_string()),
+                field: vec![FieldDescriptorProto {
+                    name: Some("f".to_string()),
+                    type_name: Some(".other.pkg.Foo".to_string()),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let table = SymbolTable::new(&file, None);
+        assert_eq!(
+            table.shortest(".other.pkg.Foo", "my.pkg.M"),
+            "other.pkg.Foo"
+        );
+    }
+}
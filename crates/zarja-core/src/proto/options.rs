@@ -0,0 +1,409 @@
+//! Standard descriptor options.
+//!
+//! Options are rendered from the raw `*Options` bytes kept by
+//! [`super::raw`] rather than from the `prost_types` structs, which lack
+//! everything added to `descriptor.proto` in recent protobuf releases
+//! (`retention`, `targets`, `debug_redact`, ...). Each options message is
+//! described by a table of its fields, in field number order.
+
+use super::escape_string;
+use super::raw::Element;
+use crate::scanner::{decode_varint, fields, WireType};
+
+/// How an option value is encoded and rendered
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Bool,
+    Int32,
+    String,
+    Enum(&'static [(i32, &'static str)]),
+    /// A message rendered as a text-format aggregate
+    Message(&'static [OptionField]),
+    /// Known but rendered elsewhere (or never rendered)
+    Skip,
+}
+
+/// One field of an options message
+#[derive(Debug, Clone, Copy)]
+struct OptionField {
+    number: u32,
+    name: &'static str,
+    kind: Kind,
+    repeated: bool,
+}
+
+const fn field(number: u32, name: &'static str, kind: Kind) -> OptionField {
+    OptionField {
+        number,
+        name,
+        kind,
+        repeated: false,
+    }
+}
+
+const fn repeated(number: u32, name: &'static str, kind: Kind) -> OptionField {
+    OptionField {
+        number,
+        name,
+        kind,
+        repeated: true,
+    }
+}
+
+/// `uninterpreted_option`, present in every options message
+const UNINTERPRETED: OptionField = repeated(999, "uninterpreted_option", Kind::Skip);
+
+const OPTIMIZE_MODE: &[(i32, &str)] = &[(1, "SPEED"), (2, "CODE_SIZE"), (3, "LITE_RUNTIME")];
+
+const CTYPE: &[(i32, &str)] = &[(0, "STRING"), (1, "CORD"), (2, "STRING_PIECE")];
+
+const JSTYPE: &[(i32, &str)] = &[(0, "JS_NORMAL"), (1, "JS_STRING"), (2, "JS_NUMBER")];
+
+const RETENTION: &[(i32, &str)] = &[
+    (0, "RETENTION_UNKNOWN"),
+    (1, "RETENTION_RUNTIME"),
+    (2, "RETENTION_SOURCE"),
+];
+
+const TARGET_TYPE: &[(i32, &str)] = &[
+    (0, "TARGET_TYPE_UNKNOWN"),
+    (1, "TARGET_TYPE_FILE"),
+    (2, "TARGET_TYPE_EXTENSION_RANGE"),
+    (3, "TARGET_TYPE_MESSAGE"),
+    (4, "TARGET_TYPE_FIELD"),
+    (5, "TARGET_TYPE_ONEOF"),
+    (6, "TARGET_TYPE_ENUM"),
+    (7, "TARGET_TYPE_ENUM_ENTRY"),
+    (8, "TARGET_TYPE_SERVICE"),
+    (9, "TARGET_TYPE_METHOD"),
+];
+
+const IDEMPOTENCY_LEVEL: &[(i32, &str)] = &[
+    (0, "IDEMPOTENCY_UNKNOWN"),
+    (1, "NO_SIDE_EFFECTS"),
+    (2, "IDEMPOTENT"),
+];
+
+const VERIFICATION_STATE: &[(i32, &str)] = &[(0, "DECLARATION"), (1, "UNVERIFIED")];
+
+const EDITION: &[(i32, &str)] = &[
+    (0, "EDITION_UNKNOWN"),
+    (900, "EDITION_LEGACY"),
+    (998, "EDITION_PROTO2"),
+    (999, "EDITION_PROTO3"),
+    (1000, "EDITION_2023"),
+    (1001, "EDITION_2024"),
+    (0x7FFF_FFFF, "EDITION_MAX"),
+];
+
+/// `FieldOptions.EditionDefault`
+const EDITION_DEFAULT: &[OptionField] = &[
+    field(3, "edition", Kind::Enum(EDITION)),
+    field(2, "value", Kind::String),
+];
+
+/// `FieldOptions.FeatureSupport`
+const FEATURE_SUPPORT: &[OptionField] = &[
+    field(1, "edition_introduced", Kind::Enum(EDITION)),
+    field(2, "edition_deprecated", Kind::Enum(EDITION)),
+    field(3, "deprecation_warning", Kind::String),
+    field(4, "edition_removed", Kind::Enum(EDITION)),
+    field(5, "removal_error", Kind::String),
+];
+
+/// `ExtensionRangeOptions.Declaration`
+const DECLARATION: &[OptionField] = &[
+    field(1, "number", Kind::Int32),
+    field(2, "full_name", Kind::String),
+    field(3, "type", Kind::String),
+    field(5, "reserved", Kind::Bool),
+    field(6, "repeated", Kind::Bool),
+];
+
+const FILE_OPTIONS: &[OptionField] = &[
+    field(1, "java_package", Kind::String),
+    field(8, "java_outer_classname", Kind::String),
+    field(9, "optimize_for", Kind::Enum(OPTIMIZE_MODE)),
+    field(10, "java_multiple_files", Kind::Bool),
+    field(11, "go_package", Kind::String),
+    field(16, "cc_generic_services", Kind::Bool),
+    field(17, "java_generic_services", Kind::Bool),
+    field(18, "py_generic_services", Kind::Bool),
+    field(20, "java_generate_equals_and_hash", Kind::Bool),
+    field(23, "deprecated", Kind::Bool),
+    field(27, "java_string_check_utf8", Kind::Bool),
+    field(31, "cc_enable_arenas", Kind::Bool),
+    field(36, "objc_class_prefix", Kind::String),
+    field(37, "csharp_namespace", Kind::String),
+    field(39, "swift_prefix", Kind::String),
+    field(40, "php_class_prefix", Kind::String),
+    field(41, "php_namespace", Kind::String),
+    field(42, "php_generic_services", Kind::Bool),
+    field(44, "php_metadata_namespace", Kind::String),
+    field(45, "ruby_package", Kind::String),
+    field(50, "features", Kind::Skip),
+    UNINTERPRETED,
+];
+
+const MESSAGE_OPTIONS: &[OptionField] = &[
+    field(1, "message_set_wire_format", Kind::Bool),
+    field(2, "no_standard_descriptor_accessor", Kind::Bool),
+    field(3, "deprecated", Kind::Bool),
+    // Synthetic: implied by `map<K, V>` syntax
+    field(7, "map_entry", Kind::Skip),
+    field(11, "deprecated_legacy_json_field_conflicts", Kind::Bool),
+    field(12, "features", Kind::Skip),
+    UNINTERPRETED,
+];
+
+const FIELD_OPTIONS: &[OptionField] = &[
+    field(1, "ctype", Kind::Enum(CTYPE)),
+    field(2, "packed", Kind::Bool),
+    field(3, "deprecated", Kind::Bool),
+    field(5, "lazy", Kind::Bool),
+    field(6, "jstype", Kind::Enum(JSTYPE)),
+    field(10, "weak", Kind::Bool),
+    field(15, "unverified_lazy", Kind::Bool),
+    field(16, "debug_redact", Kind::Bool),
+    field(17, "retention", Kind::Enum(RETENTION)),
+    repeated(19, "targets", Kind::Enum(TARGET_TYPE)),
+    repeated(20, "edition_defaults", Kind::Message(EDITION_DEFAULT)),
+    field(21, "features", Kind::Skip),
+    field(22, "feature_support", Kind::Message(FEATURE_SUPPORT)),
+    UNINTERPRETED,
+];
+
+const ONEOF_OPTIONS: &[OptionField] = &[field(1, "features", Kind::Skip), UNINTERPRETED];
+
+const ENUM_OPTIONS: &[OptionField] = &[
+    field(2, "allow_alias", Kind::Bool),
+    field(3, "deprecated", Kind::Bool),
+    field(6, "deprecated_legacy_json_field_conflicts", Kind::Bool),
+    field(7, "features", Kind::Skip),
+    UNINTERPRETED,
+];
+
+const ENUM_VALUE_OPTIONS: &[OptionField] = &[
+    field(1, "deprecated", Kind::Bool),
+    field(2, "features", Kind::Skip),
+    field(3, "debug_redact", Kind::Bool),
+    field(4, "feature_support", Kind::Message(FEATURE_SUPPORT)),
+    UNINTERPRETED,
+];
+
+const SERVICE_OPTIONS: &[OptionField] = &[
+    field(33, "deprecated", Kind::Bool),
+    field(34, "features", Kind::Skip),
+    UNINTERPRETED,
+];
+
+const METHOD_OPTIONS: &[OptionField] = &[
+    field(33, "deprecated", Kind::Bool),
+    field(34, "idempotency_level", Kind::Enum(IDEMPOTENCY_LEVEL)),
+    field(35, "features", Kind::Skip),
+    UNINTERPRETED,
+];
+
+const EXTENSION_RANGE_OPTIONS: &[OptionField] = &[
+    repeated(2, "declaration", Kind::Message(DECLARATION)),
+    field(3, "verification", Kind::Enum(VERIFICATION_STATE)),
+    field(50, "features", Kind::Skip),
+    UNINTERPRETED,
+];
+
+fn table(element: Element) -> &'static [OptionField] {
+    match element {
+        Element::File => FILE_OPTIONS,
+        Element::Message => MESSAGE_OPTIONS,
+        Element::Field => FIELD_OPTIONS,
+        Element::Oneof => ONEOF_OPTIONS,
+        Element::Enum => ENUM_OPTIONS,
+        Element::EnumValue => ENUM_VALUE_OPTIONS,
+        Element::Service => SERVICE_OPTIONS,
+        Element::Method => METHOD_OPTIONS,
+        Element::ExtensionRange => EXTENSION_RANGE_OPTIONS,
+    }
+}
+
+/// Returns true if `number` is a field of the element's options message
+///
+/// Anything else is an extension, i.e. a custom option.
+pub(crate) fn is_standard(element: Element, number: u32) -> bool {
+    table(element).iter().any(|field| field.number == number)
+}
+
+/// Renders the standard options set in raw `*Options` bytes as
+/// `name = value` assignments, in field number order
+///
+/// `features` are not included; see [`super::editions::FeatureSet`].
+pub(crate) fn standard_options(element: Element, options: Option<&[u8]>) -> Vec<String> {
+    let Some(options) = options else {
+        return Vec::new();
+    };
+
+    let mut assignments = Vec::new();
+    for (field, values) in collect(table(element), options) {
+        for value in values {
+            assignments.push(format!("{} = {}", field.name, value));
+        }
+    }
+    assignments
+}
+
+/// Collects the rendered values of each table field present in `data`
+fn collect<'t>(table: &'t [OptionField], data: &[u8]) -> Vec<(&'t OptionField, Vec<String>)> {
+    let mut result = Vec::new();
+
+    for field in table {
+        if matches!(field.kind, Kind::Skip) {
+            continue;
+        }
+
+        let mut values = Vec::new();
+        let mut merged = Vec::new();
+        for wire in fields(data).filter(|w| w.number == field.number) {
+            match field.kind {
+                // Non-repeated message fields merge across occurrences
+                Kind::Message(_) if !field.repeated => {
+                    if let Some(bytes) = wire.as_bytes() {
+                        merged.extend_from_slice(bytes);
+                    }
+                }
+                _ => values.extend(render_values(field.kind, wire.wire_type, wire.value)),
+            }
+        }
+        if !merged.is_empty() {
+            values.extend(render_values(field.kind, WireType::Len, &merged));
+        }
+
+        // Last occurrence wins for singular scalar fields
+        if !field.repeated && values.len() > 1 {
+            values.drain(..values.len() - 1);
+        }
+
+        if !values.is_empty() {
+            result.push((field, values));
+        }
+    }
+
+    result
+}
+
+/// Renders the value(s) carried by a single wire field
+fn render_values(kind: Kind, wire_type: WireType, value: &[u8]) -> Vec<String> {
+    match (kind, wire_type) {
+        (Kind::String, WireType::Len) => vec![format!(
+            "\"{}\"",
+            escape_string(&String::from_utf8_lossy(value))
+        )],
+        (Kind::Message(table), WireType::Len) => vec![aggregate(table, value)],
+        (Kind::Bool | Kind::Int32 | Kind::Enum(_), WireType::Varint) => decode_varint(value)
+            .map(|(v, _)| render_scalar(kind, v))
+            .into_iter()
+            .collect(),
+        // Packed encoding of a repeated scalar
+        (Kind::Bool | Kind::Int32 | Kind::Enum(_), WireType::Len) => {
+            let mut values = Vec::new();
+            let mut rest = value;
+            while let Ok((v, len)) = decode_varint(rest) {
+                values.push(render_scalar(kind, v));
+                rest = &rest[len..];
+            }
+            values
+        }
+        _ => Vec::new(),
+    }
+}
+
+fn render_scalar(kind: Kind, value: u64) -> String {
+    match kind {
+        Kind::Bool => (value != 0).to_string(),
+        Kind::Enum(names) => {
+            let value = value as i32;
+            names
+                .iter()
+                .find(|(v, _)| *v == value)
+                .map(|(_, name)| name.to_string())
+                .unwrap_or_else(|| value.to_string())
+        }
+        _ => (value as i32).to_string(),
+    }
+}
+
+/// Renders a message value as a text-format aggregate: `{ a: 1, b: "x" }`
+fn aggregate(table: &[OptionField], data: &[u8]) -> String {
+    let parts: Vec<String> = collect(table, data)
+        .into_iter()
+        .flat_map(|(field, values)| {
+            values
+                .into_iter()
+                .map(move |value| format!("{}: {}", field.name, value))
+        })
+        .collect();
+
+    if parts.is_empty() {
+        "{}".to_string()
+    } else {
+        format!("{{ {} }}", parts.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::raw::tests::{len_field, varint_field};
+
+    #[test]
+    fn test_field_options() {
+        let options = [
+            varint_field(3, 1),
+            varint_field(1, 1),
+            varint_field(17, 2),
+            varint_field(19, 4),
+            varint_field(19, 5),
+            varint_field(16, 1),
+            varint_field(21 /* features */, 0),
+        ]
+        .concat();
+
+        assert_eq!(
+            standard_options(Element::Field, Some(&options)),
+            vec![
+                "ctype = CORD",
+                "deprecated = true",
+                "debug_redact = true",
+                "retention = RETENTION_SOURCE",
+                "targets = TARGET_TYPE_FIELD",
+                "targets = TARGET_TYPE_ONEOF",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_last_value_wins() {
+        let options = [varint_field(9, 1), varint_field(9, 3)].concat();
+        assert_eq!(
+            standard_options(Element::File, Some(&options)),
+            vec!["optimize_for = LITE_RUNTIME"]
+        );
+    }
+
+    #[test]
+    fn test_aggregate_option() {
+        let declaration = [
+            varint_field(1, 100),
+            len_field(2, b".pkg.ext"),
+            len_field(3, b".pkg.Type"),
+        ]
+        .concat();
+        let options = [len_field(2, &declaration), varint_field(3, 1)].concat();
+
+        assert_eq!(
+            standard_options(Element::ExtensionRange, Some(&options)),
+            vec![
+                "declaration = { number: 100, full_name: \".pkg.ext\", type: \".pkg.Type\" }",
+                "verification = UNVERIFIED",
+            ]
+        );
+    }
+}
@@ -0,0 +1,175 @@
+//! Raw descriptor data that `prost_types` does not retain.
+//!
+//! `prost_types` decodes `FileDescriptorProto` against an older copy of
+//! `descriptor.proto` and silently drops unknown fields. That loses the
+//! `edition` field, `features` and anything else added to the `*Options`
+//! messages since. This module walks the original bytes with the
+//! [`crate::scanner`] wire primitives and keeps the raw options of every
+//! element, keyed by its `SourceCodeInfo`-style path.
+
+use crate::scanner::{fields, WireType};
+use std::collections::HashMap;
+
+/// `FileDescriptorProto.edition`
+const FILE_EDITION: u32 = 14;
+
+/// Shape of a descriptor message: which fields hold child elements, and
+/// which field holds the element's options.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Element {
+    File,
+    Message,
+    Field,
+    Oneof,
+    Enum,
+    EnumValue,
+    Service,
+    Method,
+    ExtensionRange,
+}
+
+impl Element {
+    /// Field number of the `options` field
+    fn options_field(self) -> u32 {
+        match self {
+            Element::File => 8,
+            Element::Message => 7,
+            Element::Field => 8,
+            Element::Oneof => 2,
+            Element::Enum => 3,
+            Element::EnumValue => 3,
+            Element::Service => 3,
+            Element::Method => 4,
+            Element::ExtensionRange => 3,
+        }
+    }
+
+    /// Element kind stored in the given repeated field, if any
+    fn child(self, number: u32) -> Option<Element> {
+        match (self, number) {
+            (Element::File, 4) => Some(Element::Message),
+            (Element::File, 5) => Some(Element::Enum),
+            (Element::File, 6) => Some(Element::Service),
+            (Element::File, 7) => Some(Element::Field),
+            (Element::Message, 2) => Some(Element::Field),
+            (Element::Message, 3) => Some(Element::Message),
+            (Element::Message, 4) => Some(Element::Enum),
+            (Element::Message, 5) => Some(Element::ExtensionRange),
+            (Element::Message, 6) => Some(Element::Field),
+            (Element::Message, 8) => Some(Element::Oneof),
+            (Element::Enum, 2) => Some(Element::EnumValue),
+            (Element::Service, 2) => Some(Element::Method),
+            _ => None,
+        }
+    }
+}
+
+/// Descriptor data recovered directly from the wire bytes
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RawDescriptor {
+    /// The `edition` field of the file, if present
+    pub(crate) edition: Option<i32>,
+    /// Raw `*Options` bytes keyed by element path (empty path = file)
+    options: HashMap<Vec<i32>, Vec<u8>>,
+}
+
+impl RawDescriptor {
+    /// Walks a serialized `FileDescriptorProto`
+    pub(crate) fn parse(data: &[u8]) -> Self {
+        let mut raw = Self::default();
+
+        for field in fields(data) {
+            if field.number == FILE_EDITION {
+                raw.edition = field.as_varint().map(|v| v as i32);
+            }
+        }
+
+        let mut path = Vec::new();
+        raw.walk(data, Element::File, &mut path);
+        raw
+    }
+
+    /// Returns the raw options bytes of the element at `path`
+    pub(crate) fn options(&self, path: &[i32]) -> Option<&[u8]> {
+        self.options.get(path).map(Vec::as_slice)
+    }
+
+    fn walk(&mut self, data: &[u8], element: Element, path: &mut Vec<i32>) {
+        let mut counts: HashMap<u32, i32> = HashMap::new();
+
+        for field in fields(data) {
+            if field.wire_type != WireType::Len {
+                continue;
+            }
+
+            if field.number == element.options_field() {
+                // Repeated occurrences of a message field merge, which for
+                // wire bytes is concatenation
+                self.options
+                    .entry(path.clone())
+                    .or_default()
+                    .extend_from_slice(field.value);
+            } else if let Some(child) = element.child(field.number) {
+                let index = counts.entry(field.number).or_insert(0);
+                path.push(field.number as i32);
+                path.push(*index);
+                self.walk(field.value, child, path);
+                path.truncate(path.len() - 2);
+                *index += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Encodes a length-delimited field
+    pub(crate) fn len_field(number: u32, payload: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        prost::encoding::encode_key(number, prost::encoding::WireType::LengthDelimited, &mut out);
+        prost::encoding::encode_varint(payload.len() as u64, &mut out);
+        out.extend_from_slice(payload);
+        out
+    }
+
+    /// Encodes a varint field
+    pub(crate) fn varint_field(number: u32, value: u64) -> Vec<u8> {
+        let mut out = Vec::new();
+        prost::encoding::encode_key(number, prost::encoding::WireType::Varint, &mut out);
+        prost::encoding::encode_varint(value, &mut out);
+        out
+    }
+
+    #[test]
+    fn test_nested_options_paths() {
+        // message_type[1].field[0].options = { packed: true }
+        let field = len_field(8, &varint_field(2, 1));
+        let message = [len_field(1, b"B"), len_field(2, &field)].concat();
+        let data = [
+            len_field(1, b"a.proto"),
+            len_field(4, &len_field(1, b"A")),
+            len_field(4, &message),
+            varint_field(14, 1000),
+        ]
+        .concat();
+
+        let raw = RawDescriptor::parse(&data);
+        assert_eq!(raw.edition, Some(1000));
+        assert_eq!(raw.options(&[4, 1, 2, 0]), Some(&[0x10, 0x01][..]));
+        assert_eq!(raw.options(&[4, 0, 2, 0]), None);
+    }
+
+    #[test]
+    fn test_repeated_options_are_merged() {
+        let data = [
+            len_field(8, &varint_field(23, 1)),
+            len_field(8, &varint_field(16, 1)),
+        ]
+        .concat();
+
+        let raw = RawDescriptor::parse(&data);
+        assert_eq!(raw.options(&[]).map(<[u8]>::len), Some(6));
+    }
+}
@@ -0,0 +1,341 @@
+//! Recovery of descriptors that don't decode.
+//!
+//! A descriptor with one overwritten byte, or one bad sub-message, fails
+//! `FileDescriptorProto::decode` as a whole. [`salvage`] walks the top-level
+//! fields with the [`crate::scanner`] wire primitives instead and decodes
+//! each one on its own. Fields that fail are repaired where possible
+//! (invalid UTF-8 in strings, malformed bytes at the end of a sub-message),
+//! and dropped otherwise. A field whose length runs past the end of the
+//! input, as the last one of a descriptor cut short does, is repaired from
+//! the bytes that are there. The result is a descriptor of the fields that
+//! survived, plus a [`SalvageReport`] saying what was lost.
+
+use crate::error::{Error, Result};
+use crate::scanner::{decode_varint, fields, read_field, WireField, WireType};
+use prost::Message;
+use prost_reflect::{DescriptorPool, Kind, MessageDescriptor};
+use prost_types::FileDescriptorProto;
+use std::collections::HashMap;
+use std::fmt;
+
+/// What salvaging a descriptor left out or changed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SalvageReport {
+    /// Top-level elements that couldn't be recovered, with the reason
+    pub dropped: Vec<String>,
+    /// Top-level elements kept after a repair, with what was repaired
+    pub repaired: Vec<String>,
+}
+
+impl SalvageReport {
+    /// Returns true if nothing was dropped or repaired
+    pub fn is_empty(&self) -> bool {
+        self.dropped.is_empty() && self.repaired.is_empty()
+    }
+}
+
+impl fmt::Display for SalvageReport {
+    /// Writes the comment that opens a salvaged file
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "// zarja: partially recovered descriptor")?;
+        for dropped in &self.dropped {
+            writeln!(f, "// dropped {}", dropped)?;
+        }
+        for repaired in &self.repaired {
+            writeln!(f, "// repaired {}", repaired)?;
+        }
+        Ok(())
+    }
+}
+
+/// Recovers the decodable top-level fields of a serialized
+/// `FileDescriptorProto`
+///
+/// Returns the bytes of the fields that were kept, which always decode,
+/// and a report of the rest. Fails if the file name isn't among them, or
+/// nothing else is.
+pub(super) fn salvage(data: &[u8]) -> Result<(Vec<u8>, SalvageReport)> {
+    let file = DescriptorPool::global()
+        .get_message_by_name("google.protobuf.FileDescriptorProto")
+        .ok_or_else(|| Error::internal("descriptor.proto missing from global pool"))?;
+
+    let mut kept = Vec::new();
+    let mut report = SalvageReport::default();
+    let mut counts: HashMap<u32, usize> = HashMap::new();
+    let mut has_name = false;
+    let mut has_content = false;
+
+    let mut position = 0;
+    while position < data.len() {
+        // A length running past the end usually means the descriptor was
+        // cut short; what is there is salvaged like any other value
+        let (field, declared) = match read_field(&data[position..]) {
+            Ok(field) => (field, None),
+            Err(e) => match truncated_field(&data[position..]) {
+                Some((field, declared)) => (field, Some(declared)),
+                None => {
+                    report
+                        .dropped
+                        .push(format!("bytes {}..{}: {}", position, data.len(), e));
+                    break;
+                }
+            },
+        };
+        let raw = &data[position..position + field.len];
+        position += field.len;
+
+        let index = counts.entry(field.number).or_default();
+        let label = label(&file, field.number, *index, field.value);
+        *index += 1;
+
+        let error = match FileDescriptorProto::decode(raw) {
+            Ok(_) => {
+                kept.extend_from_slice(raw);
+                has_name |= field.number == 1;
+                has_content |= field.number != 1;
+                continue;
+            }
+            Err(e) => e,
+        };
+
+        let kind = file.get_field(field.number).map(|f| f.kind());
+        if let (WireType::Len, Some(kind)) = (field.wire_type, kind) {
+            let mut notes = Vec::new();
+            if let Some(declared) = declared {
+                notes.push(cut_short(&label, field.value.len(), declared));
+            }
+            let value = repair(field.value, &kind, &label, &mut notes);
+            let mut encoded = Vec::new();
+            prost::encoding::bytes::encode(field.number, &value, &mut encoded);
+            if !notes.is_empty()
+                && (declared.is_none() || !value.is_empty())
+                && FileDescriptorProto::decode(encoded.as_slice()).is_ok()
+            {
+                kept.extend(encoded);
+                report.repaired.push(notes.join(", "));
+                has_name |= field.number == 1;
+                has_content |= field.number != 1;
+                continue;
+            }
+        }
+        match declared {
+            Some(declared) => report
+                .dropped
+                .push(cut_short(&label, field.value.len(), declared)),
+            None => report.dropped.push(format!("{}: {}", label, error)),
+        }
+    }
+
+    if !has_name || !has_content {
+        return Err(Error::invalid_wire_format(
+            0,
+            "nothing recoverable besides the file name",
+        ));
+    }
+    Ok((kept, report))
+}
+
+/// Reads a length-delimited field whose length runs past the end of `data`
+///
+/// Returns the field, with the bytes that are there as its value, and the
+/// length its prefix declares.
+fn truncated_field(data: &[u8]) -> Option<(WireField<'_>, usize)> {
+    let (tag, tag_len) = decode_varint(data).ok()?;
+    let number = (tag >> 3) as u32;
+    if tag & 0x07 != WireType::Len as u64 || number == 0 {
+        return None;
+    }
+    let (declared, length_len) = decode_varint(&data[tag_len..]).ok()?;
+    let value = &data[tag_len + length_len..];
+    if declared <= value.len() as u64 {
+        return None;
+    }
+
+    let field = WireField {
+        number,
+        wire_type: WireType::Len,
+        value,
+        len: data.len(),
+    };
+    Some((field, declared as usize))
+}
+
+fn cut_short(label: &str, kept: usize, declared: usize) -> String {
+    format!("{}: cut short after {} of {} bytes", label, kept, declared)
+}
+
+/// Names the `index`th occurrence of a top-level field, with the element's
+/// own name when it has one: `message_type[2] "Order"`
+fn label(file: &MessageDescriptor, number: u32, index: usize, value: &[u8]) -> String {
+    let Some(field) = file.get_field(number) else {
+        return format!("field {}", number);
+    };
+    let mut label = field.name().to_string();
+    if field.is_list() {
+        label.push_str(&format!("[{}]", index));
+    }
+    if let Kind::Message(_) = field.kind() {
+        let name = fields(value)
+            .find(|f| f.number == 1 && f.wire_type == WireType::Len)
+            .map(|f| String::from_utf8_lossy(f.value).into_owned());
+        if let Some(name) = name {
+            label.push_str(&format!(" {:?}", name));
+        }
+    }
+    label
+}
+
+/// Rewrites a length-delimited value of the given kind so that it decodes
+///
+/// Strings with invalid UTF-8 are converted lossily, and malformed bytes
+/// that end a message are cut off. Each change is noted, with `path`
+/// naming the value.
+fn repair(value: &[u8], kind: &Kind, path: &str, notes: &mut Vec<String>) -> Vec<u8> {
+    match kind {
+        Kind::String if std::str::from_utf8(value).is_err() => {
+            notes.push(format!("invalid UTF-8 in {}", path));
+            String::from_utf8_lossy(value).into_owned().into_bytes()
+        }
+        Kind::Message(message) => {
+            let mut out = Vec::with_capacity(value.len());
+            let mut position = 0;
+            while position < value.len() {
+                let Ok(field) = read_field(&value[position..]) else {
+                    notes.push(format!(
+                        "{} malformed bytes at the end of {}",
+                        value.len() - position,
+                        path
+                    ));
+                    break;
+                };
+                let raw = &value[position..position + field.len];
+                position += field.len;
+
+                let inner = message.get_field(field.number);
+                match (field.wire_type, inner) {
+                    (WireType::Len, Some(inner)) => {
+                        let path = format!("{}.{}", path, inner.name());
+                        let repaired = repair(field.value, &inner.kind(), &path, notes);
+                        prost::encoding::bytes::encode(field.number, &repaired, &mut out);
+                    }
+                    _ => out.extend_from_slice(raw),
+                }
+            }
+            out
+        }
+        _ => value.to_vec(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost_types::{DescriptorProto, EnumDescriptorProto, FieldDescriptorProto};
+
+    fn encode(number: u32, value: &[u8], buf: &mut Vec<u8>) {
+        prost::encoding::bytes::encode(number, &value.to_vec(), buf);
+    }
+
+    fn message(name: &str) -> Vec<u8> {
+        DescriptorProto {
+            name: Some(name.to_string()),
+            ..Default::default()
+        }
+        .encode_to_vec()
+    }
+
+    #[test]
+    fn test_salvage() {
+        let mut data = FileDescriptorProto {
+            name: Some("broken.proto".to_string()),
+            package: Some("test".to_string()),
+            ..Default::default()
+        }
+        .encode_to_vec();
+        encode(4, &message("Good"), &mut data);
+        // A field whose length runs past the end of the message
+        encode(4, &[message("Bad"), vec![0x12, 0x7f]].concat(), &mut data);
+        // Invalid UTF-8 in a nested name
+        let mut latin1 = Vec::new();
+        encode(1, b"Na\xe9me", &mut latin1);
+        encode(4, &latin1, &mut data);
+        // An enum whose values field has the wrong wire type
+        encode(5, &[0x0a, 0x01, b'E', 0x10, 0x01], &mut data);
+        // Cut short
+        data.extend([0x2a, 0x40]);
+        assert!(FileDescriptorProto::decode(data.as_slice()).is_err());
+
+        let (kept, report) = salvage(&data).unwrap();
+        let proto = FileDescriptorProto::decode(kept.as_slice()).unwrap();
+        let names: Vec<&str> = proto.message_type.iter().map(|m| m.name()).collect();
+        assert_eq!(names, ["Good", "Bad", "Na\u{fffd}me"]);
+        assert!(proto.enum_type.is_empty());
+
+        assert_eq!(report.repaired.len(), 2);
+        assert!(report.repaired[0].contains("2 malformed bytes at the end of message_type[1]"));
+        assert!(report.repaired[1].contains("invalid UTF-8 in message_type[2]"));
+        assert_eq!(report.dropped.len(), 2);
+        assert!(report.dropped[0].starts_with("enum_type[0] \"E\": "));
+        assert_eq!(
+            report.dropped[1],
+            "enum_type[1]: cut short after 0 of 64 bytes"
+        );
+    }
+
+    #[test]
+    fn test_salvage_truncated() {
+        let field = |name: &str, number| FieldDescriptorProto {
+            name: Some(name.to_string()),
+            number: Some(number),
+            ..Default::default()
+        };
+        let data = FileDescriptorProto {
+            name: Some("cut.proto".to_string()),
+            message_type: vec![
+                DescriptorProto {
+                    name: Some("Good".to_string()),
+                    ..Default::default()
+                },
+                DescriptorProto {
+                    name: Some("Order".to_string()),
+                    field: vec![field("id", 1), field("total", 2), field("customer", 3)],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        }
+        .encode_to_vec();
+        // Cut in the middle of the last field's name
+        let data = &data[..data.len() - 4];
+        assert!(FileDescriptorProto::decode(data).is_err());
+
+        let (kept, report) = salvage(data).unwrap();
+        let proto = FileDescriptorProto::decode(kept.as_slice()).unwrap();
+        let names: Vec<&str> = proto.message_type.iter().map(|m| m.name()).collect();
+        assert_eq!(names, ["Good", "Order"]);
+        let order = &proto.message_type[1];
+        let fields: Vec<&str> = order.field.iter().map(|f| f.name()).collect();
+        assert_eq!(fields, ["id", "total"]);
+
+        assert!(report.dropped.is_empty());
+        assert_eq!(report.repaired.len(), 1);
+        assert!(report.repaired[0].starts_with("message_type[1] \"Order\": cut short after "));
+        assert!(report.repaired[0].contains("malformed bytes at the end of message_type[1]"));
+    }
+
+    #[test]
+    fn test_nothing_to_salvage() {
+        let mut data = Vec::new();
+        encode(1, b"only.proto", &mut data);
+        encode(5, &[0x10, 0x01], &mut data);
+        assert!(salvage(&data).is_err());
+
+        let mut data = Vec::new();
+        encode(
+            5,
+            &EnumDescriptorProto::default().encode_to_vec(),
+            &mut data,
+        );
+        assert!(salvage(&data).is_err());
+    }
+}
@@ -0,0 +1,374 @@
+//! Multi-file reconstruction.
+//!
+//! A descriptor on its own rarely resolves: almost every file imports
+//! another. [`ReconstructionSession`] gathers all descriptors recovered in
+//! one run, adds them to a single [`DescriptorPool`] in dependency order and
+//! hands out reconstructors whose file descriptor is resolved against that
+//! pool. Custom options are resolved against the extensions defined by
+//! any file in the session.
+
+use super::{
+    bundled, salvage, stubs, ExtensionRegistry, ProtoReconstructor, ReconstructorConfig,
+    SalvageReport, Verifier,
+};
+use crate::error::Result;
+use prost::Message;
+use prost_reflect::DescriptorPool;
+use prost_types::FileDescriptorProto;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+#[cfg(feature = "extended-formats")]
+use super::DescriptorSet;
+
+/// A set of descriptors reconstructed together
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use zarja_core::{ReconstructionSession, ScanStrategy, Scanner};
+///
+/// let data = std::fs::read("./binary")?;
+/// let scanner = Scanner::new();
+/// let mut session = ReconstructionSession::new();
+/// for result in scanner.scan(&data)? {
+///     session.add(result.as_bytes())?;
+/// }
+///
+/// for reconstructor in session.reconstructors() {
+///     println!("{}", reconstructor.reconstruct());
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Default)]
+pub struct ReconstructionSession {
+    /// Raw descriptor bytes, in the order they were added
+    files: Vec<Vec<u8>>,
+    /// The decoded `files`
+    protos: Vec<FileDescriptorProto>,
+    /// Salvage reports of partial files, by index in `files`
+    salvaged: HashMap<usize, SalvageReport>,
+    /// Configuration passed on to every reconstructor
+    config: ReconstructorConfig,
+}
+
+impl ReconstructionSession {
+    /// Creates an empty session
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the configuration used for every reconstructor
+    pub fn with_config(mut self, config: ReconstructorConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Adds a serialized `FileDescriptorProto`
+    ///
+    /// Returns an error, and adds nothing, if the bytes don't decode.
+    pub fn add(&mut self, data: &[u8]) -> Result<()> {
+        let proto = FileDescriptorProto::decode(data)?;
+        self.files.push(data.to_vec());
+        self.protos.push(proto);
+        Ok(())
+    }
+
+    /// Adds what can be recovered from a serialized `FileDescriptorProto`
+    /// that doesn't decode
+    ///
+    /// See [`ProtoReconstructor::salvage`]; the file's reconstructor will
+    /// be marked as partial. Returns an error, and adds nothing, if nothing
+    /// but the file name could be recovered.
+    pub fn add_salvaged(&mut self, data: &[u8]) -> Result<SalvageReport> {
+        let (data, report) = salvage::salvage(data)?;
+        let proto = FileDescriptorProto::decode(data.as_slice())?;
+        if !report.is_empty() {
+            self.salvaged.insert(self.files.len(), report.clone());
+        }
+        self.files.push(data);
+        self.protos.push(proto);
+        Ok(report)
+    }
+
+    /// Returns the number of descriptors added
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Returns true if no descriptors were added
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Builds a pool of every file that resolves
+    ///
+    /// Only the first file of each name is used. Imports that no recovered
+    /// file provides fall back to the bundled option definitions (which
+    /// include `google/protobuf/descriptor.proto`). Files that still don't
+    /// resolve, for example because an import is missing, are left out.
+    pub fn pool(&self) -> DescriptorPool {
+        pool_with_bundled(self.protos.iter().cloned())
+    }
+
+    /// Creates reconstructors for stub files standing in for imports that
+    /// no file in the session provides
+    ///
+    /// Each stub declares the messages and enums that session files
+    /// reference from it, without fields, and starts with a comment marking
+    /// it as synthesized.
+    pub fn stubs(&self) -> Vec<ProtoReconstructor> {
+        let mut files: Vec<FileDescriptorProto> = Vec::new();
+        let mut names = HashSet::new();
+        for proto in &self.protos {
+            if names.insert(proto.name().to_string()) {
+                files.push(proto.clone());
+            }
+        }
+
+        // The marker comment is part of the stub
+        let config = self.config.clone().include_comments(true);
+        stubs::stubs(&files)
+            .into_iter()
+            .filter_map(|stub| ProtoReconstructor::from_proto(stub).ok())
+            .map(|stub| stub.with_config(config.clone()))
+            .collect()
+    }
+
+    /// Creates a verifier that resolves imports against every file in the
+    /// session
+    pub fn verifier(&self) -> Verifier {
+        let mut verifier = Verifier::new();
+        for proto in &self.protos {
+            verifier.add_file(proto.clone());
+        }
+        verifier
+    }
+
+    /// Collects the session's files into a [`DescriptorSet`]
+    ///
+    /// Only the first file of each name is kept.
+    #[cfg(feature = "extended-formats")]
+    pub fn descriptor_set(&self) -> DescriptorSet {
+        let mut set = DescriptorSet::new();
+        for data in &self.files {
+            set.add_bytes(data);
+        }
+        set
+    }
+
+    /// Creates a reconstructor for every added descriptor, in order
+    ///
+    /// The `n`th reconstructor belongs to the `n`th added descriptor.
+    ///
+    /// Each reconstructor gets a file descriptor from the shared pool when its
+    /// file resolved there. Files that didn't, and later copies of a name
+    /// whose content differs from the pooled one, are resolved on their own
+    /// as [`ProtoReconstructor::from_bytes`] would.
+    pub fn reconstructors(&self) -> Vec<ProtoReconstructor> {
+        let pool = self.pool();
+
+        // Consulted before the bundled option definitions
+        let mut extensions = ExtensionRegistry::new();
+        // The pool holds the first file of each name
+        let mut pooled: HashMap<&str, &FileDescriptorProto> = HashMap::new();
+        for proto in &self.protos {
+            extensions.add_file(proto);
+            pooled.entry(proto.name()).or_insert(proto);
+        }
+        let extensions = Arc::new(extensions);
+
+        self.files
+            .iter()
+            .zip(&self.protos)
+            .enumerate()
+            .map(|(index, (data, proto))| {
+                let descriptor = pool
+                    .get_file_by_name(proto.name())
+                    .filter(|_| pooled.get(proto.name()) == Some(&proto));
+                ProtoReconstructor::from_parts(proto.clone(), data.clone(), descriptor)
+                    .with_config(self.config.clone())
+                    .with_session_extensions(extensions.clone())
+                    .with_salvage(self.salvaged.get(&index).cloned())
+            })
+            .collect()
+    }
+}
+
+/// Builds a pool of `files` and the bundled files, keeping the first file of
+/// each name
+pub(super) fn pool_with_bundled(
+    files: impl IntoIterator<Item = FileDescriptorProto>,
+) -> DescriptorPool {
+    let mut names = HashSet::new();
+    let candidates: Vec<FileDescriptorProto> = files
+        .into_iter()
+        .chain(bundled::files())
+        .filter(|proto| names.insert(proto.name().to_string()))
+        .collect();
+
+    build_pool(candidates)
+}
+
+/// Adds files to a new pool, each after its dependencies
+///
+/// A file is attempted once all of its dependencies are in the pool; files
+/// that fail to build, and files depending on them, are skipped.
+fn build_pool(candidates: Vec<FileDescriptorProto>) -> DescriptorPool {
+    let mut pool = DescriptorPool::new();
+    let mut added: HashSet<String> = HashSet::new();
+    let mut pending: HashMap<String, FileDescriptorProto> = candidates
+        .into_iter()
+        // prost-reflect only understands proto2 and proto3
+        .filter(|proto| matches!(proto.syntax(), "" | "proto2" | "proto3"))
+        .map(|proto| (proto.name().to_string(), proto))
+        .collect();
+
+    loop {
+        let mut ready: Vec<String> = pending
+            .values()
+            .filter(|proto| proto.dependency.iter().all(|dep| added.contains(dep)))
+            .map(|proto| proto.name().to_string())
+            .collect();
+        if ready.is_empty() {
+            break;
+        }
+        // Keep the build order independent of hash order
+        ready.sort();
+
+        for name in ready {
+            let Some(proto) = pending.remove(&name) else {
+                continue;
+            };
+            if pool.add_file_descriptor_proto(proto).is_ok() {
+                added.insert(name);
+            }
+        }
+    }
+
+    pool
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost_types::field_descriptor_proto::{Label, Type};
+    use prost_types::{DescriptorProto, FieldDescriptorProto};
+
+    fn file(name: &str, deps: &[&str], message: DescriptorProto) -> Vec<u8> {
+        FileDescriptorProto {
+            name: Some(name.to_string()),
+            package: Some("test".to_string()),
+            dependency: deps.iter().map(|d| d.to_string()).collect(),
+            message_type: vec![message],
+            syntax: Some("proto3".to_string()),
+            ..Default::default()
+        }
+        .encode_to_vec()
+    }
+
+    fn message(name: &str, field: Option<(&str, &str)>) -> DescriptorProto {
+        DescriptorProto {
+            name: Some(name.to_string()),
+            field: field
+                .map(|(field, type_name)| FieldDescriptorProto {
+                    name: Some(field.to_string()),
+                    number: Some(1),
+                    label: Some(Label::Optional as i32),
+                    r#type: Some(Type::Message as i32),
+                    type_name: Some(type_name.to_string()),
+                    ..Default::default()
+                })
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_imports_resolve_across_files() {
+        let mut session = ReconstructionSession::new();
+        // Added before its dependency
+        session
+            .add(&file(
+                "a.proto",
+                &["b.proto"],
+                message("A", Some(("b", ".test.B"))),
+            ))
+            .unwrap();
+        session
+            .add(&file("b.proto", &[], message("B", None)))
+            .unwrap();
+        session
+            .add(&file("c.proto", &["missing.proto"], message("C", None)))
+            .unwrap();
+
+        let reconstructors = session.reconstructors();
+        assert_eq!(reconstructors.len(), 3);
+        assert!(reconstructors[0].file_descriptor().is_some());
+        assert!(reconstructors[1].file_descriptor().is_some());
+        assert!(reconstructors[2].file_descriptor().is_none());
+        assert!(reconstructors[0].build_error().is_none());
+        assert!(reconstructors[2].build_error().is_some());
+
+        // Standalone resolution fails on the import
+        assert!(ProtoReconstructor::from_bytes(&session.files[0])
+            .unwrap()
+            .file_descriptor()
+            .is_none());
+    }
+
+    #[test]
+    fn test_add_salvaged() {
+        let mut data = file("partial.proto", &[], message("Kept", None));
+        // A message whose name isn't a string
+        data.extend([0x22, 0x02, 0x08, 0x01]);
+        let mut session = ReconstructionSession::new();
+        assert!(session.add(&data).is_err());
+
+        let report = session.add_salvaged(&data).unwrap();
+        assert_eq!(report.dropped.len(), 1);
+        session
+            .add(&file("b.proto", &[], message("B", None)))
+            .unwrap();
+        let reconstructors = session.reconstructors();
+        assert_eq!(reconstructors.len(), session.len());
+        assert_eq!(reconstructors[1].proto().name(), "b.proto");
+        assert_eq!(reconstructors[1].salvage_report(), None);
+        let partial = &reconstructors[0];
+        assert_eq!(partial.proto().message_type.len(), 1);
+        assert_eq!(partial.salvage_report(), Some(&report));
+        let source = partial.reconstruct();
+        assert!(source
+            .starts_with("// zarja: partially recovered descriptor\n// dropped message_type[1]"));
+        assert!(source.contains("message Kept {"));
+    }
+
+    #[test]
+    fn test_bundled_imports_and_conflicts() {
+        let mut session = ReconstructionSession::new();
+        session
+            .add(&file(
+                "a.proto",
+                &["google/protobuf/descriptor.proto"],
+                message("A", Some(("d", ".google.protobuf.FileDescriptorProto"))),
+            ))
+            .unwrap();
+        // Same name, different content
+        session
+            .add(&file("a.proto", &[], message("Other", None)))
+            .unwrap();
+        assert!(session.add(b"\xff\xff").is_err());
+
+        let reconstructors = session.reconstructors();
+        assert_eq!(reconstructors.len(), 2);
+        let pooled = reconstructors[0].file_descriptor().unwrap();
+        assert!(pooled.parent_pool().get_message_by_name("test.A").is_some());
+        let other = reconstructors[1].file_descriptor().unwrap();
+        assert!(other
+            .parent_pool()
+            .get_message_by_name("test.Other")
+            .is_some());
+        assert!(other.parent_pool().get_message_by_name("test.A").is_none());
+    }
+}
@@ -0,0 +1,104 @@
+//! `SourceCodeInfo` lookups.
+//!
+//! Descriptors compiled with source info record where each element was
+//! declared and the comments around it. Most embedded descriptors have it
+//! stripped, so every lookup is optional.
+
+use prost_types::FileDescriptorProto;
+use std::collections::HashMap;
+
+/// Comments attached to one element, as protoc recorded them
+///
+/// Each comment keeps the text between the comment markers, so a line
+/// comment `// Foo` is stored as `" Foo\n"`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct Comments {
+    /// Comment directly above the element
+    pub(crate) leading: Option<String>,
+    /// Comment after the element (or after a block's opening brace)
+    pub(crate) trailing: Option<String>,
+    /// Comments above the element separated from it by blank lines
+    pub(crate) detached: Vec<String>,
+}
+
+impl Comments {
+    /// Splits comment text into the lines to write after `//`
+    pub(crate) fn lines(text: &str) -> Vec<&str> {
+        let text = text.strip_suffix('\n').unwrap_or(text);
+        text.split('\n').map(str::trim_end).collect()
+    }
+}
+
+/// Start and comments of a location covering a whole block
+pub(crate) type Block = ((i32, i32), Comments);
+
+/// Declaration positions and comments of a file's elements
+#[derive(Debug, Default)]
+pub(crate) struct SourceInfo {
+    /// `(line, column)` of each path's first location
+    starts: HashMap<Vec<i32>, (i32, i32)>,
+    /// Comments of each path's first location that has any
+    comments: HashMap<Vec<i32>, Comments>,
+    /// Starts and comments of every location of a repeated field as a
+    /// whole, which protoc records once per `extend` block
+    blocks: HashMap<Vec<i32>, Vec<Block>>,
+}
+
+impl SourceInfo {
+    pub(crate) fn new(proto: &FileDescriptorProto) -> Self {
+        let mut info = Self::default();
+        let Some(source) = &proto.source_code_info else {
+            return info;
+        };
+
+        for location in &source.location {
+            if let [line, column, ..] = location.span[..] {
+                info.starts
+                    .entry(location.path.clone())
+                    .or_insert((line, column));
+            }
+
+            let comments = Comments {
+                leading: location.leading_comments.clone(),
+                trailing: location.trailing_comments.clone(),
+                detached: location.leading_detached_comments.clone(),
+            };
+            if let ([line, column, ..], 1) = (&location.span[..], location.path.len() % 2) {
+                info.blocks
+                    .entry(location.path.clone())
+                    .or_default()
+                    .push(((*line, *column), comments.clone()));
+            }
+            if comments != Comments::default() {
+                info.comments
+                    .entry(location.path.clone())
+                    .or_insert(comments);
+            }
+        }
+        info
+    }
+
+    /// Returns where the element at `path` starts
+    pub(crate) fn start(&self, path: &[i32]) -> Option<(i32, i32)> {
+        self.starts.get(path).copied()
+    }
+
+    /// Returns the comments of the element at `path`
+    pub(crate) fn comments(&self, path: &[i32]) -> Option<&Comments> {
+        self.comments.get(path)
+    }
+
+    /// Returns the start and comments of the block at `path` that contains
+    /// `start`
+    ///
+    /// `path` names a repeated field, such as `[7]` for a file's
+    /// extensions; of its locations, the last one starting before `start`
+    /// is the enclosing block.
+    pub(crate) fn block(&self, path: &[i32], start: (i32, i32)) -> Option<&Block> {
+        self.blocks
+            .get(path)?
+            .iter()
+            .rev()
+            .find(|(block, _)| *block <= start)
+    }
+}
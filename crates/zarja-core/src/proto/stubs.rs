@@ -0,0 +1,549 @@
+//! Stub files for imports that weren't recovered.
+//!
+//! Binaries often embed only part of a dependency graph. For each import
+//! that no recovered file provides, a stub declares the messages and enums
+//! that dependent files reference, without their fields, so the recovered
+//! tree compiles. Which of a file's missing imports defines a referenced
+//! type is unknown; the import whose path best matches the type's package
+//! is assumed.
+//!
+//! Extended messages need a proto2 stub, and enums used from proto3 files
+//! a proto3 one. When one import needs both, the extended messages go into
+//! a proto2 companion stub that the import's stub imports publicly.
+
+use crate::MAX_FIELD_NUMBER;
+use prost_types::descriptor_proto::ExtensionRange;
+use prost_types::field_descriptor_proto::Type;
+use prost_types::source_code_info::Location;
+use prost_types::{
+    DescriptorProto, EnumDescriptorProto, EnumValueDescriptorProto, FieldDescriptorProto,
+    FileDescriptorProto, SourceCodeInfo,
+};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+/// How a missing type is used
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Usage {
+    Message,
+    /// A message that is extended, so it needs an extension range
+    Extendee,
+    Enum,
+}
+
+/// A type referenced by recovered files but defined by none of them
+#[derive(Debug)]
+struct Wanted {
+    usage: Usage,
+    /// Enum values named by field defaults
+    values: BTreeSet<String>,
+    /// Missing imports of each referencing file
+    candidates: Vec<BTreeSet<String>>,
+    /// Referenced from a proto3 file
+    proto3: bool,
+}
+
+/// A message or enum in a stub file, with its nested types
+#[derive(Debug, Default)]
+struct Node {
+    usage: Option<Usage>,
+    values: BTreeSet<String>,
+    proto3: bool,
+    children: BTreeMap<String, Node>,
+}
+
+/// Builds stub files for the imports of `files` that none of them provide
+///
+/// `google/protobuf/` imports are left alone, since protoc ships them.
+pub(crate) fn stubs(files: &[FileDescriptorProto]) -> Vec<FileDescriptorProto> {
+    let names: HashSet<&str> = files.iter().map(|f| f.name()).collect();
+    let mut defined = HashSet::new();
+    for file in files {
+        collect_defined(file, &mut defined);
+    }
+
+    let mut wanted: BTreeMap<String, Wanted> = BTreeMap::new();
+    for file in files {
+        let missing: BTreeSet<String> = file
+            .dependency
+            .iter()
+            .filter(|dep| !names.contains(dep.as_str()) && !dep.starts_with("google/protobuf/"))
+            .cloned()
+            .collect();
+        if missing.is_empty() {
+            continue;
+        }
+
+        let proto3 = file.syntax() == "proto3";
+        let mut references = Vec::new();
+        collect_references(file, &mut references);
+        let mut seen = HashSet::new();
+        for (type_name, usage, value) in references {
+            if defined.contains(type_name)
+                || !type_name.starts_with('.')
+                || type_name.starts_with(".google.protobuf.")
+            {
+                continue;
+            }
+            let entry = wanted
+                .entry(type_name.to_string())
+                .or_insert_with(|| Wanted {
+                    usage,
+                    values: BTreeSet::new(),
+                    candidates: Vec::new(),
+                    proto3: false,
+                });
+            entry.usage = entry.usage.max(usage);
+            entry.proto3 |= proto3;
+            entry.values.extend(value.map(str::to_string));
+            if seen.insert(type_name) {
+                entry.candidates.push(missing.clone());
+            }
+        }
+    }
+
+    // Place each type in one import, shared by every referencing file if
+    // possible
+    let mut placed: BTreeMap<String, Vec<(String, Wanted)>> = BTreeMap::new();
+    for (type_name, wanted) in wanted {
+        let mut candidates = wanted.candidates.iter();
+        let Some(first) = candidates.next() else {
+            continue;
+        };
+        let shared: BTreeSet<&String> = candidates.fold(first.iter().collect(), |shared, c| {
+            shared.into_iter().filter(|i| c.contains(*i)).collect()
+        });
+        let pool: BTreeSet<&String> = if shared.is_empty() {
+            wanted.candidates.iter().flatten().collect()
+        } else {
+            shared
+        };
+
+        let (package, _) = split_type_name(&type_name);
+        let import = pool
+            .into_iter()
+            .max_by_key(|import| (package_match(import, &package), std::cmp::Reverse(*import)))
+            .cloned();
+        if let Some(import) = import {
+            placed.entry(import).or_default().push((type_name, wanted));
+        }
+    }
+
+    placed
+        .into_iter()
+        .flat_map(|(import, types)| stub_files(import, types))
+        .collect()
+}
+
+/// Builds the stub file of one import, and its proto2 companion if it has
+/// one
+fn stub_files(name: String, types: Vec<(String, Wanted)>) -> Vec<FileDescriptorProto> {
+    // A file has one package; types of other packages can't be declared
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    for (type_name, _) in &types {
+        *counts.entry(split_type_name(type_name).0).or_default() += 1;
+    }
+    let package = counts
+        .iter()
+        .max_by_key(|(package, count)| (**count, std::cmp::Reverse(*package)))
+        .map(|(package, _)| package.clone())
+        .unwrap_or_default();
+
+    let mut root = Node::default();
+    let mut unplaced = Vec::new();
+    for (type_name, wanted) in types {
+        let (type_package, path) = split_type_name(&type_name);
+        if type_package != package {
+            unplaced.push(type_name);
+            continue;
+        }
+        let mut node = &mut root;
+        for part in path {
+            node = node.children.entry(part).or_default();
+        }
+        node.usage = Some(wanted.usage);
+        node.values = wanted.values;
+        node.proto3 = wanted.proto3;
+    }
+
+    // Extension ranges need proto2; enums used from proto3 files need proto3
+    if !root.has_extendee() {
+        return vec![stub_file(name, package, "proto3", root.children, unplaced)];
+    }
+    if !root.has_open_enum() {
+        return vec![stub_file(name, package, "proto2", root.children, unplaced)];
+    }
+
+    let (extended, rest): (BTreeMap<_, _>, BTreeMap<_, _>) = root
+        .children
+        .into_iter()
+        .partition(|(_, node)| node.has_extendee());
+    let companion_name = format!("{}_extended.proto", name.trim_end_matches(".proto"));
+    let companion = stub_file(
+        companion_name.clone(),
+        package.clone(),
+        "proto2",
+        extended,
+        Vec::new(),
+    );
+    let mut file = stub_file(name, package, "proto3", rest, unplaced);
+    file.dependency.push(companion_name);
+    file.public_dependency.push(0);
+    vec![file, companion]
+}
+
+/// Builds one stub file declaring `types`
+fn stub_file(
+    name: String,
+    package: String,
+    syntax: &str,
+    types: BTreeMap<String, Node>,
+    unplaced: Vec<String>,
+) -> FileDescriptorProto {
+    let mut file = FileDescriptorProto {
+        name: Some(name),
+        package: Some(package).filter(|p| !p.is_empty()),
+        syntax: Some(syntax.to_string()),
+        ..Default::default()
+    };
+    for (name, node) in types {
+        match node.usage {
+            Some(Usage::Enum) => file.enum_type.push(node.enum_type(name)),
+            _ => file.message_type.push(node.message(name)),
+        }
+    }
+
+    let mut header = " Stub generated by zarja: this import was not found in the scanned\n \
+         binaries. It only declares the types that recovered files reference,\n \
+         without their fields.\n"
+        .to_string();
+    if !unplaced.is_empty() {
+        header.push_str(
+            " \n Referenced types from other packages that could not be declared here:\n",
+        );
+        for type_name in unplaced {
+            header.push_str(&format!("   {}\n", type_name));
+        }
+    }
+    file.source_code_info = Some(SourceCodeInfo {
+        location: vec![Location {
+            path: vec![12],
+            leading_detached_comments: vec![header],
+            ..Default::default()
+        }],
+    });
+
+    file
+}
+
+impl Node {
+    fn has_extendee(&self) -> bool {
+        self.usage == Some(Usage::Extendee) || self.children.values().any(Node::has_extendee)
+    }
+
+    /// Returns true if the node holds an enum that a proto3 file uses
+    fn has_open_enum(&self) -> bool {
+        (self.usage == Some(Usage::Enum) && self.proto3)
+            || self.children.values().any(Node::has_open_enum)
+    }
+
+    fn message(self, name: String) -> DescriptorProto {
+        let mut message = DescriptorProto {
+            name: Some(name),
+            ..Default::default()
+        };
+        if self.usage == Some(Usage::Extendee) {
+            message.extension_range.push(ExtensionRange {
+                start: Some(1),
+                end: Some(MAX_FIELD_NUMBER as i32 + 1),
+                ..Default::default()
+            });
+        }
+        for (name, node) in self.children {
+            match node.usage {
+                Some(Usage::Enum) => message.enum_type.push(node.enum_type(name)),
+                _ => message.nested_type.push(node.message(name)),
+            }
+        }
+        message
+    }
+
+    fn enum_type(self, name: String) -> EnumDescriptorProto {
+        // Value names share the enclosing scope, so prefix the zero value
+        let zero = format!("{}_UNSPECIFIED", upper_snake_case(&name));
+        let values = std::iter::once(zero.clone())
+            .chain(self.values.into_iter().filter(|v| *v != zero))
+            .enumerate()
+            .map(|(number, name)| EnumValueDescriptorProto {
+                name: Some(name),
+                number: Some(number as i32),
+                ..Default::default()
+            })
+            .collect();
+        EnumDescriptorProto {
+            name: Some(name),
+            value: values,
+            ..Default::default()
+        }
+    }
+}
+
+/// Adds the fully-qualified names of every message and enum in a file
+fn collect_defined(file: &FileDescriptorProto, defined: &mut HashSet<String>) {
+    fn message(scope: &str, message_type: &DescriptorProto, defined: &mut HashSet<String>) {
+        let name = format!("{}.{}", scope, message_type.name());
+        for nested in &message_type.nested_type {
+            message(&name, nested, defined);
+        }
+        for enum_type in &message_type.enum_type {
+            defined.insert(format!("{}.{}", name, enum_type.name()));
+        }
+        defined.insert(name);
+    }
+
+    let scope = match file.package() {
+        "" => String::new(),
+        package => format!(".{}", package),
+    };
+    for message_type in &file.message_type {
+        message(&scope, message_type, defined);
+    }
+    for enum_type in &file.enum_type {
+        defined.insert(format!("{}.{}", scope, enum_type.name()));
+    }
+}
+
+/// Lists every type a file references, with how it is used and, for enum
+/// fields, the value named by the field's default
+fn collect_references<'a>(
+    file: &'a FileDescriptorProto,
+    references: &mut Vec<(&'a str, Usage, Option<&'a str>)>,
+) {
+    fn field<'a>(
+        field: &'a FieldDescriptorProto,
+        references: &mut Vec<(&'a str, Usage, Option<&'a str>)>,
+    ) {
+        match field.r#type() {
+            Type::Message | Type::Group => {
+                references.push((field.type_name(), Usage::Message, None));
+            }
+            Type::Enum => {
+                references.push((
+                    field.type_name(),
+                    Usage::Enum,
+                    field.default_value.as_deref(),
+                ));
+            }
+            _ => {}
+        }
+        if field.extendee.is_some() {
+            references.push((field.extendee(), Usage::Extendee, None));
+        }
+    }
+
+    fn message<'a>(
+        message_type: &'a DescriptorProto,
+        references: &mut Vec<(&'a str, Usage, Option<&'a str>)>,
+    ) {
+        for f in message_type.field.iter().chain(&message_type.extension) {
+            field(f, references);
+        }
+        for nested in &message_type.nested_type {
+            message(nested, references);
+        }
+    }
+
+    for message_type in &file.message_type {
+        message(message_type, references);
+    }
+    for extension in &file.extension {
+        field(extension, references);
+    }
+    for method in file.service.iter().flat_map(|s| &s.method) {
+        references.push((method.input_type(), Usage::Message, None));
+        references.push((method.output_type(), Usage::Message, None));
+    }
+}
+
+/// Splits `.pkg.Outer.Inner` into its package and type path
+///
+/// Descriptors don't record where the package ends, so the type path is
+/// assumed to start at the first capitalized component, or else to be the
+/// last component.
+fn split_type_name(type_name: &str) -> (String, Vec<String>) {
+    let parts: Vec<&str> = type_name.trim_start_matches('.').split('.').collect();
+    let start = parts
+        .iter()
+        .position(|p| p.starts_with(|c: char| c.is_ascii_uppercase()))
+        .unwrap_or(parts.len().saturating_sub(1));
+    (
+        parts[..start].join("."),
+        parts[start..].iter().map(|p| p.to_string()).collect(),
+    )
+}
+
+/// Scores how well an import path matches a package: the number of
+/// leading directory components equal to leading package components
+fn package_match(import: &str, package: &str) -> usize {
+    let directories = import.rsplit_once('/').map_or("", |(dir, _)| dir);
+    directories
+        .split('/')
+        .zip(package.split('.'))
+        .take_while(|(dir, part)| !dir.is_empty() && dir == part)
+        .count()
+}
+
+fn upper_snake_case(name: &str) -> String {
+    let mut result = String::with_capacity(name.len() + 4);
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            result.push('_');
+        }
+        result.push(c.to_ascii_uppercase());
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::{ProtoReconstructor, Verifier};
+    use prost_types::field_descriptor_proto::Label;
+    use prost_types::{MethodDescriptorProto, ServiceDescriptorProto};
+
+    fn field(name: &str, ty: Type, type_name: &str) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            name: Some(name.to_string()),
+            number: Some(1),
+            label: Some(Label::Optional as i32),
+            r#type: Some(ty as i32),
+            type_name: Some(type_name.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_split_type_name() {
+        assert_eq!(
+            split_type_name(".foo.bar.Outer.Inner"),
+            (
+                "foo.bar".to_string(),
+                vec!["Outer".to_string(), "Inner".to_string()]
+            )
+        );
+        assert_eq!(split_type_name(".foo.thing").1, vec!["thing".to_string()]);
+        assert_eq!(package_match("foo/bar/x.proto", "foo.bar"), 2);
+        assert_eq!(package_match("x.proto", "foo"), 0);
+        assert_eq!(upper_snake_case("HttpMethod"), "HTTP_METHOD");
+    }
+
+    #[test]
+    fn test_stubs_for_missing_imports() {
+        let file = FileDescriptorProto {
+            name: Some("app.proto".to_string()),
+            package: Some("app".to_string()),
+            dependency: vec![
+                "common/types.proto".to_string(),
+                "other/things.proto".to_string(),
+                "google/protobuf/empty.proto".to_string(),
+            ],
+            message_type: vec![DescriptorProto {
+                name: Some("Request".to_string()),
+                field: vec![
+                    field("id", Type::Message, ".common.Id"),
+                    field("kind", Type::Enum, ".common.Id.Kind"),
+                    field("thing", Type::Message, ".other.Thing"),
+                    field("own", Type::Message, ".app.Request"),
+                ],
+                ..Default::default()
+            }],
+            extension: vec![FieldDescriptorProto {
+                extendee: Some(".other.Base".to_string()),
+                ..field("ext", Type::Int32, "")
+            }],
+            service: vec![ServiceDescriptorProto {
+                name: Some("Api".to_string()),
+                method: vec![MethodDescriptorProto {
+                    name: Some("Call".to_string()),
+                    input_type: Some(".app.Request".to_string()),
+                    output_type: Some(".google.protobuf.Empty".to_string()),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let stubs = stubs(&[file]);
+        assert_eq!(stubs.len(), 2);
+
+        let common = &stubs[0];
+        assert_eq!(common.name(), "common/types.proto");
+        assert_eq!(common.package(), "common");
+        assert_eq!(common.syntax(), "proto3");
+        let id = &common.message_type[0];
+        assert_eq!(id.name(), "Id");
+        assert_eq!(id.enum_type[0].value[0].name(), "KIND_UNSPECIFIED");
+
+        let other = &stubs[1];
+        assert_eq!(other.name(), "other/things.proto");
+        assert_eq!(other.syntax(), "proto2");
+        let names: Vec<&str> = other.message_type.iter().map(|m| m.name()).collect();
+        assert_eq!(names, ["Base", "Thing"]);
+        assert_eq!(other.message_type[0].extension_range.len(), 1);
+        assert!(other.source_code_info.is_some());
+    }
+
+    #[test]
+    fn test_extendee_and_open_enum_in_one_import() {
+        let file = |name: &str, syntax: &str| FileDescriptorProto {
+            name: Some(name.to_string()),
+            package: Some("app".to_string()),
+            dependency: vec!["common/types.proto".to_string()],
+            syntax: Some(syntax.to_string()),
+            ..Default::default()
+        };
+        let extending = FileDescriptorProto {
+            extension: vec![FieldDescriptorProto {
+                extendee: Some(".common.Base".to_string()),
+                number: Some(100),
+                type_name: None,
+                ..field("ext", Type::Int32, "")
+            }],
+            ..file("extending.proto", "proto2")
+        };
+        let using = FileDescriptorProto {
+            message_type: vec![DescriptorProto {
+                name: Some("Request".to_string()),
+                field: vec![field("kind", Type::Enum, ".common.Kind")],
+                ..Default::default()
+            }],
+            ..file("using.proto", "proto3")
+        };
+
+        let stubs = stubs(&[extending.clone(), using.clone()]);
+        let names: Vec<&str> = stubs.iter().map(|s| s.name()).collect();
+        assert_eq!(names, ["common/types.proto", "common/types_extended.proto"]);
+        assert_eq!(stubs[0].syntax(), "proto3");
+        assert_eq!(stubs[0].enum_type[0].name(), "Kind");
+        assert_eq!(stubs[0].public_dependency, [0]);
+        assert_eq!(stubs[1].syntax(), "proto2");
+        assert_eq!(stubs[1].message_type[0].name(), "Base");
+
+        // The stubs and both files compile
+        let mut verifier = Verifier::new();
+        for stub in &stubs {
+            verifier.add_file(stub.clone());
+        }
+        let stub = ProtoReconstructor::from_proto(stubs[0].clone()).unwrap();
+        for reconstructor in [
+            stub,
+            ProtoReconstructor::from_proto(extending).unwrap(),
+            ProtoReconstructor::from_proto(using).unwrap(),
+        ] {
+            let report = verifier
+                .verify(&reconstructor, &reconstructor.reconstruct())
+                .unwrap();
+            assert!(report.is_faithful(), "{:?}", report.differences);
+        }
+    }
+}
@@ -0,0 +1,587 @@
+//! Round-trip verification.
+//!
+//! The reconstructed source is compiled back into a descriptor with protox,
+//! a protobuf compiler written in Rust, and compared with the original one
+//! field by field. Both sides are decoded dynamically, so option extensions
+//! that `prost_types` drops are compared too.
+
+use super::custom::{dump, escape_bytes};
+use super::{bundled, to_lower_camel_case, ProtoReconstructor};
+use crate::error::{Error, Result};
+use crate::scanner::fields;
+use prost_reflect::{DescriptorPool, DynamicMessage, FieldDescriptor, Kind, ReflectMessage, Value};
+use prost_types::FileDescriptorProto;
+use protox::file::{File, FileResolver, GoogleFileResolver};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// Compiles reconstructed source and compares it with the original
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use zarja_core::{ProtoReconstructor, Verifier};
+///
+/// # let data: Vec<u8> = Vec::new();
+/// let reconstructor = ProtoReconstructor::from_bytes(&data)?;
+/// let report = Verifier::new().verify(&reconstructor, &reconstructor.reconstruct())?;
+/// for difference in &report.differences {
+///     println!("{}", difference);
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Verifier {
+    /// Files available as imports, by name
+    imports: Arc<HashMap<String, FileDescriptorProto>>,
+}
+
+impl Default for Verifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Verifier {
+    /// Creates a verifier that resolves imports against the bundled option
+    /// definitions and the well-known types
+    pub fn new() -> Self {
+        let mut verifier = Self {
+            imports: Arc::default(),
+        };
+        for proto in bundled::files() {
+            verifier.add_file(proto);
+        }
+        verifier
+    }
+
+    /// Makes a file available as an import
+    ///
+    /// The first file added under a name is kept.
+    pub fn add_file(&mut self, proto: FileDescriptorProto) {
+        Arc::make_mut(&mut self.imports)
+            .entry(proto.name().to_string())
+            .or_insert(proto);
+    }
+
+    /// Compiles `source` as the reconstructor's file and compares the result
+    /// with its original descriptor
+    ///
+    /// Returns an error if the source doesn't compile, for example because
+    /// an import is unavailable.
+    pub fn verify(&self, reconstructor: &ProtoReconstructor, source: &str) -> Result<VerifyReport> {
+        let filename = reconstructor.filename().to_string();
+        let compiled = self.compile(&filename, source)?;
+
+        let mut comparison = Comparison::default();
+        comparison.messages("", &decode(reconstructor.data())?, &decode(&compiled)?);
+
+        Ok(VerifyReport {
+            filename,
+            differences: comparison.differences,
+            compared: comparison.compared,
+        })
+    }
+
+    /// Compiles a file, returning its encoded descriptor
+    fn compile(&self, name: &str, source: &str) -> Result<Vec<u8>> {
+        let resolver = ImportResolver {
+            name: name.to_string(),
+            source: source.to_string(),
+            imports: self.imports.clone(),
+            google: GoogleFileResolver::new(),
+        };
+        let mut compiler = protox::Compiler::with_file_resolver(resolver);
+        compiler
+            .open_file(name)
+            .map_err(|e| Error::recompile(name, e.to_string()))?;
+
+        compiler
+            .descriptor_pool()
+            .get_file_by_name(name)
+            .map(|file| file.encode_to_vec())
+            .ok_or_else(|| Error::recompile(name, "file missing from compiled pool"))
+    }
+}
+
+/// Serves the file under verification from source and its imports from
+/// descriptors
+struct ImportResolver {
+    name: String,
+    source: String,
+    imports: Arc<HashMap<String, FileDescriptorProto>>,
+    google: GoogleFileResolver,
+}
+
+impl FileResolver for ImportResolver {
+    fn open_file(&self, name: &str) -> std::result::Result<File, protox::Error> {
+        if name == self.name {
+            return File::from_source(name, &self.source);
+        }
+        match self.imports.get(name) {
+            Some(proto) => Ok(File::from_file_descriptor_proto(proto.clone())),
+            None => self.google.open_file(name),
+        }
+    }
+}
+
+/// Semantic differences between an original descriptor and its recompiled
+/// reconstruction
+#[derive(Debug, Clone)]
+pub struct VerifyReport {
+    /// Name of the verified file
+    pub filename: String,
+    /// Every value that differs
+    pub differences: Vec<Difference>,
+    /// Number of values compared
+    pub compared: usize,
+}
+
+impl VerifyReport {
+    /// Returns true if the reconstruction recompiles to the original
+    pub fn is_faithful(&self) -> bool {
+        self.differences.is_empty()
+    }
+
+    /// Returns the share of compared values that match, from 0 to 1
+    pub fn fidelity(&self) -> f64 {
+        if self.compared == 0 {
+            return 1.0;
+        }
+        let matching = self.compared.saturating_sub(self.differences.len());
+        matching as f64 / self.compared as f64
+    }
+}
+
+/// A value that differs after the round trip
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Difference {
+    /// Location in the descriptor, e.g. `message_type[Request].field[id].label`
+    pub path: String,
+    /// Value in the original descriptor
+    pub original: String,
+    /// Value in the recompiled descriptor
+    pub reconstructed: String,
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} -> {}",
+            self.path, self.original, self.reconstructed
+        )
+    }
+}
+
+/// Decodes and normalizes an encoded `FileDescriptorProto`
+fn decode(data: &[u8]) -> Result<DynamicMessage> {
+    let descriptor = DescriptorPool::global()
+        .get_message_by_name("google.protobuf.FileDescriptorProto")
+        .ok_or_else(|| Error::internal("descriptor.proto missing from global pool"))?;
+    let mut message = DynamicMessage::decode(descriptor, data)?;
+    normalize(&mut message);
+    Ok(message)
+}
+
+/// Clears values that don't affect the meaning of a descriptor
+fn normalize(message: &mut DynamicMessage) {
+    let string = |message: &DynamicMessage, name: &str| {
+        message
+            .get_field_by_name(name)
+            .and_then(|value| value.as_str().map(str::to_string))
+            .unwrap_or_default()
+    };
+
+    match message.descriptor().full_name() {
+        "google.protobuf.FileDescriptorProto" => {
+            message.clear_field_by_name("source_code_info");
+            if string(message, "syntax") == "proto2" {
+                message.clear_field_by_name("syntax");
+            }
+        }
+        "google.protobuf.DescriptorProto" => sort_oneofs(message),
+        "google.protobuf.FieldDescriptorProto"
+            if string(message, "json_name") == to_lower_camel_case(&string(message, "name")) =>
+        {
+            message.clear_field_by_name("json_name");
+        }
+        _ => {}
+    }
+
+    for (_, value) in message.fields_mut() {
+        match value {
+            Value::Message(nested) => normalize(nested),
+            Value::List(items) => items
+                .iter_mut()
+                .filter_map(Value::as_message_mut)
+                .for_each(normalize),
+            _ => {}
+        }
+    }
+}
+
+/// Puts a message's oneofs in name order, renumbering the `oneof_index`
+/// of its fields
+///
+/// Fields refer to their oneof by index, and sorted output can declare the
+/// oneofs in a different order than the original.
+fn sort_oneofs(message: &mut DynamicMessage) {
+    let oneofs = match message.get_field_by_name("oneof_decl").as_deref() {
+        Some(Value::List(oneofs)) if oneofs.len() > 1 => oneofs.clone(),
+        _ => return,
+    };
+    let name = |oneof: &Value| {
+        oneof
+            .as_message()
+            .and_then(|oneof| oneof.get_field_by_name("name"))
+            .and_then(|name| name.as_str().map(str::to_string))
+            .unwrap_or_default()
+    };
+
+    let mut order: Vec<usize> = (0..oneofs.len()).collect();
+    order.sort_by_key(|&i| name(&oneofs[i]));
+    let mut renumbered = vec![0; order.len()];
+    for (new, &old) in order.iter().enumerate() {
+        renumbered[old] = new as i32;
+    }
+
+    let sorted = order.iter().map(|&i| oneofs[i].clone()).collect();
+    message.set_field_by_name("oneof_decl", Value::List(sorted));
+    if let Some(Value::List(fields)) = message.get_field_by_name_mut("field") {
+        for field in fields.iter_mut().filter_map(Value::as_message_mut) {
+            if !field.has_field_by_name("oneof_index") {
+                continue;
+            }
+            if let Some(Value::I32(index)) = field.get_field_by_name_mut("oneof_index") {
+                if let Some(&new) = renumbered.get(*index as usize) {
+                    *index = new;
+                }
+            }
+        }
+    }
+}
+
+/// Differences found while walking both descriptors
+#[derive(Debug, Default)]
+struct Comparison {
+    differences: Vec<Difference>,
+    compared: usize,
+}
+
+impl Comparison {
+    fn push(&mut self, path: String, original: String, reconstructed: String) {
+        self.differences.push(Difference {
+            path,
+            original,
+            reconstructed,
+        });
+    }
+
+    fn messages(&mut self, path: &str, original: &DynamicMessage, reconstructed: &DynamicMessage) {
+        for field in original.descriptor().fields() {
+            let path = join(path, field.name());
+            let a = original.get_field(&field);
+            let b = reconstructed.get_field(&field);
+
+            if let (Value::List(a), Value::List(b)) = (&*a, &*b) {
+                self.lists(&path, &field, a, b);
+            } else if let (Value::Message(a), Value::Message(b)) = (&*a, &*b) {
+                self.messages(&path, a, b);
+            } else {
+                self.compared += 1;
+                let a = original.has_field(&field).then_some(&*a);
+                let b = reconstructed.has_field(&field).then_some(&*b);
+                if a != b {
+                    self.push(path, format_value(&field, a), format_value(&field, b));
+                }
+            }
+        }
+
+        self.unknown_fields(path, original, reconstructed);
+    }
+
+    /// Compares the elements of a repeated field
+    ///
+    /// Descriptor elements whose order carries no meaning are matched by
+    /// their key, everything else by index.
+    fn lists(&mut self, path: &str, field: &FieldDescriptor, a: &[Value], b: &[Value]) {
+        let Some(keys) = key_fields(field) else {
+            if a.len() != b.len() {
+                self.compared += 1;
+                self.push(
+                    path.to_string(),
+                    format!("{} elements", a.len()),
+                    format!("{} elements", b.len()),
+                );
+            }
+            for (i, (a, b)) in a.iter().zip(b).enumerate() {
+                let path = format!("{}[{}]", path, element_label(a, i));
+                self.values(path, field, a, b);
+            }
+            return;
+        };
+
+        let mut unmatched: Vec<(usize, &Value)> = b.iter().enumerate().collect();
+        for (i, a) in a.iter().enumerate() {
+            let element = format!("{}[{}]", path, element_label(a, i));
+            let key = element_key(a, keys);
+            let matching = unmatched
+                .iter()
+                .position(|(_, b)| element_key(b, keys) == key);
+            match matching {
+                Some(j) => {
+                    let (_, b) = unmatched.remove(j);
+                    self.values(element, field, a, b);
+                }
+                None => {
+                    self.compared += 1;
+                    self.push(element, "present".to_string(), "(missing)".to_string());
+                }
+            }
+        }
+        for (i, b) in unmatched {
+            self.compared += 1;
+            let element = format!("{}[{}]", path, element_label(b, i));
+            self.push(element, "(missing)".to_string(), "present".to_string());
+        }
+    }
+
+    fn values(&mut self, path: String, field: &FieldDescriptor, a: &Value, b: &Value) {
+        if let (Value::Message(a), Value::Message(b)) = (a, b) {
+            return self.messages(&path, a, b);
+        }
+        self.compared += 1;
+        if a != b {
+            self.push(
+                path,
+                format_value(field, Some(a)),
+                format_value(field, Some(b)),
+            );
+        }
+    }
+
+    /// Compares fields unknown to descriptor.proto, such as custom options
+    fn unknown_fields(
+        &mut self,
+        path: &str,
+        original: &DynamicMessage,
+        reconstructed: &DynamicMessage,
+    ) {
+        let a = unknown_fields(original);
+        let b = unknown_fields(reconstructed);
+        let mut numbers: Vec<u32> = a.keys().chain(b.keys()).copied().collect();
+        numbers.sort_unstable();
+        numbers.dedup();
+
+        for number in numbers {
+            self.compared += 1;
+            let a = a.get(&number);
+            let b = b.get(&number);
+            if a != b {
+                let format = |values: Option<&Vec<String>>| match values {
+                    Some(values) => values.join(", "),
+                    None => "(unset)".to_string(),
+                };
+                self.push(format!("{}.[{}]", path, number), format(a), format(b));
+            }
+        }
+    }
+}
+
+/// Unknown field values of a message by number, in `--decode_raw` form
+fn unknown_fields(message: &DynamicMessage) -> HashMap<u32, Vec<String>> {
+    let mut encoded = Vec::new();
+    for field in message.unknown_fields() {
+        field.encode(&mut encoded);
+    }
+
+    let mut values: HashMap<u32, Vec<String>> = HashMap::new();
+    for field in fields(&encoded) {
+        values
+            .entry(field.number)
+            .or_default()
+            .push(dump(field.wire_type, field.value));
+    }
+    for values in values.values_mut() {
+        values.sort();
+    }
+    values
+}
+
+fn join(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", path, name)
+    }
+}
+
+/// Fields that identify an element of a repeated descriptor field whose
+/// order carries no meaning
+///
+/// Sorted output and grouped `extend` blocks declare these in a different
+/// order than the original, and protoc keeps declaration order.
+fn key_fields(field: &FieldDescriptor) -> Option<&'static [&'static str]> {
+    let Kind::Message(message) = field.kind() else {
+        return None;
+    };
+    match message.full_name() {
+        "google.protobuf.DescriptorProto"
+        | "google.protobuf.EnumDescriptorProto"
+        | "google.protobuf.ServiceDescriptorProto"
+        | "google.protobuf.MethodDescriptorProto" => Some(&["name"]),
+        "google.protobuf.FieldDescriptorProto" => Some(&["extendee", "number"]),
+        _ => None,
+    }
+}
+
+/// Values of an element's key fields
+fn element_key(value: &Value, keys: &[&str]) -> Vec<Option<Value>> {
+    keys.iter()
+        .map(|key| {
+            value
+                .as_message()
+                .and_then(|message| message.get_field_by_name(key))
+                .map(|value| value.into_owned())
+        })
+        .collect()
+}
+
+/// Names a list element by its `name` field, falling back to its index
+fn element_label(value: &Value, index: usize) -> String {
+    value
+        .as_message()
+        .and_then(|message| message.get_field_by_name("name"))
+        .and_then(|name| name.as_str().filter(|n| !n.is_empty()).map(str::to_string))
+        .unwrap_or_else(|| index.to_string())
+}
+
+fn format_value(field: &FieldDescriptor, value: Option<&Value>) -> String {
+    match (value, field.kind()) {
+        (None, _) => "(unset)".to_string(),
+        (Some(Value::EnumNumber(number)), Kind::Enum(enum_type)) => enum_type
+            .get_value(*number)
+            .map(|value| value.name().to_string())
+            .unwrap_or_else(|| number.to_string()),
+        (Some(Value::Bool(b)), _) => b.to_string(),
+        (Some(Value::I32(v)), _) => v.to_string(),
+        (Some(Value::I64(v)), _) => v.to_string(),
+        (Some(Value::U32(v)), _) => v.to_string(),
+        (Some(Value::U64(v)), _) => v.to_string(),
+        (Some(Value::F32(v)), _) => v.to_string(),
+        (Some(Value::F64(v)), _) => v.to_string(),
+        (Some(Value::String(s)), _) => format!("{:?}", s),
+        (Some(Value::Bytes(b)), _) => format!("\"{}\"", escape_bytes(b)),
+        (Some(value), _) => format!("{:?}", value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::ReconstructorConfig;
+    use prost_types::field_descriptor_proto::{Label, Type};
+    use prost_types::{DescriptorProto, FieldDescriptorProto, FieldOptions, OneofDescriptorProto};
+
+    fn file() -> FileDescriptorProto {
+        FileDescriptorProto {
+            name: Some("test.proto".to_string()),
+            package: Some("test".to_string()),
+            message_type: vec![DescriptorProto {
+                name: Some("M".to_string()),
+                field: vec![FieldDescriptorProto {
+                    name: Some("id".to_string()),
+                    number: Some(1),
+                    label: Some(Label::Required as i32),
+                    r#type: Some(Type::Int64 as i32),
+                    default_value: Some("7".to_string()),
+                    options: Some(FieldOptions {
+                        deprecated: Some(true),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_faithful_round_trip() {
+        let reconstructor = ProtoReconstructor::from_proto(file()).unwrap();
+        let report = Verifier::new()
+            .verify(&reconstructor, &reconstructor.reconstruct())
+            .unwrap();
+        assert!(report.is_faithful(), "{:?}", report.differences);
+        assert!(report.compared > 0);
+        assert_eq!(report.fidelity(), 1.0);
+    }
+
+    #[test]
+    fn test_sorted_round_trip() {
+        let field = |name: &str, number, oneof| FieldDescriptorProto {
+            name: Some(name.to_string()),
+            number: Some(number),
+            label: Some(Label::Optional as i32),
+            r#type: Some(Type::Int32 as i32),
+            oneof_index: oneof,
+            ..Default::default()
+        };
+        let oneof = |name: &str| OneofDescriptorProto {
+            name: Some(name.to_string()),
+            ..Default::default()
+        };
+        let file = FileDescriptorProto {
+            name: Some("sorted.proto".to_string()),
+            message_type: vec![DescriptorProto {
+                name: Some("M".to_string()),
+                field: vec![
+                    field("x", 5, Some(0)),
+                    field("y", 3, Some(1)),
+                    field("z", 1, None),
+                ],
+                oneof_decl: vec![oneof("first"), oneof("second")],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let config = ReconstructorConfig::new().sort_fields(true);
+        let reconstructor = ProtoReconstructor::from_proto(file)
+            .unwrap()
+            .with_config(config);
+        let source = reconstructor.reconstruct();
+        assert!(source.find("z = 1").unwrap() < source.find("oneof second").unwrap());
+        assert!(source.find("oneof second").unwrap() < source.find("oneof first").unwrap());
+
+        let report = Verifier::new().verify(&reconstructor, &source).unwrap();
+        assert!(report.is_faithful(), "{:?}", report.differences);
+    }
+
+    #[test]
+    fn test_differences_reported() {
+        let reconstructor = ProtoReconstructor::from_proto(file()).unwrap();
+        let source = reconstructor
+            .reconstruct()
+            .replace("required", "optional")
+            .replace(", deprecated = true", "")
+            .replace("default = 7", "default = 8");
+        let report = Verifier::new().verify(&reconstructor, &source).unwrap();
+
+        let differences: Vec<String> = report.differences.iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            differences,
+            [
+                "message_type[M].field[id].label: LABEL_REQUIRED -> LABEL_OPTIONAL",
+                "message_type[M].field[id].default_value: \"7\" -> \"8\"",
+                "message_type[M].field[id].options.deprecated: true -> (unset)",
+            ]
+        );
+        assert!(report.fidelity() < 1.0);
+
+        assert!(Verifier::new().verify(&reconstructor, "message {").is_err());
+    }
+}
@@ -0,0 +1,539 @@
+//! Traversal of a file's declarations.
+//!
+//! [`Walker`] visits the elements of a `FileDescriptorProto` in the order
+//! `.proto` source lists them and calls a [`ProtoWriter`]'s hooks for each.
+//! Ordering decisions (declaration order, group bodies written with their
+//! field, map entries left out, `extend` blocks) are made here, so every
+//! writer sees the same structure.
+
+use super::names::qualify;
+use super::source::SourceInfo;
+use super::writer::{ProtoWriter, VisitContext};
+use super::{ProtoSyntax, ReconstructorConfig};
+use prost_types::field_descriptor_proto::Type;
+use prost_types::{
+    DescriptorProto, EnumDescriptorProto, FieldDescriptorProto, FileDescriptorProto,
+    ServiceDescriptorProto,
+};
+use std::collections::HashSet;
+use std::fmt::Result;
+
+/// A declaration inside a message body, by index into its descriptor list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Member {
+    Message(usize),
+    Enum(usize),
+    Field(usize),
+    Oneof(usize),
+    ExtensionRange(usize),
+    /// The `extend` block of the extension's extendee, by its first
+    /// extension
+    Extend(usize),
+    /// All reserved ranges and names
+    Reserved,
+}
+
+/// Walks a file, tracking where in it each element sits
+pub(super) struct Walker<'a> {
+    config: &'a ReconstructorConfig,
+    /// Declaration positions, if the descriptor kept them
+    source: SourceInfo,
+    syntax: ProtoSyntax,
+    /// `SourceCodeInfo`-style path of the element being visited
+    path: Vec<i32>,
+    /// Fully-qualified name of the scope being visited
+    scope: String,
+    depth: usize,
+}
+
+impl<'a> Walker<'a> {
+    pub(super) fn new(
+        config: &'a ReconstructorConfig,
+        source: SourceInfo,
+        syntax: ProtoSyntax,
+    ) -> Self {
+        Self {
+            config,
+            source,
+            syntax,
+            path: Vec::new(),
+            scope: String::new(),
+            depth: 0,
+        }
+    }
+
+    /// Descends into element `index` of repeated field `field_number`
+    fn enter(&mut self, field_number: i32, index: usize) {
+        self.path.push(field_number);
+        self.path.push(index as i32);
+    }
+
+    /// Returns to the parent element
+    fn leave(&mut self) {
+        self.path.truncate(self.path.len().saturating_sub(2));
+    }
+
+    /// Descends into the named scope, returning the enclosing one
+    fn enter_scope(&mut self, name: &str) -> String {
+        let inner = qualify(&self.scope, name);
+        std::mem::replace(&mut self.scope, inner)
+    }
+
+    fn context<'c>(
+        &'c self,
+        message: Option<&'c DescriptorProto>,
+        group: Option<&'c FieldDescriptorProto>,
+    ) -> VisitContext<'c> {
+        VisitContext {
+            path: &self.path,
+            scope: &self.scope,
+            depth: self.depth,
+            syntax: self.syntax,
+            message,
+            group,
+        }
+    }
+
+    pub(super) fn walk_file<W: ProtoWriter + ?Sized>(
+        &mut self,
+        w: &mut W,
+        file: &FileDescriptorProto,
+    ) -> Result {
+        self.scope = file.package().to_string();
+        w.enter_file(file, &self.context(None, None))?;
+
+        for (i, service) in file.service.iter().enumerate() {
+            self.enter(6, i);
+            self.walk_service(w, service)?;
+            self.leave();
+        }
+
+        // Messages, except the bodies of top-level extension groups
+        let groups = self.group_bodies(&file.extension, &file.message_type);
+        for (i, message) in file.message_type.iter().enumerate() {
+            if groups.contains(&i) {
+                continue;
+            }
+            self.enter(4, i);
+            self.walk_message(w, message, None, None)?;
+            self.leave();
+        }
+
+        for (i, enum_type) in file.enum_type.iter().enumerate() {
+            self.enter(5, i);
+            self.walk_enum(w, enum_type, None)?;
+            self.leave();
+        }
+
+        // Extensions (top-level), grouped into blocks by extendee
+        let keep_order = self.config.keep_extension_order;
+        for first in extend_blocks(&file.extension, keep_order) {
+            self.walk_extend(w, &file.extension, first, 7, None, &file.message_type)?;
+        }
+
+        w.exit_file(file, &self.context(None, None))
+    }
+
+    fn walk_service<W: ProtoWriter + ?Sized>(
+        &mut self,
+        w: &mut W,
+        service: &ServiceDescriptorProto,
+    ) -> Result {
+        w.enter_service(service, &self.context(None, None))?;
+        let outer = self.enter_scope(service.name());
+        self.depth += 1;
+
+        for (i, method) in service.method.iter().enumerate() {
+            self.enter(2, i);
+            w.write_method(method, &self.context(None, None))?;
+            self.leave();
+        }
+
+        self.depth -= 1;
+        self.scope = outer;
+        w.exit_service(service, &self.context(None, None))
+    }
+
+    /// Visits a message, or the body of `group`
+    fn walk_message<W: ProtoWriter + ?Sized>(
+        &mut self,
+        w: &mut W,
+        message: &DescriptorProto,
+        parent: Option<&DescriptorProto>,
+        group: Option<&FieldDescriptorProto>,
+    ) -> Result {
+        w.enter_message(message, &self.context(parent, group))?;
+        let outer = self.enter_scope(message.name());
+        self.depth += 1;
+
+        for member in self.message_members(message) {
+            let inside = Some(message);
+            match member {
+                Member::Message(i) => {
+                    self.enter(3, i);
+                    self.walk_message(w, &message.nested_type[i], inside, None)?;
+                    self.leave();
+                }
+                Member::Enum(i) => {
+                    self.enter(4, i);
+                    self.walk_enum(w, &message.enum_type[i], inside)?;
+                    self.leave();
+                }
+                Member::Field(i) => {
+                    self.enter(2, i);
+                    self.walk_field(w, &message.field[i], inside, &message.nested_type)?;
+                    self.leave();
+                }
+                Member::Oneof(i) => self.walk_oneof(w, message, i)?,
+                Member::ExtensionRange(i) => {
+                    self.enter(5, i);
+                    w.write_extension_range(
+                        &message.extension_range[i],
+                        &self.context(inside, None),
+                    )?;
+                    self.leave();
+                }
+                Member::Extend(i) => {
+                    self.walk_extend(w, &message.extension, i, 6, inside, &message.nested_type)?;
+                }
+                Member::Reserved => w.write_reserved(message, &self.context(inside, None))?,
+            }
+        }
+
+        self.depth -= 1;
+        self.scope = outer;
+        w.exit_message(message, &self.context(parent, group))
+    }
+
+    /// Visits a field, with the body of a group field
+    fn walk_field<W: ProtoWriter + ?Sized>(
+        &mut self,
+        w: &mut W,
+        field: &FieldDescriptorProto,
+        message: Option<&DescriptorProto>,
+        nested: &[DescriptorProto],
+    ) -> Result {
+        let Some((index, body)) = self.group_body(field, nested) else {
+            return w.write_field(field, &self.context(message, None));
+        };
+
+        w.enter_group(field, &self.context(message, None))?;
+
+        // The body is a sibling of the field: nested_type of the enclosing
+        // message, or message_type of the file for top-level extensions
+        let field_path = self.path.clone();
+        self.leave();
+        self.enter(if self.path.is_empty() { 4 } else { 3 }, index);
+        self.walk_message(w, body, message, Some(field))?;
+        self.path = field_path;
+
+        w.exit_group(field, &self.context(message, None))
+    }
+
+    /// Visits oneof `index` of a message with its fields
+    fn walk_oneof<W: ProtoWriter + ?Sized>(
+        &mut self,
+        w: &mut W,
+        message: &DescriptorProto,
+        index: usize,
+    ) -> Result {
+        let mut fields: Vec<(usize, &FieldDescriptorProto)> = message
+            .field
+            .iter()
+            .enumerate()
+            .filter(|(_, field)| {
+                field.oneof_index == Some(index as i32) && !is_proto3_optional(field, message)
+            })
+            .collect();
+        if self.config.sort_fields {
+            fields.sort_by_key(|(_, field)| field.number());
+        }
+
+        let oneof = &message.oneof_decl[index];
+        self.enter(8, index);
+        w.enter_oneof(oneof, &self.context(Some(message), None))?;
+        self.leave();
+        self.depth += 1;
+
+        // Member fields live under the message, not the oneof
+        for (i, field) in fields {
+            self.enter(2, i);
+            self.walk_field(w, field, Some(message), &message.nested_type)?;
+            self.leave();
+        }
+
+        self.depth -= 1;
+        self.enter(8, index);
+        w.exit_oneof(oneof, &self.context(Some(message), None))?;
+        self.leave();
+        Ok(())
+    }
+
+    /// Visits the `extend` block starting at `extensions[first]`
+    ///
+    /// `field_number` is the extension field of the enclosing file or
+    /// message.
+    fn walk_extend<W: ProtoWriter + ?Sized>(
+        &mut self,
+        w: &mut W,
+        extensions: &[FieldDescriptorProto],
+        first: usize,
+        field_number: i32,
+        message: Option<&DescriptorProto>,
+        nested: &[DescriptorProto],
+    ) -> Result {
+        let keep_order = self.config.keep_extension_order;
+        let block: Vec<(usize, &FieldDescriptorProto)> =
+            extend_block(extensions, first, keep_order)
+                .into_iter()
+                .map(|i| (i, &extensions[i]))
+                .collect();
+        let extendee = extensions[first].extendee();
+
+        self.path.push(field_number);
+        w.enter_extend(extendee, &block, &self.context(message, None))?;
+        self.path.pop();
+        self.depth += 1;
+
+        for (i, extension) in block {
+            self.enter(field_number, i);
+            self.walk_field(w, extension, message, nested)?;
+            self.leave();
+        }
+
+        self.depth -= 1;
+        self.path.push(field_number);
+        w.exit_extend(extendee, &self.context(message, None))?;
+        self.path.pop();
+        Ok(())
+    }
+
+    fn walk_enum<W: ProtoWriter + ?Sized>(
+        &mut self,
+        w: &mut W,
+        enum_type: &EnumDescriptorProto,
+        message: Option<&DescriptorProto>,
+    ) -> Result {
+        w.enter_enum(enum_type, &self.context(message, None))?;
+        self.depth += 1;
+
+        if !enum_type.reserved_range.is_empty() || !enum_type.reserved_name.is_empty() {
+            w.write_enum_reserved(enum_type, &self.context(message, None))?;
+        }
+        for (i, value) in enum_type.value.iter().enumerate() {
+            self.enter(2, i);
+            w.write_enum_value(value, &self.context(message, None))?;
+            self.leave();
+        }
+
+        self.depth -= 1;
+        w.exit_enum(enum_type, &self.context(message, None))
+    }
+
+    /// Returns the declarations of a message body in output order
+    ///
+    /// Without `sort_fields`, members follow their declaration order when
+    /// the descriptor has source info. Otherwise (and always for sorted
+    /// output) they use protoc's own layout: nested messages, enums, fields
+    /// with each oneof at its first member, extension ranges, extensions
+    /// and reserved ranges.
+    fn message_members(&self, message: &DescriptorProto) -> Vec<Member> {
+        let mut members = Vec::new();
+        members.extend((0..message.enum_type.len()).map(Member::Enum));
+
+        let mut fields: Vec<usize> = (0..message.field.len()).collect();
+        if self.config.sort_fields {
+            fields.sort_by_key(|&i| message.field[i].number());
+        }
+        let mut oneofs = HashSet::new();
+        for i in fields {
+            let field = &message.field[i];
+            match field.oneof_index {
+                Some(oneof) if !is_proto3_optional(field, message) => {
+                    if oneofs.insert(oneof) {
+                        members.push(Member::Oneof(oneof as usize));
+                    }
+                }
+                _ => members.push(Member::Field(i)),
+            }
+        }
+
+        members.extend((0..message.extension_range.len()).map(Member::ExtensionRange));
+        let keep_order = self.config.keep_extension_order;
+        members.extend(extend_blocks(&message.extension, keep_order).map(Member::Extend));
+        if !message.reserved_range.is_empty() || !message.reserved_name.is_empty() {
+            members.push(Member::Reserved);
+        }
+
+        // Map entries are synthetic, and group bodies are written inline
+        // with their field
+        let groups = self.group_bodies(
+            message.field.iter().chain(&message.extension),
+            &message.nested_type,
+        );
+        let nested = message
+            .nested_type
+            .iter()
+            .enumerate()
+            .filter(|(i, nested)| {
+                !groups.contains(i) && !nested.options.as_ref().is_some_and(|o| o.map_entry())
+            });
+
+        // Group bodies share nested_type with regular messages, so a message
+        // listed between two group bodies was declared between their fields
+        let bodies: Vec<Vec<usize>> = members
+            .iter()
+            .map(|&member| self.member_group_bodies(message, member))
+            .collect();
+        let mut positions: Vec<(usize, Member)> = Vec::new();
+        for (i, _) in nested {
+            let position = bodies
+                .iter()
+                .position(|b| b.iter().any(|&body| body > i))
+                .or_else(|| bodies.iter().rposition(|b| !b.is_empty()).map(|p| p + 1))
+                .unwrap_or(0);
+            positions.push((position, Member::Message(i)));
+        }
+        let mut ordered = Vec::with_capacity(members.len() + positions.len());
+        for (k, member) in members.into_iter().chain([Member::Reserved]).enumerate() {
+            ordered.extend(positions.iter().filter(|(p, _)| *p == k).map(|&(_, m)| m));
+            ordered.push(member);
+        }
+        // Drop the sentinel
+        ordered.pop();
+        members = ordered;
+
+        if !self.config.sort_fields {
+            let starts: Option<Vec<_>> = members
+                .iter()
+                .map(|&member| self.member_start(message, member))
+                .collect();
+            if let Some(starts) = starts {
+                let mut ordered: Vec<_> = starts.into_iter().zip(members).collect();
+                ordered.sort_by_key(|(start, _)| *start);
+                members = ordered.into_iter().map(|(_, member)| member).collect();
+            }
+        }
+
+        members
+    }
+
+    /// Returns the nested_type indices of the group bodies a member writes
+    fn member_group_bodies(&self, message: &DescriptorProto, member: Member) -> Vec<usize> {
+        let keep_order = self.config.keep_extension_order;
+        let fields: Vec<&FieldDescriptorProto> = match member {
+            Member::Field(i) => vec![&message.field[i]],
+            Member::Extend(i) => extend_block(&message.extension, i, keep_order)
+                .into_iter()
+                .map(|i| &message.extension[i])
+                .collect(),
+            Member::Oneof(o) => message
+                .field
+                .iter()
+                .filter(|field| field.oneof_index == Some(o as i32))
+                .collect(),
+            _ => Vec::new(),
+        };
+        fields
+            .into_iter()
+            .filter_map(|field| self.group_body(field, &message.nested_type))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Returns where a member of the current message was declared
+    fn member_start(&self, message: &DescriptorProto, member: Member) -> Option<(i32, i32)> {
+        let start = |field_number: i32, index: usize| {
+            let mut path = self.path.clone();
+            path.extend([field_number, index as i32]);
+            self.source.start(&path)
+        };
+
+        match member {
+            Member::Message(i) => start(3, i),
+            Member::Enum(i) => start(4, i),
+            Member::Field(i) => start(2, i),
+            Member::Oneof(i) => start(8, i),
+            Member::ExtensionRange(i) => start(5, i),
+            Member::Extend(i) => start(6, i),
+            Member::Reserved => (0..message.reserved_range.len())
+                .map(|i| start(9, i))
+                .chain((0..message.reserved_name.len()).map(|i| start(10, i)))
+                .flatten()
+                .min(),
+        }
+    }
+
+    /// Finds the synthetic message holding the body of a proto2 group field
+    ///
+    /// Group bodies are declared in the same scope as the field.
+    fn group_body<'m>(
+        &self,
+        field: &FieldDescriptorProto,
+        nested: &'m [DescriptorProto],
+    ) -> Option<(usize, &'m DescriptorProto)> {
+        // Editions has no group syntax; delimited fields name a regular message
+        if self.syntax != ProtoSyntax::Proto2 || field.r#type() != Type::Group {
+            return None;
+        }
+
+        nested.iter().enumerate().find(|(_, message)| {
+            field.type_name().strip_prefix('.') == Some(&qualify(&self.scope, message.name()))
+        })
+    }
+
+    /// Indices of the messages in `nested` that are bodies of `fields`' groups
+    fn group_bodies<'f>(
+        &self,
+        fields: impl IntoIterator<Item = &'f FieldDescriptorProto>,
+        nested: &[DescriptorProto],
+    ) -> HashSet<usize> {
+        fields
+            .into_iter()
+            .filter_map(|field| self.group_body(field, nested))
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+/// Returns true for a proto3 `optional` field, whose oneof is synthetic
+pub(super) fn is_proto3_optional(field: &FieldDescriptorProto, message: &DescriptorProto) -> bool {
+    // In proto3, optional fields have a synthetic oneof
+    if let Some(oneof_index) = field.oneof_index {
+        if let Some(oneof) = message.oneof_decl.get(oneof_index as usize) {
+            // Synthetic oneofs have names starting with "_"
+            return oneof.name().starts_with('_');
+        }
+    }
+    false
+}
+
+/// Returns the first extension of each `extend` block, in order
+///
+/// A block holds every extension of one extendee in the scope, or with
+/// `keep_order` (see [`ReconstructorConfig::keep_extension_order`]) only a
+/// run of consecutive ones.
+fn extend_blocks(
+    extensions: &[FieldDescriptorProto],
+    keep_order: bool,
+) -> impl Iterator<Item = usize> + '_ {
+    (0..extensions.len()).filter(move |&i| {
+        let extendee = extensions[i].extendee();
+        if keep_order {
+            i == 0 || extensions[i - 1].extendee() != extendee
+        } else {
+            extensions[..i].iter().all(|e| e.extendee() != extendee)
+        }
+    })
+}
+
+/// Returns the extensions of the `extend` block starting at `first`
+fn extend_block(extensions: &[FieldDescriptorProto], first: usize, keep_order: bool) -> Vec<usize> {
+    let extendee = extensions[first].extendee();
+    let same = |&i: &usize| extensions[i].extendee() == extendee;
+    if keep_order {
+        (first..extensions.len()).take_while(same).collect()
+    } else {
+        (first..extensions.len()).filter(same).collect()
+    }
+}
@@ -3,75 +3,222 @@
 //! This module provides the [`ProtoWriter`] trait for customizing
 //! how proto elements are written to output.
 
+use super::ProtoSyntax;
+use prost_types::descriptor_proto::ExtensionRange;
 use prost_types::{
-    DescriptorProto, EnumDescriptorProto, FieldDescriptorProto, FileDescriptorProto,
-    MethodDescriptorProto, OneofDescriptorProto, ServiceDescriptorProto,
+    DescriptorProto, EnumDescriptorProto, EnumValueDescriptorProto, FieldDescriptorProto,
+    FileDescriptorProto, MethodDescriptorProto, OneofDescriptorProto, ServiceDescriptorProto,
 };
 use std::fmt::Result;
 
+/// Where a visited element sits in its file
+#[derive(Debug, Clone, Copy)]
+pub struct VisitContext<'a> {
+    pub(crate) path: &'a [i32],
+    pub(crate) scope: &'a str,
+    pub(crate) depth: usize,
+    pub(crate) syntax: ProtoSyntax,
+    pub(crate) message: Option<&'a DescriptorProto>,
+    pub(crate) group: Option<&'a FieldDescriptorProto>,
+}
+
+impl<'a> VisitContext<'a> {
+    /// `SourceCodeInfo` path of the element
+    ///
+    /// For an `extend` block this is the path of the enclosing extension
+    /// list (`[7]` at the top level), which protoc records once per block.
+    pub fn path(&self) -> &'a [i32] {
+        self.path
+    }
+
+    /// Fully-qualified name of the scope type references resolve in
+    ///
+    /// The package for top-level elements, and the enclosing message or
+    /// service inside one.
+    pub fn scope(&self) -> &'a str {
+        self.scope
+    }
+
+    /// Number of enclosing messages, services, enums, oneofs and `extend`
+    /// blocks
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Syntax of the file
+    pub fn syntax(&self) -> ProtoSyntax {
+        self.syntax
+    }
+
+    /// The message the element is declared in, if any
+    pub fn message(&self) -> Option<&'a DescriptorProto> {
+        self.message
+    }
+
+    /// For the body of a proto2 group, the group field it belongs to
+    pub fn group(&self) -> Option<&'a FieldDescriptorProto> {
+        self.group
+    }
+}
+
 /// Trait for writing proto elements to output.
 ///
-/// Implement this trait to customize the output format for proto definitions.
-/// The default implementation writes standard `.proto` syntax.
+/// [`ProtoReconstructor::visit`](super::ProtoReconstructor::visit) walks a
+/// file and calls these hooks in the order `.proto` source lists the
+/// elements; every hook defaults to doing nothing. Container elements get
+/// an `enter_*`/`exit_*` pair with their members visited in between:
+///
+/// - a group field calls `enter_group`, then visits its body as a message
+///   (whose context has [`VisitContext::group`] set), then `exit_group`
+/// - extensions are visited as fields inside `enter_extend`/`exit_extend`,
+///   one block per extendee in each scope (or per run of extensions with
+///   the same extendee, with `keep_extension_order`)
+/// - map entry messages are not visited; their fields are
 ///
 /// # Example
 ///
-/// ```ignore
-/// use protodump_core::proto::ProtoWriter;
+/// ```
+/// use std::fmt::Result;
+/// use zarja_core::{ProtoReconstructor, ProtoWriter, VisitContext};
+/// use prost_types::{DescriptorProto, FileDescriptorProto};
 ///
-/// struct JsonProtoWriter {
-///     output: String,
-/// }
+/// /// Lists the fully-qualified name of every message
+/// #[derive(Default)]
+/// struct MessageNames(Vec<String>);
 ///
-/// impl ProtoWriter for JsonProtoWriter {
-///     fn write_message(&mut self, message: &DescriptorProto) -> Result {
-///         // Write message as JSON
-///         self.output.push_str(&format!(r#"{{"name": "{}"}}"#, message.name()));
+/// impl ProtoWriter for MessageNames {
+///     fn enter_message(&mut self, message: &DescriptorProto, cx: &VisitContext<'_>) -> Result {
+///         self.0.push(format!("{}.{}", cx.scope(), message.name()));
 ///         Ok(())
 ///     }
-///     // ... implement other methods
 /// }
+///
+/// let file = FileDescriptorProto {
+///     name: Some("test.proto".to_string()),
+///     package: Some("test".to_string()),
+///     message_type: vec![DescriptorProto {
+///         name: Some("Request".to_string()),
+///         ..Default::default()
+///     }],
+///     ..Default::default()
+/// };
+/// let mut names = MessageNames::default();
+/// ProtoReconstructor::from_proto(file).unwrap().visit(&mut names).unwrap();
+/// assert_eq!(names.0, ["test.Request"]);
 /// ```
+#[allow(unused_variables)]
 pub trait ProtoWriter {
-    /// Write the complete file descriptor
-    fn write_file(&mut self, file: &FileDescriptorProto) -> Result {
-        let _ = file;
+    /// Starts the file; imports and file options are part of it
+    fn enter_file(&mut self, file: &FileDescriptorProto, cx: &VisitContext<'_>) -> Result {
         Ok(())
     }
 
-    /// Write a message definition
-    fn write_message(&mut self, message: &DescriptorProto) -> Result {
-        let _ = message;
+    /// Ends the file
+    fn exit_file(&mut self, file: &FileDescriptorProto, cx: &VisitContext<'_>) -> Result {
         Ok(())
     }
 
-    /// Write a field definition
-    fn write_field(&mut self, field: &FieldDescriptorProto) -> Result {
-        let _ = field;
+    /// Starts a service definition
+    fn enter_service(&mut self, service: &ServiceDescriptorProto, cx: &VisitContext<'_>) -> Result {
         Ok(())
     }
 
-    /// Write an enum definition
-    fn write_enum(&mut self, enum_type: &EnumDescriptorProto) -> Result {
-        let _ = enum_type;
+    /// Ends a service definition
+    fn exit_service(&mut self, service: &ServiceDescriptorProto, cx: &VisitContext<'_>) -> Result {
         Ok(())
     }
 
-    /// Write a service definition
-    fn write_service(&mut self, service: &ServiceDescriptorProto) -> Result {
-        let _ = service;
+    /// Write a method definition
+    fn write_method(&mut self, method: &MethodDescriptorProto, cx: &VisitContext<'_>) -> Result {
         Ok(())
     }
 
-    /// Write a method definition
-    fn write_method(&mut self, method: &MethodDescriptorProto) -> Result {
-        let _ = method;
+    /// Starts a message definition, or the body of a group
+    fn enter_message(&mut self, message: &DescriptorProto, cx: &VisitContext<'_>) -> Result {
+        Ok(())
+    }
+
+    /// Ends a message definition, or the body of a group
+    fn exit_message(&mut self, message: &DescriptorProto, cx: &VisitContext<'_>) -> Result {
+        Ok(())
+    }
+
+    /// Write a field definition, including map fields and extensions
+    fn write_field(&mut self, field: &FieldDescriptorProto, cx: &VisitContext<'_>) -> Result {
+        Ok(())
+    }
+
+    /// Starts a proto2 group field; its body is visited next
+    fn enter_group(&mut self, field: &FieldDescriptorProto, cx: &VisitContext<'_>) -> Result {
+        Ok(())
+    }
+
+    /// Ends a proto2 group field
+    fn exit_group(&mut self, field: &FieldDescriptorProto, cx: &VisitContext<'_>) -> Result {
+        Ok(())
+    }
+
+    /// Starts a oneof; its fields are visited next
+    fn enter_oneof(&mut self, oneof: &OneofDescriptorProto, cx: &VisitContext<'_>) -> Result {
+        Ok(())
+    }
+
+    /// Ends a oneof
+    fn exit_oneof(&mut self, oneof: &OneofDescriptorProto, cx: &VisitContext<'_>) -> Result {
+        Ok(())
+    }
+
+    /// Starts an `extend` block of the given extensions, with their indices
+    /// in the enclosing extension list
+    fn enter_extend(
+        &mut self,
+        extendee: &str,
+        extensions: &[(usize, &FieldDescriptorProto)],
+        cx: &VisitContext<'_>,
+    ) -> Result {
         Ok(())
     }
 
-    /// Write a oneof definition
-    fn write_oneof(&mut self, oneof: &OneofDescriptorProto) -> Result {
-        let _ = oneof;
+    /// Ends an `extend` block
+    fn exit_extend(&mut self, extendee: &str, cx: &VisitContext<'_>) -> Result {
+        Ok(())
+    }
+
+    /// Write an extension range of a message
+    fn write_extension_range(&mut self, range: &ExtensionRange, cx: &VisitContext<'_>) -> Result {
+        Ok(())
+    }
+
+    /// Write the reserved ranges and names of a message
+    fn write_reserved(&mut self, message: &DescriptorProto, cx: &VisitContext<'_>) -> Result {
+        Ok(())
+    }
+
+    /// Starts an enum definition
+    fn enter_enum(&mut self, enum_type: &EnumDescriptorProto, cx: &VisitContext<'_>) -> Result {
+        Ok(())
+    }
+
+    /// Ends an enum definition
+    fn exit_enum(&mut self, enum_type: &EnumDescriptorProto, cx: &VisitContext<'_>) -> Result {
+        Ok(())
+    }
+
+    /// Write the reserved ranges and names of an enum
+    fn write_enum_reserved(
+        &mut self,
+        enum_type: &EnumDescriptorProto,
+        cx: &VisitContext<'_>,
+    ) -> Result {
+        Ok(())
+    }
+
+    /// Write an enum value
+    fn write_enum_value(
+        &mut self,
+        value: &EnumValueDescriptorProto,
+        cx: &VisitContext<'_>,
+    ) -> Result {
         Ok(())
     }
 }
@@ -84,9 +231,9 @@ impl ProtoWriter for NullWriter {}
 /// A writer that collects statistics about the proto file
 #[derive(Debug, Default)]
 pub struct StatsWriter {
-    /// Number of messages
+    /// Number of messages, including group bodies
     pub message_count: usize,
-    /// Number of fields
+    /// Number of fields, including groups and extensions
     pub field_count: usize,
     /// Number of enums
     pub enum_count: usize,
@@ -97,27 +244,36 @@ pub struct StatsWriter {
 }
 
 impl ProtoWriter for StatsWriter {
-    fn write_message(&mut self, _message: &DescriptorProto) -> Result {
+    fn enter_message(&mut self, _message: &DescriptorProto, _cx: &VisitContext<'_>) -> Result {
         self.message_count += 1;
         Ok(())
     }
 
-    fn write_field(&mut self, _field: &FieldDescriptorProto) -> Result {
+    fn write_field(&mut self, _field: &FieldDescriptorProto, _cx: &VisitContext<'_>) -> Result {
+        self.field_count += 1;
+        Ok(())
+    }
+
+    fn enter_group(&mut self, _field: &FieldDescriptorProto, _cx: &VisitContext<'_>) -> Result {
         self.field_count += 1;
         Ok(())
     }
 
-    fn write_enum(&mut self, _enum_type: &EnumDescriptorProto) -> Result {
+    fn enter_enum(&mut self, _enum_type: &EnumDescriptorProto, _cx: &VisitContext<'_>) -> Result {
         self.enum_count += 1;
         Ok(())
     }
 
-    fn write_service(&mut self, _service: &ServiceDescriptorProto) -> Result {
+    fn enter_service(
+        &mut self,
+        _service: &ServiceDescriptorProto,
+        _cx: &VisitContext<'_>,
+    ) -> Result {
         self.service_count += 1;
         Ok(())
     }
 
-    fn write_method(&mut self, _method: &MethodDescriptorProto) -> Result {
+    fn write_method(&mut self, _method: &MethodDescriptorProto, _cx: &VisitContext<'_>) -> Result {
         self.method_count += 1;
         Ok(())
     }
@@ -126,21 +282,186 @@ impl ProtoWriter for StatsWriter {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::proto::ProtoReconstructor;
+    use prost_types::field_descriptor_proto::{Label, Type};
+
+    fn field(name: &str, number: i32) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            name: Some(name.to_string()),
+            number: Some(number),
+            label: Some(Label::Optional as i32),
+            r#type: Some(Type::Int32 as i32),
+            ..Default::default()
+        }
+    }
 
     #[test]
     fn test_null_writer() {
-        let mut writer = NullWriter;
-        assert!(writer.write_file(&FileDescriptorProto::default()).is_ok());
+        let file = FileDescriptorProto {
+            name: Some("test.proto".to_string()),
+            ..Default::default()
+        };
+        let reconstructor = ProtoReconstructor::from_proto(file).unwrap();
+        assert!(reconstructor.visit(&mut NullWriter).is_ok());
     }
 
     #[test]
     fn test_stats_writer() {
+        let file = FileDescriptorProto {
+            name: Some("test.proto".to_string()),
+            package: Some("test".to_string()),
+            message_type: vec![DescriptorProto {
+                name: Some("Outer".to_string()),
+                field: vec![field("a", 1), field("b", 2)],
+                nested_type: vec![DescriptorProto {
+                    name: Some("Inner".to_string()),
+                    field: vec![field("c", 1)],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            service: vec![ServiceDescriptorProto {
+                name: Some("Api".to_string()),
+                method: vec![MethodDescriptorProto {
+                    name: Some("Call".to_string()),
+                    input_type: Some(".test.Outer".to_string()),
+                    output_type: Some(".test.Outer".to_string()),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
         let mut writer = StatsWriter::default();
-        writer.write_message(&DescriptorProto::default()).unwrap();
-        writer.write_message(&DescriptorProto::default()).unwrap();
-        writer.write_field(&FieldDescriptorProto::default()).unwrap();
-        
+        ProtoReconstructor::from_proto(file)
+            .unwrap()
+            .visit(&mut writer)
+            .unwrap();
+
         assert_eq!(writer.message_count, 2);
-        assert_eq!(writer.field_count, 1);
+        assert_eq!(writer.field_count, 3);
+        assert_eq!(writer.service_count, 1);
+        assert_eq!(writer.method_count, 1);
+    }
+
+    /// Records every hook call with its path, scope and depth
+    #[derive(Default)]
+    struct Trace(Vec<String>);
+
+    impl Trace {
+        fn push(&mut self, event: &str, cx: &VisitContext<'_>) -> Result {
+            self.0.push(format!(
+                "{} {:?} {} {}",
+                event,
+                cx.path(),
+                cx.scope(),
+                cx.depth()
+            ));
+            Ok(())
+        }
+    }
+
+    impl ProtoWriter for Trace {
+        fn enter_message(&mut self, message: &DescriptorProto, cx: &VisitContext<'_>) -> Result {
+            self.push(&format!("message {}", message.name()), cx)
+        }
+
+        fn exit_message(&mut self, message: &DescriptorProto, cx: &VisitContext<'_>) -> Result {
+            self.push(&format!("end {}", message.name()), cx)
+        }
+
+        fn write_field(&mut self, field: &FieldDescriptorProto, cx: &VisitContext<'_>) -> Result {
+            self.push(&format!("field {}", field.name()), cx)
+        }
+
+        fn enter_oneof(&mut self, oneof: &OneofDescriptorProto, cx: &VisitContext<'_>) -> Result {
+            self.push(&format!("oneof {}", oneof.name()), cx)
+        }
+
+        fn exit_oneof(&mut self, oneof: &OneofDescriptorProto, cx: &VisitContext<'_>) -> Result {
+            self.push(&format!("end {}", oneof.name()), cx)
+        }
+
+        fn enter_extend(
+            &mut self,
+            extendee: &str,
+            extensions: &[(usize, &FieldDescriptorProto)],
+            cx: &VisitContext<'_>,
+        ) -> Result {
+            self.push(&format!("extend {} ({})", extendee, extensions.len()), cx)
+        }
+
+        fn exit_extend(&mut self, extendee: &str, cx: &VisitContext<'_>) -> Result {
+            self.push(&format!("end {}", extendee), cx)
+        }
+    }
+
+    #[test]
+    fn test_visit_order_and_context() {
+        let file = FileDescriptorProto {
+            name: Some("test.proto".to_string()),
+            package: Some("test".to_string()),
+            syntax: Some("proto2".to_string()),
+            message_type: vec![DescriptorProto {
+                name: Some("Outer".to_string()),
+                field: vec![
+                    field("a", 1),
+                    FieldDescriptorProto {
+                        oneof_index: Some(0),
+                        ..field("b", 2)
+                    },
+                ],
+                oneof_decl: vec![OneofDescriptorProto {
+                    name: Some("choice".to_string()),
+                    ..Default::default()
+                }],
+                nested_type: vec![DescriptorProto {
+                    name: Some("Inner".to_string()),
+                    ..Default::default()
+                }],
+                extension_range: vec![ExtensionRange {
+                    start: Some(100),
+                    end: Some(200),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            extension: vec![
+                FieldDescriptorProto {
+                    extendee: Some(".test.Outer".to_string()),
+                    ..field("x", 100)
+                },
+                FieldDescriptorProto {
+                    extendee: Some(".test.Outer".to_string()),
+                    ..field("y", 101)
+                },
+            ],
+            ..Default::default()
+        };
+
+        let mut trace = Trace::default();
+        ProtoReconstructor::from_proto(file)
+            .unwrap()
+            .visit(&mut trace)
+            .unwrap();
+
+        assert_eq!(
+            trace.0,
+            [
+                "message Outer [4, 0] test 0",
+                "message Inner [4, 0, 3, 0] test.Outer 1",
+                "end Inner [4, 0, 3, 0] test.Outer 1",
+                "field a [4, 0, 2, 0] test.Outer 1",
+                "oneof choice [4, 0, 8, 0] test.Outer 1",
+                "field b [4, 0, 2, 1] test.Outer 2",
+                "end choice [4, 0, 8, 0] test.Outer 1",
+                "end Outer [4, 0] test 0",
+                "extend .test.Outer (2) [7] test 0",
+                "field x [7, 0] test 1",
+                "field y [7, 1] test 1",
+                "end .test.Outer [7] test 0",
+            ]
+        );
     }
 }
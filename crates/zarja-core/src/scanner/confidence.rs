@@ -0,0 +1,336 @@
+//! Confidence scoring for scan candidates.
+//!
+//! The scanner accepts any record that starts with a `.proto` name and
+//! continues as wire fields, which also matches length-prefixed strings
+//! followed by unrelated bytes. [`Confidence`] checks the record's structure
+//! against what protoc writes, so junk can be told apart from descriptors
+//! before it is decoded.
+
+use super::wire::{fields, WireField, WireType};
+use std::collections::HashSet;
+use std::fmt;
+
+/// Field score of a record that has nothing but its name, which could as
+/// well be any length-prefixed string
+const LONE_NAME: f32 = 0.25;
+
+/// Structural checks on a candidate `FileDescriptorProto`
+///
+/// Each check scores from 0.0 (failed) to 1.0 (passed), mostly as the
+/// fraction of the checked elements that look right. A check with nothing
+/// to look at passes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Confidence {
+    /// Share of the record's bytes in top-level fields with a
+    /// `FileDescriptorProto` field number and matching wire type
+    pub fields: f32,
+    /// Share of valid names: the file path, the package, and the names of
+    /// top-level definitions and their fields, values and methods
+    pub names: f32,
+    /// Share of fully qualified type references, with references to types
+    /// of this file carrying its package
+    pub type_names: f32,
+    /// 1.0 if `syntax` is absent or a known value, 0.0 otherwise
+    pub syntax: f32,
+}
+
+impl Confidence {
+    /// Checks a serialized `FileDescriptorProto`
+    pub fn of(data: &[u8]) -> Self {
+        let mut fields = Fields::default();
+        let mut names = Ratio::default();
+        let mut syntax = 1.0;
+        let mut package = "";
+        let mut locals = HashSet::new();
+        let mut references = Vec::new();
+
+        let mut position = 0;
+        for field in self::fields(data) {
+            position += field.len;
+            fields.add(&field);
+            let Some(value) = field.as_bytes() else {
+                continue;
+            };
+            match field.number {
+                1 => names.add(is_path(value)),
+                2 => {
+                    names.add(is_dotted(value));
+                    package = std::str::from_utf8(value).unwrap_or_default();
+                }
+                4 | 5 => {
+                    let name = name(value);
+                    names.add(name.is_some_and(is_identifier));
+                    locals.extend(name);
+                    if field.number == 4 {
+                        message(value, &mut names, &mut references);
+                    } else {
+                        children(value, 2, &mut names);
+                    }
+                }
+                6 => {
+                    names.add(name(value).is_some_and(is_identifier));
+                    for method in self::fields(value).filter(|f| f.number == 2) {
+                        let Some(method) = method.as_bytes() else {
+                            continue;
+                        };
+                        names.add(name(method).is_some_and(is_identifier));
+                        references.extend(strings(method, &[2, 3]));
+                    }
+                }
+                7 => {
+                    names.add(name(value).is_some_and(is_identifier));
+                    references.extend(strings(value, &[2, 6]));
+                }
+                12 if !matches!(value, b"proto2" | b"proto3" | b"editions") => syntax = 0.0,
+                _ => {}
+            }
+        }
+        // Bytes the wire format couldn't account for count against the record
+        fields.total += data.len() - position;
+
+        let mut type_names = Ratio::default();
+        for reference in references {
+            let qualified = reference.first() == Some(&b'.') && is_dotted(&reference[1..]);
+            // `.Order` for an `Order` of this file means the package is missing
+            let unpackaged =
+                !package.is_empty() && locals.contains(&reference[1.min(reference.len())..]);
+            type_names.add(qualified && !unpackaged);
+        }
+
+        Self {
+            fields: fields.score(),
+            names: names.score(),
+            type_names: type_names.score(),
+            syntax,
+        }
+    }
+
+    /// Returns the overall confidence, the product of the checks
+    pub fn score(&self) -> f32 {
+        self.fields * self.names * self.type_names * self.syntax
+    }
+}
+
+impl fmt::Display for Confidence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "fields {:.2}, names {:.2}, type names {:.2}, syntax {:.2}",
+            self.fields, self.names, self.type_names, self.syntax
+        )
+    }
+}
+
+/// Counts passed and total checks
+#[derive(Default)]
+struct Ratio {
+    passed: usize,
+    total: usize,
+}
+
+impl Ratio {
+    fn add(&mut self, passed: bool) {
+        self.passed += usize::from(passed);
+        self.total += 1;
+    }
+
+    fn score(&self) -> f32 {
+        if self.total == 0 {
+            1.0
+        } else {
+            self.passed as f32 / self.total as f32
+        }
+    }
+}
+
+/// Byte counts of the top-level fields
+#[derive(Default)]
+struct Fields {
+    valid: usize,
+    total: usize,
+    count: usize,
+}
+
+impl Fields {
+    fn add(&mut self, field: &WireField<'_>) {
+        let valid = match field.number {
+            1..=9 | 12 | 13 | 15 => field.wire_type == WireType::Len,
+            // Public and weak dependency indexes, packed or not
+            10 | 11 => matches!(field.wire_type, WireType::Varint | WireType::Len),
+            // Edition
+            14 => field.wire_type == WireType::Varint,
+            _ => false,
+        };
+        if valid {
+            self.valid += field.len;
+        }
+        self.total += field.len;
+        self.count += 1;
+    }
+
+    fn score(&self) -> f32 {
+        let share = if self.total == 0 {
+            0.0
+        } else {
+            self.valid as f32 / self.total as f32
+        };
+        if self.count == 1 {
+            share * LONE_NAME
+        } else {
+            share
+        }
+    }
+}
+
+/// Checks the names in a `DescriptorProto` and collects its type references
+fn message<'a>(data: &'a [u8], names: &mut Ratio, references: &mut Vec<&'a [u8]>) {
+    for field in fields(data) {
+        let Some(value) = field.as_bytes() else {
+            continue;
+        };
+        match field.number {
+            // Fields and extensions
+            2 | 6 => {
+                names.add(name(value).is_some_and(is_identifier));
+                references.extend(strings(value, &[2, 6]));
+            }
+            // Nested enums and oneofs
+            4 | 8 => names.add(name(value).is_some_and(is_identifier)),
+            _ => {}
+        }
+    }
+}
+
+/// Checks the names of the elements in field `number` of `data`
+fn children(data: &[u8], number: u32, names: &mut Ratio) {
+    for child in fields(data).filter(|f| f.number == number) {
+        names.add(child.as_bytes().and_then(name).is_some_and(is_identifier));
+    }
+}
+
+/// Returns the name (field 1) of a message
+fn name(data: &[u8]) -> Option<&[u8]> {
+    strings(data, &[1]).next()
+}
+
+/// Returns the length-delimited values of the given fields
+fn strings<'a>(data: &'a [u8], numbers: &'a [u32]) -> impl Iterator<Item = &'a [u8]> + 'a {
+    fields(data)
+        .filter(|f| numbers.contains(&f.number))
+        .filter_map(|f| f.as_bytes())
+}
+
+/// `[A-Za-z_][A-Za-z0-9_]*`
+fn is_identifier(name: &[u8]) -> bool {
+    match name.split_first() {
+        Some((first, rest)) => {
+            (first.is_ascii_alphabetic() || *first == b'_')
+                && rest.iter().all(|b| b.is_ascii_alphanumeric() || *b == b'_')
+        }
+        None => false,
+    }
+}
+
+/// Identifiers joined by dots, like a package name
+fn is_dotted(name: &[u8]) -> bool {
+    name.split(|&b| b == b'.').all(is_identifier)
+}
+
+/// A relative path of the characters seen in `.proto` file names
+fn is_path(name: &[u8]) -> bool {
+    !name.is_empty()
+        && name[0] != b'/'
+        && name
+            .iter()
+            .all(|b| b.is_ascii_alphanumeric() || b"_-./+@".contains(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost::Message;
+    use prost_types::{
+        DescriptorProto, FieldDescriptorProto, FileDescriptorProto, MethodDescriptorProto,
+        ServiceDescriptorProto,
+    };
+
+    fn descriptor() -> FileDescriptorProto {
+        let field = |name: &str, type_name: &str| FieldDescriptorProto {
+            name: Some(name.to_string()),
+            type_name: Some(type_name.to_string()),
+            ..Default::default()
+        };
+        FileDescriptorProto {
+            name: Some("acme/v1/orders.proto".to_string()),
+            package: Some("acme.v1".to_string()),
+            message_type: vec![
+                DescriptorProto {
+                    name: Some("Order".to_string()),
+                    field: vec![
+                        field("item", ".acme.v1.Item"),
+                        field("created", ".google.protobuf.Timestamp"),
+                    ],
+                    ..Default::default()
+                },
+                DescriptorProto {
+                    name: Some("Item".to_string()),
+                    ..Default::default()
+                },
+            ],
+            service: vec![ServiceDescriptorProto {
+                name: Some("Orders".to_string()),
+                method: vec![MethodDescriptorProto {
+                    name: Some("Get".to_string()),
+                    input_type: Some(".acme.v1.Item".to_string()),
+                    output_type: Some(".acme.v1.Order".to_string()),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            syntax: Some("proto3".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_descriptor() {
+        let confidence = Confidence::of(&descriptor().encode_to_vec());
+        assert_eq!(confidence.score(), 1.0, "{}", confidence);
+    }
+
+    #[test]
+    fn test_checks() {
+        // Junk after the descriptor: field 6 as a varint, and field 20
+        let mut data = descriptor().encode_to_vec();
+        let valid = data.len();
+        data.extend([0x30, 0x01, 0xa0, 0x01, 0x00]);
+        let confidence = Confidence::of(&data);
+        assert_eq!(confidence.fields, valid as f32 / data.len() as f32);
+        assert_eq!(confidence.names, 1.0);
+
+        let mut proto = descriptor();
+        proto.package = Some("acme v1".to_string());
+        proto.message_type[1].name = Some("1tem".to_string());
+        proto.syntax = Some("proto4".to_string());
+        let confidence = Confidence::of(&proto.encode_to_vec());
+        assert_eq!(confidence.names, 6.0 / 8.0);
+        assert_eq!(confidence.syntax, 0.0);
+        assert_eq!(confidence.score(), 0.0);
+
+        // A reference to a type of this file without the package
+        let mut proto = descriptor();
+        proto.service[0].method[0].input_type = Some(".Item".to_string());
+        proto.service[0].method[0].output_type = Some("Order".to_string());
+        let confidence = Confidence::of(&proto.encode_to_vec());
+        assert_eq!(confidence.type_names, 0.5);
+    }
+
+    #[test]
+    fn test_lone_name() {
+        let confidence = Confidence::of(b"\x0a\x09abc.proto");
+        assert_eq!(confidence.fields, LONE_NAME);
+        assert_eq!(confidence.names, 1.0);
+        assert_eq!(Confidence::of(b"\x0a\x09a c.proto").names, 0.0);
+        assert_eq!(Confidence::of(&[]).score(), 0.0);
+    }
+}
@@ -0,0 +1,392 @@
+//! dyld shared caches, which hold the system libraries of macOS and iOS.
+//!
+//! A cache is one main file, split into subcaches (`.01`, `.02`, ...,
+//! `.symbols`) since macOS 12 and iOS 15. The sections of each image are
+//! found in the cache files through the cache's mappings, so a record found
+//! at an offset in one of the files is attributed to the image it lies in,
+//! such as `/System/Library/Frameworks/Foo.framework/Versions/A/Foo`.
+
+use super::Location;
+use crate::error::{Error, Result};
+use object::macho::{self, DyldCacheHeader};
+use object::read::macho::DyldSubCacheSlice;
+use object::{Endianness, Object, ObjectSection, SectionFlags};
+use std::ops::Range;
+
+/// Start of the magic of every cache file, followed by the architecture
+const MAGIC: &[u8] = b"dyld_v1 ";
+
+/// Section types without data in the file
+const ZEROFILL: [u32; 3] = [
+    macho::S_ZEROFILL,
+    macho::S_GB_ZEROFILL,
+    macho::S_THREAD_LOCAL_ZEROFILL,
+];
+
+/// A range of addresses stored in a cache file
+#[derive(Debug, Clone)]
+struct Mapping {
+    address: u64,
+    size: u64,
+    offset: u64,
+}
+
+/// A section of an image, at its range in one cache file
+#[derive(Debug, Clone)]
+struct ImageSection {
+    file: Range<usize>,
+    /// Index of the image's path
+    image: usize,
+    name: Option<String>,
+    segment: Option<String>,
+    address: u64,
+    /// Holds data rather than code or link edit information
+    data: bool,
+}
+
+/// The images of a dyld shared cache, and where their sections lie in the
+/// cache files
+///
+/// Files are numbered as [`DyldCache::parse`] takes them: the main cache
+/// is 0, and the subcaches follow.
+#[derive(Debug, Clone)]
+pub struct DyldCache {
+    images: Vec<String>,
+    /// The sections in each file, sorted by offset
+    files: Vec<Vec<ImageSection>>,
+}
+
+impl DyldCache {
+    /// Returns the file name suffixes of the subcaches a main cache file
+    /// needs, such as `.01` or `.symbols`, in the order [`Self::parse`]
+    /// takes them
+    ///
+    /// Returns `None` if `data` isn't a dyld shared cache file, and no
+    /// suffixes for caches that aren't split and for subcaches.
+    pub fn subcache_suffixes(data: &[u8]) -> Option<Vec<String>> {
+        if !data.starts_with(MAGIC) {
+            return None;
+        }
+        let header = DyldCacheHeader::<Endianness>::parse(data).ok()?;
+        let (_, endian) = header.parse_magic().ok()?;
+        let mut suffixes: Vec<String> = match header.subcaches(endian, data).ok()? {
+            // Numbered from 1 before dyld-1042 (macOS 13)
+            Some(DyldSubCacheSlice::V1(entries)) => {
+                (1..=entries.len()).map(|i| format!(".{}", i)).collect()
+            }
+            Some(DyldSubCacheSlice::V2(entries)) => entries
+                .iter()
+                .map(|entry| {
+                    let suffix = &entry.file_suffix;
+                    let len = memchr::memchr(0, suffix).unwrap_or(suffix.len());
+                    String::from_utf8_lossy(&suffix[..len]).into_owned()
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        if header.symbols_subcache_uuid(endian).is_some() {
+            suffixes.push(".symbols".to_string());
+        }
+        Some(suffixes)
+    }
+
+    /// Parses a main cache file and its subcaches, in the order of
+    /// [`Self::subcache_suffixes`]
+    ///
+    /// Images whose headers don't parse are left out.
+    pub fn parse(data: &[u8], subcaches: &[&[u8]]) -> Result<Self> {
+        let invalid = |e: object::Error| Error::invalid_dyld_cache(e.to_string());
+        let cache = object::read::macho::DyldCache::<Endianness>::parse(data, subcaches)
+            .map_err(invalid)?;
+
+        let files: Vec<&[u8]> = std::iter::once(data)
+            .chain(subcaches.iter().copied())
+            .collect();
+        let mappings = files
+            .iter()
+            .map(|data| mappings(data).map_err(invalid))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut images = Vec::new();
+        let mut sections = vec![Vec::new(); files.len()];
+        for image in cache.images() {
+            let (Ok(path), Ok(object)) = (image.path(), image.parse_object()) else {
+                continue;
+            };
+            for section in object.sections() {
+                let SectionFlags::MachO { flags } = section.flags() else {
+                    continue;
+                };
+                if ZEROFILL.contains(&(flags & macho::SECTION_TYPE)) {
+                    continue;
+                }
+                let Some((file, start)) = file_offset(&mappings, section.address()) else {
+                    continue;
+                };
+                let len = files[file].len();
+                let end = usize::try_from(section.size())
+                    .ok()
+                    .and_then(|size| start.checked_add(size))
+                    .map_or(len, |end| end.min(len));
+                if start >= end {
+                    continue;
+                }
+
+                let segment = section.segment_name().ok().flatten();
+                let code = macho::S_ATTR_PURE_INSTRUCTIONS | macho::S_ATTR_SOME_INSTRUCTIONS;
+                sections[file].push(ImageSection {
+                    file: start..end,
+                    image: images.len(),
+                    name: section.name().ok().map(str::to_string),
+                    segment: segment.map(str::to_string),
+                    address: section.address(),
+                    data: flags & code == 0 && !matches!(segment, Some("__LINKEDIT" | "__DWARF")),
+                });
+            }
+            images.push(path.to_string());
+        }
+        for sections in &mut sections {
+            sections.sort_by_key(|section| section.file.start);
+        }
+
+        Ok(Self {
+            images,
+            files: sections,
+        })
+    }
+
+    /// Returns the install paths of the images, in the cache's order
+    pub fn images(&self) -> &[String] {
+        &self.images
+    }
+
+    /// Returns the ranges of `file` that hold image data, in order
+    ///
+    /// Code and link edit information are left out.
+    pub fn data_ranges(&self, file: usize) -> Vec<Range<usize>> {
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+        let sections = self.files.get(file).map_or(&[][..], Vec::as_slice);
+        for section in sections.iter().filter(|section| section.data) {
+            match ranges.last_mut() {
+                Some(last) if section.file.start <= last.end => {
+                    last.end = last.end.max(section.file.end);
+                }
+                _ => ranges.push(section.file.clone()),
+            }
+        }
+        ranges
+    }
+
+    /// Finds the image and section at an offset in `file`
+    ///
+    /// Returns `None` if the offset is in no image's section, such as in
+    /// the cache's own tables.
+    pub fn locate(&self, file: usize, offset: usize) -> Option<(&str, Location)> {
+        let sections = self.files.get(file)?;
+        let end = sections.partition_point(|section| section.file.start <= offset);
+        let section = sections[..end].last()?;
+        if !section.file.contains(&offset) {
+            return None;
+        }
+        let location = Location {
+            section: section.name.clone(),
+            segment: section.segment.clone(),
+            address: Some(section.address + (offset - section.file.start) as u64),
+            symbol: None,
+        };
+        Some((&self.images[section.image], location))
+    }
+}
+
+/// Reads the mappings of one cache file
+fn mappings(data: &[u8]) -> object::Result<Vec<Mapping>> {
+    let header = DyldCacheHeader::<Endianness>::parse(data)?;
+    let (_, endian) = header.parse_magic()?;
+    Ok(header
+        .mappings(endian, data)?
+        .iter()
+        .map(|mapping| Mapping {
+            address: mapping.address.get(endian),
+            size: mapping.size.get(endian),
+            offset: mapping.file_offset.get(endian),
+        })
+        .collect())
+}
+
+/// Finds the file that maps `address`, and the offset in it
+fn file_offset(files: &[Vec<Mapping>], address: u64) -> Option<(usize, usize)> {
+    files.iter().enumerate().find_map(|(file, mappings)| {
+        let mapping = mappings.iter().find(|mapping| {
+            address >= mapping.address && address - mapping.address < mapping.size
+        })?;
+        let offset = usize::try_from(mapping.offset + (address - mapping.address)).ok()?;
+        Some((file, offset))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::{Scanner, ScannerConfig};
+    use bytes::Bytes;
+
+    const HEADER_SIZE: usize = 0x1d0;
+    const FOO: &str = "/System/Library/Frameworks/Foo.framework/Versions/A/Foo";
+    const BAR: &str = "/usr/lib/libbar.dylib";
+
+    fn put(data: &mut Vec<u8>, at: usize, bytes: &[u8]) {
+        if data.len() < at + bytes.len() {
+            data.resize(at + bytes.len(), 0);
+        }
+        data[at..at + bytes.len()].copy_from_slice(bytes);
+    }
+
+    fn name(name: &str) -> [u8; 16] {
+        let mut bytes = [0; 16];
+        bytes[..name.len()].copy_from_slice(name.as_bytes());
+        bytes
+    }
+
+    /// A dylib of one page at `address`, with a `__TEXT,__text` section
+    /// of `code` and a `__TEXT,__const` one of `data`
+    fn dylib(address: u64, code: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut image = Vec::new();
+        for value in [0xfeed_facf_u32, 0x0100_0007, 3, 6, 1, 72 + 2 * 80, 0, 0] {
+            image.extend(value.to_le_bytes());
+        }
+        image.extend(0x19_u32.to_le_bytes());
+        image.extend((72 + 2 * 80_u32).to_le_bytes());
+        image.extend(name("__TEXT"));
+        for value in [address, 0x1000, 0, 0x1000] {
+            image.extend(value.to_le_bytes());
+        }
+        for value in [5_u32, 5, 2, 0] {
+            image.extend(value.to_le_bytes());
+        }
+        for (section, offset, len, flags) in [
+            ("__text", 0x200, code.len(), macho::S_ATTR_PURE_INSTRUCTIONS),
+            ("__const", 0x400, data.len(), 0),
+        ] {
+            image.extend(name(section));
+            image.extend(name("__TEXT"));
+            image.extend((address + offset).to_le_bytes());
+            image.extend((len as u64).to_le_bytes());
+            for value in [offset as u32, 0, 0, 0, flags, 0, 0, 0] {
+                image.extend(value.to_le_bytes());
+            }
+        }
+        put(&mut image, 0x200, code);
+        put(&mut image, 0x400, data);
+        image.resize(0x1000, 0);
+        image
+    }
+
+    /// The header of a cache file with one mapping
+    fn header(uuid: u8, mapping: [u64; 3]) -> Vec<u8> {
+        let mut data = Vec::new();
+        put(&mut data, 0, b"dyld_v1  x86_64\0");
+        put(&mut data, 0x10, &(HEADER_SIZE as u32).to_le_bytes());
+        put(&mut data, 0x14, &1_u32.to_le_bytes());
+        put(&mut data, 0x58, &[uuid; 16]);
+        for (i, value) in mapping.iter().enumerate() {
+            put(&mut data, HEADER_SIZE + i * 8, &value.to_le_bytes());
+        }
+        put(&mut data, HEADER_SIZE + 24, &[5, 0, 0, 0, 5, 0, 0, 0]);
+        data
+    }
+
+    /// A cache split in two files: Foo in the main one, after a stray
+    /// record, and Bar in `.01`
+    fn cache(foo: &[u8], bar: &[u8], stray: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let mut main = header(1, [0x1_8000_0000, 0x2000, 0]);
+        put(&mut main, 0x188, &0x300_u32.to_le_bytes());
+        put(&mut main, 0x18c, &1_u32.to_le_bytes());
+        put(&mut main, 0x1c0, &0x400_u32.to_le_bytes());
+        put(&mut main, 0x1c4, &2_u32.to_le_bytes());
+        // Subcache entry: uuid, VM offset and file suffix
+        put(&mut main, 0x300, &[2; 16]);
+        put(&mut main, 0x318, b".01");
+        for (i, (address, path)) in [(0x1_8000_1000_u64, FOO), (0x1_8000_4000, BAR)]
+            .iter()
+            .enumerate()
+        {
+            let at = 0x400 + i * 32;
+            put(&mut main, at, &address.to_le_bytes());
+            put(
+                &mut main,
+                at + 24,
+                &(0x500 + i as u32 * 0x100).to_le_bytes(),
+            );
+            put(&mut main, 0x500 + i * 0x100, path.as_bytes());
+        }
+        put(&mut main, 0x800, stray);
+        put(&mut main, 0x1000, &dylib(0x1_8000_1000, b"\x90\x90", foo));
+
+        let mut sub = header(2, [0x1_8000_4000, 0x1000, 0x1000]);
+        put(&mut sub, 0x1000, &dylib(0x1_8000_4000, bar, &[0xff; 16]));
+        (main, sub)
+    }
+
+    fn record(name: &str) -> Vec<u8> {
+        let mut record = vec![0x0a, name.len() as u8];
+        record.extend_from_slice(name.as_bytes());
+        record.extend_from_slice(b"\x12\x03foo\x62\x06proto3\0");
+        record
+    }
+
+    #[test]
+    fn test_subcache_suffixes() {
+        let (main, sub) = cache(&record("a.proto"), &record("b.proto"), &[]);
+        assert_eq!(DyldCache::subcache_suffixes(&main).unwrap(), [".01"]);
+        assert!(DyldCache::subcache_suffixes(&sub).unwrap().is_empty());
+        assert!(DyldCache::subcache_suffixes(b"\xcf\xfa\xed\xfe").is_none());
+
+        // The subcache is needed
+        assert!(DyldCache::parse(&main, &[]).is_err());
+    }
+
+    #[test]
+    fn test_locate_images() {
+        let (main, sub) = cache(&record("a.proto"), &record("b.proto"), &record("c.proto"));
+        let cache = DyldCache::parse(&main, &[&sub]).unwrap();
+        assert_eq!(cache.images(), [FOO, BAR]);
+
+        let (image, location) = cache.locate(0, 0x1402).unwrap();
+        assert_eq!(image, FOO);
+        assert_eq!(location.to_string(), "__TEXT,__const at 0x180001402");
+        let (image, location) = cache.locate(1, 0x1200).unwrap();
+        assert_eq!(image, BAR);
+        assert_eq!(location.section.as_deref(), Some("__text"));
+        assert!(cache.locate(0, 0x800).is_none());
+        assert!(cache.locate(2, 0).is_none());
+
+        assert_eq!(cache.data_ranges(0).len(), 1);
+        assert!(cache.data_ranges(0)[0].contains(&0x1400));
+    }
+
+    #[test]
+    fn test_scan_dyld_file() {
+        let (main, sub) = cache(&record("a.proto"), &record("b.proto"), &record("c.proto"));
+        let cache = DyldCache::parse(&main, &[&sub]).unwrap();
+        let scanner = Scanner::new();
+
+        let results: Vec<_> = scanner
+            .scan_dyld_file(&cache, 0, Bytes::from(main))
+            .collect();
+        let images: Vec<_> = results.iter().map(|r| r.image.as_deref()).collect();
+        assert_eq!(images, [None, Some(FOO)]);
+        assert_eq!(results[1].range.start, 0x1400);
+        let location = results[1].location.as_ref().unwrap();
+        assert_eq!(location.address, Some(0x1_8000_1400));
+
+        let sub = Bytes::from(sub);
+        let results: Vec<_> = scanner.scan_dyld_file(&cache, 1, sub.clone()).collect();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].image.as_deref(), Some(BAR));
+
+        // Bar's record is in its code
+        let config = ScannerConfig::new().data_sections_only(true);
+        let scanner = Scanner::with_config(config);
+        assert_eq!(scanner.scan_dyld_file(&cache, 1, sub).count(), 0);
+    }
+}
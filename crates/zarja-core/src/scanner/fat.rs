@@ -0,0 +1,207 @@
+//! Universal (fat) Mach-O binaries, which hold one Mach-O file per
+//! architecture.
+//!
+//! Each slice is scanned on its own, so ranges and locations refer to the
+//! slice, and a descriptor that every architecture embeds is reported once
+//! with the list of slices it was found in.
+
+#[cfg(feature = "object-files")]
+use super::Location;
+use std::ops::Range;
+
+/// Magic of a universal binary with 32-bit offsets, stored big-endian
+const FAT_MAGIC: u32 = 0xcafe_babe;
+
+/// Magic of a universal binary with 64-bit offsets
+const FAT_MAGIC_64: u32 = 0xcafe_babf;
+
+/// More architectures than a universal binary holds; Java class files,
+/// which share the magic, have their version of at least 45 here
+const MAX_SLICES: u32 = 30;
+
+/// Mask of the capability bits of a CPU subtype
+const CPU_SUBTYPE_MASK: u32 = 0xff00_0000;
+
+/// One architecture's slice of a universal binary
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FatSlice {
+    /// Architecture name, such as `arm64`, `arm64e` or `x86_64`
+    pub arch: String,
+    /// Byte range of the slice's Mach-O file in the universal binary
+    pub range: Range<usize>,
+}
+
+/// Where a result was found in one slice of a universal binary
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SliceMatch {
+    /// The slice's architecture, see [`FatSlice::arch`]
+    pub arch: String,
+    /// Byte range of the data in the slice
+    pub range: Range<usize>,
+    /// Where the data lies in the slice's Mach-O file
+    #[cfg(feature = "object-files")]
+    pub location: Option<Location>,
+}
+
+/// Returns the slices of a universal binary, in header order
+///
+/// Returns `None` for other inputs, and for headers with slices that
+/// overlap the header or run past the end of the input.
+pub fn fat_slices(data: &[u8]) -> Option<Vec<FatSlice>> {
+    let word = |at: usize| -> Option<u32> {
+        Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+    };
+    let double = |at: usize| -> Option<u64> {
+        Some(u64::from_be_bytes(data.get(at..at + 8)?.try_into().ok()?))
+    };
+
+    let wide = match word(0)? {
+        FAT_MAGIC => false,
+        FAT_MAGIC_64 => true,
+        _ => return None,
+    };
+    let count = word(4)?;
+    if count == 0 || count > MAX_SLICES {
+        return None;
+    }
+
+    let entry = if wide { 32 } else { 20 };
+    let header = 8 + count as usize * entry;
+    (0..count as usize)
+        .map(|i| {
+            let at = 8 + i * entry;
+            let (offset, size) = if wide {
+                (double(at + 8)?, double(at + 16)?)
+            } else {
+                (u64::from(word(at + 8)?), u64::from(word(at + 12)?))
+            };
+            let start = usize::try_from(offset).ok()?;
+            let end = start.checked_add(usize::try_from(size).ok()?)?;
+            if start < header || start >= end || end > data.len() {
+                return None;
+            }
+            Some(FatSlice {
+                arch: arch_name(word(at)?, word(at + 4)?),
+                range: start..end,
+            })
+        })
+        .collect()
+}
+
+/// Names an architecture as `lipo` does
+fn arch_name(cputype: u32, subtype: u32) -> String {
+    let name = match (cputype, subtype & !CPU_SUBTYPE_MASK) {
+        (7, _) => "i386",
+        (0x0100_0007, 8) => "x86_64h",
+        (0x0100_0007, _) => "x86_64",
+        (12, 6) => "armv6",
+        (12, 9) => "armv7",
+        (12, 11) => "armv7s",
+        (12, 12) => "armv7k",
+        (12, _) => "arm",
+        (0x0100_000c, 2) => "arm64e",
+        (0x0100_000c, _) => "arm64",
+        (0x0200_000c, _) => "arm64_32",
+        (18, _) => "ppc",
+        (0x0100_0012, _) => "ppc64",
+        (cputype, subtype) => return format!("cpu{}.{}", cputype, subtype),
+    };
+    name.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::{ScanStrategy, Scanner};
+    use bytes::Bytes;
+    use prost::Message;
+
+    fn descriptor(name: &str) -> Vec<u8> {
+        let mut record = prost_types::FileDescriptorProto {
+            name: Some(name.to_string()),
+            package: Some("test".to_string()),
+            syntax: Some("proto3".to_string()),
+            ..Default::default()
+        }
+        .encode_to_vec();
+        // Ends the record
+        record.push(0);
+        record
+    }
+
+    /// A universal binary holding `slices` of (cputype, subtype, data)
+    fn fat(slices: &[(u32, u32, &[u8])]) -> Vec<u8> {
+        let mut data = FAT_MAGIC.to_be_bytes().to_vec();
+        data.extend((slices.len() as u32).to_be_bytes());
+        let mut offset = 4096;
+        let mut contents = Vec::new();
+        for (cputype, subtype, slice) in slices {
+            for value in [*cputype, *subtype, offset, slice.len() as u32, 12] {
+                data.extend(value.to_be_bytes());
+            }
+            contents.push((offset as usize, *slice));
+            offset += (slice.len() as u32).next_multiple_of(4096);
+        }
+        for (offset, slice) in contents {
+            data.resize(offset, 0);
+            data.extend_from_slice(slice);
+        }
+        data
+    }
+
+    #[test]
+    fn test_fat_slices() {
+        let data = fat(&[
+            (0x0100_0007, 3, b"x86 slice"),
+            (0x0100_000c, 0x8000_0002, b"arm64e slice"),
+        ]);
+        let slices = fat_slices(&data).unwrap();
+        let archs: Vec<_> = slices.iter().map(|s| s.arch.as_str()).collect();
+        assert_eq!(archs, ["x86_64", "arm64e"]);
+        assert_eq!(&data[slices[1].range.clone()], b"arm64e slice");
+
+        // Cut short, and a Java class file (version 52)
+        assert!(fat_slices(&data[..4200]).is_none());
+        assert!(fat_slices(b"\xca\xfe\xba\xbe\x00\x00\x00\x34").is_none());
+        assert!(fat_slices(b"\x0a\x09foo.proto").is_none());
+    }
+
+    #[test]
+    fn test_scan_fat() {
+        let (shared, x86, arm) = (
+            descriptor("a.proto"),
+            descriptor("b.proto"),
+            descriptor("c.proto"),
+        );
+        let x86_slice = [&b"x86"[..], &shared, &x86].concat();
+        let arm_slice = [&b"arm64 slice"[..], &arm, &shared].concat();
+        let data = fat(&[(0x0100_0007, 3, &x86_slice), (0x0100_000c, 0, &arm_slice)]);
+
+        let results = Scanner::new().scan(&data).unwrap();
+        let found: Vec<_> = results
+            .iter()
+            .map(|r| {
+                let archs: Vec<_> = r.slices.iter().map(|s| s.arch.as_str()).collect();
+                (&data[r.range.clone()], archs)
+            })
+            .collect();
+        let len = shared.len() - 1;
+        assert_eq!(
+            found,
+            [
+                (&shared[..len], vec!["x86_64", "arm64"]),
+                (&x86[..len], vec!["x86_64"]),
+                (&arm[..len], vec!["arm64"]),
+            ]
+        );
+
+        // Ranges in each slice, and in the file for the first
+        let slices = &results[0].slices;
+        assert_eq!(slices[0].range, 3..3 + len);
+        assert_eq!(slices[1].range, 11 + arm.len()..11 + arm.len() + len);
+        assert_eq!(results[0].range, 4096 + 3..4096 + 3 + len);
+
+        let shared = Scanner::new().scan_bytes(Bytes::from(data)).count();
+        assert_eq!(shared, 3);
+    }
+}
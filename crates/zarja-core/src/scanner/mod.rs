@@ -5,10 +5,45 @@
 //!
 //! ## Algorithm Overview
 //!
-//! 1. Search for the `.proto` byte sequence in the binary
-//! 2. Backtrack to find the magic byte `0x0A` (field 1, wire type LEN)
+//! 1. Search for file name suffixes (`.proto`, `.protodevel`) in the binary
+//! 2. Check the length prefixes that could end there for the magic byte
+//!    `0x0A` (field 1, wire type LEN)
 //! 3. Parse forward using protobuf wire format to find record boundaries
 //! 4. Extract the complete `FileDescriptorProto` bytes
+//! 5. Score the record's structure and drop it below the
+//!    [`ScannerConfig::min_confidence`] threshold
+//!
+//! ## Large inputs
+//!
+//! [`Scanner::scan_bytes`] yields results lazily as slices of a shared
+//! [`Bytes`] buffer, such as a file mapped with [`map_file`], without
+//! copying them. [`Scanner::scan_reader`] scans a `Read + Seek` source
+//! through a window of about [`ScannerConfig::max_descriptor_size`] bytes.
+//!
+//! In-memory inputs larger than [`ScannerConfig::chunk_size`] are split
+//! into chunks that are searched on [`ScannerConfig::threads`] threads,
+//! or on the idle threads of a [`ThreadBudget`] shared by several scans.
+//! Results are the same as those of one pass over the input.
+//!
+//! ## Object files
+//!
+//! With the `object-files` feature, in-memory ELF, Mach-O and PE inputs
+//! are parsed into an [`ObjectMap`], and each result carries its
+//! [`Location`]: section, virtual address and nearest symbol.
+//! [`ScannerConfig::data_sections_only`] limits the search to sections
+//! that hold data.
+//!
+//! ## Universal binaries
+//!
+//! Each slice of a universal (fat) Mach-O binary is scanned on its own.
+//! Identical records found in several slices are reported once, listing
+//! every slice in [`ScanResult::slices`].
+//!
+//! ## dyld shared caches
+//!
+//! With the `object-files` feature, [`DyldCache`] maps the files of a dyld
+//! shared cache to the images in them, and [`Scanner::scan_dyld_file`]
+//! attributes each result to its image.
 //!
 //! ## Extensibility
 //!
@@ -28,16 +63,41 @@
 //! }
 //! ```
 
+mod confidence;
+#[cfg(feature = "object-files")]
+mod dyld;
+mod fat;
+#[cfg(feature = "object-files")]
+mod object;
+mod parallel;
+mod search;
+mod stream;
 mod wire;
 
+use crate::diagnostics::{Diagnostic, Stage};
 use crate::error::{Error, Result};
+use bytes::Bytes;
+use search::{record_start, SuffixSearch};
 use std::ops::Range;
+use std::sync::Arc;
 use tracing::{debug, trace};
 
-pub use wire::{WireType, decode_varint, consume_field, consume_fields, MAX_VALID_NUMBER};
+pub use confidence::Confidence;
+#[cfg(feature = "object-files")]
+pub use dyld::DyldCache;
+pub use fat::{fat_slices, FatSlice, SliceMatch};
+#[cfg(feature = "object-files")]
+pub use object::{Location, NearSymbol, ObjectMap};
+pub use parallel::ThreadBudget;
+pub use stream::{map_file, ReaderScan, ScanIter};
+
+pub use wire::{
+    consume_field, consume_fields, decode_varint, fields, read_field, WireField, WireType,
+    MAX_VALID_NUMBER,
+};
 
-/// Pattern to search for in binaries (filename suffix)
-const PROTO_SUFFIX: &[u8] = b".proto";
+/// File name suffixes searched for by default
+const DEFAULT_SUFFIXES: &[&[u8]] = &[b".proto", b".protodevel"];
 
 /// Magic byte indicating start of FileDescriptorProto
 /// This is field 1 (name) with wire type 2 (LEN): (1 << 3) | 2 = 0x0A
@@ -46,16 +106,41 @@ const MAGIC_BYTE: u8 = 0x0A;
 /// Result of scanning a binary for a single descriptor
 #[derive(Debug, Clone)]
 pub struct ScanResult {
-    /// The raw bytes of the FileDescriptorProto
-    pub data: Vec<u8>,
+    /// The raw bytes of the FileDescriptorProto, sharing the scanned
+    /// buffer where possible
+    pub data: Bytes,
     /// Byte range in the original input where this was found
     pub range: Range<usize>,
+    /// How much the data looks like a descriptor, from 0.0 to 1.0; see
+    /// [`Confidence`]
+    pub confidence: f32,
+    /// Where the data lies in an object file, if the input is one
+    #[cfg(feature = "object-files")]
+    pub location: Option<Location>,
+    /// Install path of the dyld shared cache image the data lies in
+    #[cfg(feature = "object-files")]
+    pub image: Option<String>,
+    /// The slices of a universal binary the data was found in, in header
+    /// order; empty for other inputs. `range` is then the first slice's
+    /// range in the whole file, and `location` its location in the slice.
+    pub slices: Vec<SliceMatch>,
 }
 
 impl ScanResult {
-    /// Creates a new scan result
-    pub fn new(data: Vec<u8>, range: Range<usize>) -> Self {
-        Self { data, range }
+    /// Creates a new scan result, scoring the data
+    pub fn new(data: impl Into<Bytes>, range: Range<usize>) -> Self {
+        let data = data.into();
+        let confidence = Confidence::of(&data).score();
+        Self {
+            data,
+            range,
+            confidence,
+            #[cfg(feature = "object-files")]
+            location: None,
+            #[cfg(feature = "object-files")]
+            image: None,
+            slices: Vec::new(),
+        }
     }
 
     /// Returns the data as a slice
@@ -73,6 +158,24 @@ pub struct ScannerConfig {
     pub min_descriptor_size: usize,
     /// Maximum size for a valid descriptor (filters garbage)
     pub max_descriptor_size: usize,
+    /// Minimum [`Confidence`] score for a valid descriptor (filters
+    /// strings and other noise that parse as wire fields)
+    pub min_confidence: f32,
+    /// File name suffixes that mark a candidate descriptor
+    pub suffixes: Vec<Vec<u8>>,
+    /// Threads to scan one input on (0 = one per CPU)
+    pub threads: usize,
+    /// Threads shared with other scans, used instead of `threads`
+    pub budget: Option<Arc<ThreadBudget>>,
+    /// Bytes of input per task when scanning on several threads; smaller
+    /// inputs are scanned on the calling thread
+    pub chunk_size: usize,
+    /// Locate results in the sections of object files
+    #[cfg(feature = "object-files")]
+    pub locate: bool,
+    /// Only search the data sections of object files
+    #[cfg(feature = "object-files")]
+    pub data_sections_only: bool,
 }
 
 impl Default for ScannerConfig {
@@ -81,6 +184,15 @@ impl Default for ScannerConfig {
             max_results: 0,
             min_descriptor_size: 10,
             max_descriptor_size: 10 * 1024 * 1024, // 10 MB
+            min_confidence: 0.5,
+            suffixes: DEFAULT_SUFFIXES.iter().map(|s| s.to_vec()).collect(),
+            threads: 0,
+            budget: None,
+            chunk_size: 16 * 1024 * 1024, // 16 MB
+            #[cfg(feature = "object-files")]
+            locate: true,
+            #[cfg(feature = "object-files")]
+            data_sections_only: false,
         }
     }
 }
@@ -108,6 +220,57 @@ impl ScannerConfig {
         self.max_descriptor_size = size;
         self
     }
+
+    /// Sets the minimum confidence filter (0.0 keeps every record)
+    pub fn min_confidence(mut self, score: f32) -> Self {
+        self.min_confidence = score;
+        self
+    }
+
+    /// Sets the file name suffixes to search for, replacing the defaults
+    pub fn suffixes<S: Into<Vec<u8>>>(mut self, suffixes: impl IntoIterator<Item = S>) -> Self {
+        self.suffixes = suffixes.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the number of threads to scan one input on (0 = one per CPU)
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Sets a thread budget shared with other scans
+    ///
+    /// Inputs larger than one chunk are then searched on the calling
+    /// thread, plus any thread of the budget that becomes idle during the
+    /// scan.
+    pub fn budget(mut self, budget: Arc<ThreadBudget>) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Sets the bytes of input per task when scanning on several threads
+    pub fn chunk_size(mut self, size: usize) -> Self {
+        self.chunk_size = size.max(1);
+        self
+    }
+
+    /// Sets whether results are located in the sections of object files
+    #[cfg(feature = "object-files")]
+    pub fn locate(mut self, locate: bool) -> Self {
+        self.locate = locate;
+        self
+    }
+
+    /// Sets whether only the data sections of object files are searched
+    ///
+    /// Inputs that aren't object files, or have no data sections, are
+    /// searched in full.
+    #[cfg(feature = "object-files")]
+    pub fn data_sections_only(mut self, only: bool) -> Self {
+        self.data_sections_only = only;
+        self
+    }
 }
 
 /// Trait for implementing custom scanning strategies
@@ -119,6 +282,9 @@ pub trait ScanStrategy: Send + Sync {
     fn scan(&self, data: &[u8]) -> Result<Vec<ScanResult>>;
 
     /// Scan the data and return an iterator (for streaming large files)
+    ///
+    /// The default implementation collects [`Self::scan`]; [`Scanner`]
+    /// searches as the iterator advances.
     fn scan_iter<'a>(&'a self, data: &'a [u8]) -> Box<dyn Iterator<Item = Result<ScanResult>> + 'a> {
         // Default implementation: collect all results into a vec and iterate
         match self.scan(data) {
@@ -132,6 +298,7 @@ pub trait ScanStrategy: Send + Sync {
 #[derive(Debug, Clone)]
 pub struct Scanner {
     config: ScannerConfig,
+    search: SuffixSearch,
 }
 
 impl Default for Scanner {
@@ -143,26 +310,86 @@ impl Default for Scanner {
 impl Scanner {
     /// Creates a new scanner with default configuration
     pub fn new() -> Self {
-        Self {
-            config: ScannerConfig::default(),
-        }
+        Self::with_config(ScannerConfig::default())
     }
 
     /// Creates a new scanner with custom configuration
     pub fn with_config(config: ScannerConfig) -> Self {
-        Self { config }
+        let search = SuffixSearch::new(&config.suffixes);
+        Self { config, search }
+    }
+
+    /// Scans the data, also returning why each rejected candidate was
+    /// dropped
+    ///
+    /// Every `.proto` suffix that doesn't lead to a result gets a
+    /// [`Diagnostic`] at the [`Stage::Backtrack`], [`Stage::Consume`] or
+    /// [`Stage::Confidence`] stage. Most of them are ordinary strings rather than descriptors.
+    pub fn scan_with_diagnostics(&self, data: &[u8]) -> Result<(Vec<ScanResult>, Vec<Diagnostic>)> {
+        let mut iter = ScanIter::borrowed(self, data).with_diagnostics();
+        let results = iter.by_ref().collect();
+        Ok((results, iter.take_diagnostics()))
+    }
+
+    /// Scans a shared buffer, yielding results as they are found
+    ///
+    /// Each result's data is a slice of `data`, not a copy.
+    ///
+    /// ```
+    /// use bytes::Bytes;
+    /// use zarja_core::Scanner;
+    ///
+    /// let data = Bytes::from_static(b"\x0a\x0bhello.proto\x12\x05hello");
+    /// let scanner = Scanner::new();
+    /// let results: Vec<_> = scanner.scan_bytes(data).collect();
+    /// assert_eq!(results[0].range, 0..20);
+    /// ```
+    pub fn scan_bytes(&self, data: Bytes) -> ScanIter<'_> {
+        ScanIter::shared(self, data)
+    }
+
+    /// Scans one file of a dyld shared cache, numbered as in `cache`
+    ///
+    /// Each result found in an image's section carries the image's path,
+    /// and its location unless [`ScannerConfig::locate`] is off.
+    /// [`ScannerConfig::data_sections_only`] limits the search to the
+    /// images' data.
+    #[cfg(feature = "object-files")]
+    pub fn scan_dyld_file<'a>(
+        &'a self,
+        cache: &'a DyldCache,
+        file: usize,
+        data: Bytes,
+    ) -> ScanIter<'a> {
+        ScanIter::dyld(self, cache, file, data)
+    }
+
+    /// Scans a reader from its current position, reading as the iterator
+    /// advances
+    ///
+    /// Result ranges are offsets in the source, so a reader positioned
+    /// partway into a file reports offsets in the file. Results aren't
+    /// located in object files, nor universal binaries split into slices,
+    /// which takes the whole input.
+    pub fn scan_reader<R>(&self, reader: R) -> Result<ReaderScan<'_, R>>
+    where
+        R: std::io::Read + std::io::Seek,
+    {
+        ReaderScan::new(self, reader)
     }
 
     /// Consumes protobuf fields starting from the given position
-    /// Returns the number of bytes consumed for the complete record
-    fn consume_record(&self, data: &[u8], start: usize) -> Result<usize> {
+    ///
+    /// Returns the number of bytes consumed for the complete record, and
+    /// the error that ended it if it stopped at invalid wire data.
+    fn consume_record(&self, data: &[u8], start: usize) -> (usize, Option<Error>) {
         let mut position = start;
         let mut consumed_field_one = false;
 
         loop {
             if position >= data.len() {
                 // Reached end of data, return what we have
-                return Ok(position - start);
+                return (position - start, None);
             }
 
             match consume_field(&data[position..]) {
@@ -175,7 +402,7 @@ impl Scanner {
                                 "Found adjacent descriptor at position {}",
                                 position
                             );
-                            return Ok(position - start);
+                            return (position - start, None);
                         }
                         consumed_field_one = true;
                     }
@@ -184,136 +411,223 @@ impl Scanner {
 
                     // Safety check: don't exceed data bounds
                     if position > data.len() {
-                        return Ok(data.len() - start);
+                        return (data.len() - start, None);
                     }
                 }
-                Err(_) => {
+                Err(e) => {
                     // Hit invalid data, return what we have so far
-                    return Ok(position - start);
+                    return (position - start, Some(e));
                 }
             }
         }
     }
+}
 
-    /// Find the start of a FileDescriptorProto by backtracking from a `.proto` match
-    fn find_record_start(&self, data: &[u8], proto_suffix_pos: usize) -> Option<usize> {
-        // We need to backtrack to find the 0x0A byte that starts the record
-        // The structure is: 0x0A [varint length] [filename bytes ending in .proto]
+impl ScanStrategy for Scanner {
+    fn scan(&self, data: &[u8]) -> Result<Vec<ScanResult>> {
+        Ok(ScanIter::borrowed(self, data).collect())
+    }
 
-        // The .proto suffix is at proto_suffix_pos, so the filename ends at proto_suffix_pos + 6
-        // We need to find where the filename starts
+    fn scan_iter<'a>(
+        &'a self,
+        data: &'a [u8],
+    ) -> Box<dyn Iterator<Item = Result<ScanResult>> + 'a> {
+        Box::new(ScanIter::borrowed(self, data).map(Ok))
+    }
+}
 
-        // Search backwards for the magic byte
-        let search_start = proto_suffix_pos.saturating_sub(256); // Filenames shouldn't be longer than 256 bytes
+/// Where a search through the input stands
+#[derive(Debug, Clone, Default)]
+struct Search {
+    /// Offset in the window to search from
+    position: usize,
+    /// Results found so far
+    found: usize,
+}
 
-        for i in (search_start..proto_suffix_pos).rev() {
-            if data[i] == MAGIC_BYTE {
-                // Verify this is a valid length-prefixed string
-                if i + 1 < data.len() {
-                    // Try to decode the length varint
-                    if let Ok((length, varint_len)) = decode_varint(&data[i + 1..]) {
-                        let expected_end = i + 1 + varint_len + length as usize;
-                        let actual_end = proto_suffix_pos + PROTO_SUFFIX.len();
+/// Outcome of a [`Scanner::judge`]
+#[derive(Debug)]
+enum Verdict {
+    /// An accepted record, at this range of the window, with its score
+    Found(Range<usize>, f32),
+    /// Not a descriptor
+    Rejected,
+    /// The window ends before the record could be decided
+    NeedMore,
+}
 
-                        // Check if this length matches our .proto position
-                        if expected_end == actual_end {
-                            return Some(i);
-                        }
+/// Outcome of a [`Scanner::step`]
+#[derive(Debug)]
+enum Step {
+    /// An accepted record, at this range of the window, with its score
+    Found(Range<usize>, f32),
+    /// The window ends before the next record could be decided
+    NeedMore,
+    /// No more records
+    Done,
+}
 
-                        // Edge case: filename is exactly 10 chars, 0x0A might be the length
-                        if length == 10 && i > 0 && data[i - 1] == MAGIC_BYTE {
-                            return Some(i - 1);
-                        }
-                    }
+impl Scanner {
+    /// Searches `window` from `search.position` for the next accepted record
+    ///
+    /// `base` is the window's offset in the input, for reported offsets.
+    /// Unless the window is `complete`, reaching the end of the input, a
+    /// record is only decided once the window holds more than
+    /// `max_descriptor_size` bytes from its start, so that it is decided
+    /// as it would be on the whole input.
+    fn step(
+        &self,
+        window: &[u8],
+        base: usize,
+        search: &mut Search,
+        complete: bool,
+        mut diagnostics: Option<&mut Vec<Diagnostic>>,
+    ) -> Step {
+        if self.config.max_results > 0 && search.found >= self.config.max_results {
+            return Step::Done;
+        }
+
+        while search.position < window.len() {
+            // Find the next file name suffix
+            let remaining = &window[search.position..];
+            let Some(suffix) = self.search.find(remaining) else {
+                break;
+            };
+
+            let suffix = search.position + suffix.start..search.position + suffix.end;
+            match self.judge(
+                window,
+                base,
+                suffix.clone(),
+                complete,
+                diagnostics.as_deref_mut(),
+            ) {
+                Verdict::Found(range, score) => {
+                    // Skip past this record
+                    search.position = range.end;
+                    search.found += 1;
+                    return Step::Found(range, score);
+                }
+                // Move past this suffix and continue searching
+                Verdict::Rejected => search.position = suffix.end,
+                Verdict::NeedMore => {
+                    // Come back to this suffix with more of the input
+                    search.position = suffix.start;
+                    return Step::NeedMore;
                 }
             }
         }
 
-        None
+        if complete {
+            search.position = window.len();
+            return Step::Done;
+        }
+        // Keep the start of a suffix that the next window may complete
+        search.position = search.position.max(
+            window
+                .len()
+                .saturating_sub(self.search.longest().saturating_sub(1)),
+        );
+        Step::NeedMore
     }
-}
-
-impl ScanStrategy for Scanner {
-    fn scan(&self, data: &[u8]) -> Result<Vec<ScanResult>> {
-        let mut results = Vec::new();
-        let mut position = 0;
 
-        debug!("Starting scan of {} bytes", data.len());
-
-        while position < data.len() {
-            // Find next occurrence of ".proto"
-            let remaining = &data[position..];
-            let proto_pos = find_subsequence(remaining, PROTO_SUFFIX);
-
-            let Some(relative_pos) = proto_pos else {
-                break;
-            };
-
-            let absolute_pos = position + relative_pos;
-            trace!("Found .proto suffix at position {}", absolute_pos);
-
-            // Try to find the record start
-            if let Some(record_start) = self.find_record_start(data, absolute_pos) {
-                trace!("Found record start at position {}", record_start);
-
-                // Consume the complete record
-                match self.consume_record(data, record_start) {
-                    Ok(record_len) => {
-                        // Apply size filters
-                        if record_len >= self.config.min_descriptor_size
-                            && record_len <= self.config.max_descriptor_size
-                        {
-                            let record_data = data[record_start..record_start + record_len].to_vec();
-                            let range = record_start..record_start + record_len;
-
-                            debug!(
-                                "Found descriptor at {}..{} ({} bytes)",
-                                range.start, range.end, record_len
-                            );
+    /// Decides the record whose file name ends with the `suffix` range of
+    /// `window`
+    ///
+    /// Depends on nothing but the input around the suffix, so suffixes can
+    /// be judged in any order; see [`Self::step`] for `complete`.
+    fn judge(
+        &self,
+        window: &[u8],
+        base: usize,
+        suffix: Range<usize>,
+        complete: bool,
+        diagnostics: Option<&mut Vec<Diagnostic>>,
+    ) -> Verdict {
+        trace!("Found suffix at position {}", base + suffix.start);
+
+        // Try to find the record start
+        let Some(record_start) = record_start(window, suffix.end, suffix.len()) else {
+            if let Some(diagnostics) = diagnostics {
+                let error = Error::invalid_wire_format(
+                    base + suffix.start,
+                    "no field 1 tag ends at the suffix",
+                );
+                diagnostics.push(
+                    Diagnostic::new(Stage::Backtrack, &error, window, suffix.start)
+                        .with_offset(base + suffix.start),
+                );
+            }
+            return Verdict::Rejected;
+        };
+        trace!("Found record start at position {}", base + record_start);
 
-                            results.push(ScanResult::new(record_data, range));
+        if !complete && window.len() - record_start <= self.config.max_descriptor_size {
+            return Verdict::NeedMore;
+        }
 
-                            // Check if we've hit the limit
-                            if self.config.max_results > 0
-                                && results.len() >= self.config.max_results
-                            {
-                                break;
-                            }
+        // Consume the complete record
+        let (record_len, stopped_at) = self.consume_record(window, record_start);
+
+        // Apply size filters
+        let range = record_start..record_start + record_len;
+        let sized = record_len >= self.config.min_descriptor_size
+            && record_len <= self.config.max_descriptor_size;
+        let confidence = sized.then(|| Confidence::of(&window[range.clone()]));
+        let score = confidence.map_or(0.0, |c| c.score());
+
+        if sized && score >= self.config.min_confidence {
+            debug!(
+                "Found descriptor at {}..{} ({} bytes, confidence {:.2})",
+                base + range.start,
+                base + range.end,
+                record_len,
+                score
+            );
+            return Verdict::Found(range, score);
+        }
 
-                            // Skip past this record
-                            position = record_start + record_len;
-                            continue;
-                        }
-                    }
-                    Err(e) => {
-                        trace!("Failed to consume record: {}", e);
-                    }
+        if let Some(diagnostics) = diagnostics {
+            // A record cut short by bad wire data is reported as such
+            let (stage, error) = match (confidence, stopped_at) {
+                (Some(confidence), _) => (
+                    Stage::Confidence,
+                    Error::LowConfidence {
+                        offset: base + record_start,
+                        score,
+                        min: self.config.min_confidence,
+                        checks: confidence.to_string(),
+                    },
+                ),
+                (None, Some(error)) if record_len < self.config.min_descriptor_size => {
+                    (Stage::Consume, error)
                 }
-            }
-
-            // Move past this .proto occurrence and continue searching
-            position = absolute_pos + PROTO_SUFFIX.len();
+                (None, _) => (
+                    Stage::Consume,
+                    Error::RecordSize {
+                        offset: base + record_start,
+                        size: record_len,
+                        min: self.config.min_descriptor_size,
+                        max: self.config.max_descriptor_size,
+                    },
+                ),
+            };
+            trace!("Rejected record at {}: {}", base + record_start, error);
+            diagnostics.push(
+                Diagnostic::new(stage, &error, window, record_start)
+                    .with_offset(base + record_start),
+            );
         }
 
-        debug!("Scan complete: found {} descriptors", results.len());
-        Ok(results)
+        Verdict::Rejected
     }
 }
 
-/// Find a subsequence within a byte slice
-fn find_subsequence(haystack: &[u8], needle: &[u8]) -> Option<usize> {
-    haystack
-        .windows(needle.len())
-        .position(|window| window == needle)
-}
-
 /// Scan a file for embedded protobuf descriptors
 ///
-/// This is a convenience function that reads the file and scans it.
+/// This is a convenience function that maps the file and scans it.
 pub fn scan_file(path: impl AsRef<std::path::Path>) -> Result<Vec<ScanResult>> {
-    let path = path.as_ref();
-    let data = std::fs::read(path).map_err(|e| Error::file_read(path, e))?;
-    Scanner::new().scan(&data)
+    scan_file_with_config(path, ScannerConfig::default())
 }
 
 /// Scan a file with custom configuration
@@ -321,9 +635,8 @@ pub fn scan_file_with_config(
     path: impl AsRef<std::path::Path>,
     config: ScannerConfig,
 ) -> Result<Vec<ScanResult>> {
-    let path = path.as_ref();
-    let data = std::fs::read(path).map_err(|e| Error::file_read(path, e))?;
-    Scanner::with_config(config).scan(&data)
+    let data = map_file(path)?;
+    Ok(Scanner::with_config(config).scan_bytes(data).collect())
 }
 
 #[cfg(test)]
@@ -331,11 +644,29 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_find_subsequence() {
-        let data = b"hello.proto.world";
-        assert_eq!(find_subsequence(data, b".proto"), Some(5));
-        assert_eq!(find_subsequence(data, b"world"), Some(12));
-        assert_eq!(find_subsequence(data, b"missing"), None);
+    fn test_suffix_search() {
+        let search = SuffixSearch::new(&ScannerConfig::default().suffixes);
+        assert_eq!(search.find(b"hello.proto.world"), Some(5..11));
+        assert_eq!(search.find(b"old.protodevel"), Some(3..14));
+        assert_eq!(search.find(b"missing"), None);
+
+        let search = SuffixSearch::new(&[b".pb".to_vec(), Vec::new()]);
+        assert_eq!(search.find(b"a.proto b.pb"), Some(9..12));
+        assert_eq!(search.longest(), 3);
+    }
+
+    #[test]
+    fn test_record_start() {
+        // Ten-byte names, whose length byte is also 0x0A
+        assert_eq!(record_start(b"\x0a\x0a\x0aabcd.proto", 13, 6), Some(1));
+        assert_eq!(record_start(b"\x00\x0a\x0babcde.proto", 14, 6), Some(1));
+        assert_eq!(record_start(b"\x0a\x0babcd.proto", 12, 6), None);
+
+        let name = format!("{}.proto", "a".repeat(194));
+        let mut data = vec![0x0a, 0xc8, 0x01];
+        data.extend(name.as_bytes());
+        assert_eq!(record_start(&data, data.len(), 6), Some(0));
+        assert_eq!(record_start(&data[1..], data.len() - 1, 6), None);
     }
 
     #[test]
@@ -357,6 +688,52 @@ mod tests {
         assert!(results.is_empty());
     }
 
+    #[test]
+    fn test_scan_with_diagnostics() {
+        use prost::Message;
+
+        let mut data = b"\x00\x00see bar.proto\x00".to_vec();
+        let start = data.len();
+        data.extend(
+            prost_types::FileDescriptorProto {
+                name: Some("ok.proto".to_string()),
+                package: Some("ok".to_string()),
+                ..Default::default()
+            }
+            .encode_to_vec(),
+        );
+        let end = data.len();
+        data.extend(b"\xff\x0a\x07x.proto");
+
+        let (results, diagnostics) = Scanner::new().scan_with_diagnostics(&data).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].range, start..end);
+        assert_eq!(results[0].confidence, 1.0);
+
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].stage, Stage::Backtrack);
+        assert_eq!(diagnostics[0].offset, 9);
+        assert_eq!(diagnostics[1].stage, Stage::Consume);
+        assert_eq!(diagnostics[1].kind, "RecordSize");
+        assert_eq!(diagnostics[1].offset, end + 1);
+        assert!(diagnostics[1].context.contains("ff [0a] 07"));
+    }
+
+    #[test]
+    fn test_min_confidence() {
+        // A string table entry that happens to be length-prefixed by 0x0A
+        let data = b"\x00\x0a\x0cconfig.proto";
+        let (results, diagnostics) = Scanner::new().scan_with_diagnostics(data).unwrap();
+        assert!(results.is_empty());
+        assert_eq!(diagnostics[0].stage, Stage::Confidence);
+        assert_eq!(diagnostics[0].kind, "LowConfidence");
+
+        let scanner = Scanner::with_config(ScannerConfig::new().min_confidence(0.0));
+        let results = scanner.scan(data).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].confidence, 0.25);
+    }
+
     #[test]
     fn test_no_proto_suffix() {
         let scanner = Scanner::new();
@@ -0,0 +1,363 @@
+//! Object file layout, for attributing scan results.
+//!
+//! ELF, Mach-O and PE headers are parsed into the file ranges of sections
+//! and segments, their virtual addresses, and the defined symbols. A record
+//! found at a file offset is then located in its section, at its address,
+//! after its nearest symbol, such as `file_foo_proto_rawDesc` in Go
+//! binaries or `descriptor_table_protodef_foo_2eproto` in C++ ones.
+
+use object::{Object, ObjectSection, ObjectSegment, ObjectSymbol, SectionKind, SymbolKind};
+use std::fmt;
+use std::ops::Range;
+
+/// Where a record lies in an object file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// Name of the containing section, such as `.rodata` or `__const`
+    pub section: Option<String>,
+    /// Name of the containing segment, such as `__DATA`; ELF and PE
+    /// segments have none
+    pub segment: Option<String>,
+    /// Virtual address of the record's first byte
+    pub address: Option<u64>,
+    /// The nearest symbol at or before the record
+    pub symbol: Option<NearSymbol>,
+}
+
+/// A symbol at or before a record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NearSymbol {
+    /// The symbol name, as stored (not demangled)
+    pub name: String,
+    /// Bytes from the symbol's address to the record
+    pub offset: u64,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.segment, &self.section) {
+            (Some(segment), Some(section)) => write!(f, "{},{}", segment, section)?,
+            (None, Some(name)) | (Some(name), None) => write!(f, "{}", name)?,
+            (None, None) => write!(f, "?")?,
+        }
+        if let Some(address) = self.address {
+            write!(f, " at {:#x}", address)?;
+        }
+        if let Some(symbol) = &self.symbol {
+            write!(f, " ({}+{:#x})", symbol.name, symbol.offset)?;
+        }
+        Ok(())
+    }
+}
+
+/// A section or segment that has bytes in the file
+#[derive(Debug, Clone)]
+struct Region {
+    name: Option<String>,
+    /// Segment of a Mach-O section
+    segment: Option<String>,
+    file: Range<usize>,
+    address: u64,
+    /// Section index, which symbols refer to
+    index: Option<usize>,
+    /// Holds data rather than code, debug info or metadata
+    data: bool,
+}
+
+impl Region {
+    /// Returns the virtual address of a file offset in the region
+    fn address_of(&self, offset: usize) -> u64 {
+        self.address + (offset - self.file.start) as u64
+    }
+}
+
+/// A defined symbol
+#[derive(Debug, Clone)]
+struct Symbol {
+    address: u64,
+    /// Size in bytes; 0 if unknown, as for all Mach-O symbols
+    size: u64,
+    section: Option<usize>,
+    name: String,
+}
+
+/// The sections, segments and symbols of an object file
+#[derive(Debug, Clone)]
+pub struct ObjectMap {
+    format: object::BinaryFormat,
+    sections: Vec<Region>,
+    segments: Vec<Region>,
+    /// Sorted by address
+    symbols: Vec<Symbol>,
+}
+
+impl ObjectMap {
+    /// Parses the headers of an ELF, Mach-O or PE file
+    ///
+    /// Returns `None` for other inputs, including fat Mach-O files and
+    /// files with headers that don't parse.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let file = object::File::parse(data).ok()?;
+        let format = file.format();
+        if !matches!(
+            format,
+            object::BinaryFormat::Elf | object::BinaryFormat::MachO | object::BinaryFormat::Pe
+        ) {
+            return None;
+        }
+
+        let mut sections: Vec<Region> = file
+            .sections()
+            .filter_map(|section| {
+                let file = file_range(section.file_range()?, data.len())?;
+                Some(Region {
+                    name: section.name().ok().map(str::to_string),
+                    segment: section.segment_name().ok().flatten().map(str::to_string),
+                    file,
+                    address: section.address(),
+                    index: Some(section.index().0),
+                    data: matches!(
+                        section.kind(),
+                        SectionKind::Data
+                            | SectionKind::ReadOnlyData
+                            | SectionKind::ReadOnlyDataWithRel
+                            | SectionKind::ReadOnlyString
+                            | SectionKind::Tls
+                    ),
+                })
+            })
+            .collect();
+        sections.sort_by_key(|section| section.file.start);
+
+        let segments = file
+            .segments()
+            .filter_map(|segment| {
+                Some(Region {
+                    name: segment.name().ok().flatten().map(str::to_string),
+                    segment: None,
+                    file: file_range(segment.file_range(), data.len())?,
+                    address: segment.address(),
+                    index: None,
+                    data: false,
+                })
+            })
+            .collect();
+
+        // Stripped binaries may still export dynamic symbols
+        let mut symbols: Vec<Symbol> = file.symbols().filter_map(defined).collect();
+        if symbols.is_empty() {
+            symbols = file.dynamic_symbols().filter_map(defined).collect();
+        }
+        symbols.sort_by_key(|symbol| symbol.address);
+
+        Some(Self {
+            format,
+            sections,
+            segments,
+            symbols,
+        })
+    }
+
+    /// Returns the format's name: `ELF`, `Mach-O` or `PE`
+    pub fn format(&self) -> &'static str {
+        match self.format {
+            object::BinaryFormat::Elf => "ELF",
+            object::BinaryFormat::MachO => "Mach-O",
+            _ => "PE",
+        }
+    }
+
+    /// Returns the file ranges of the sections that hold data, in order
+    ///
+    /// Code, debug info, symbol tables and other metadata are left out.
+    pub fn data_ranges(&self) -> Vec<Range<usize>> {
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+        for section in self.sections.iter().filter(|section| section.data) {
+            match ranges.last_mut() {
+                Some(last) if section.file.start <= last.end => {
+                    last.end = last.end.max(section.file.end);
+                }
+                _ => ranges.push(section.file.clone()),
+            }
+        }
+        ranges
+    }
+
+    /// Locates a file offset in its section or segment
+    ///
+    /// Returns `None` if the offset is in neither, such as in the headers.
+    pub fn locate(&self, offset: usize) -> Option<Location> {
+        let contains = |region: &&Region| region.file.contains(&offset);
+        let section = self.sections.iter().find(contains);
+        let segment = self.segments.iter().find(contains);
+        let region = section.or(segment)?;
+
+        let address = region.address_of(offset);
+        let symbol = self.nearest_symbol(region, address);
+        Some(Location {
+            section: section.and_then(|section| section.name.clone()),
+            segment: section
+                .and_then(|section| section.segment.clone())
+                .or_else(|| segment.and_then(|segment| segment.name.clone())),
+            address: Some(address),
+            symbol,
+        })
+    }
+
+    /// Finds the last symbol in `region` at or before `address`
+    ///
+    /// A symbol of known size must also extend to `address`, so that a
+    /// stripped binary's exports don't claim what follows them.
+    fn nearest_symbol(&self, region: &Region, address: u64) -> Option<NearSymbol> {
+        let end = self
+            .symbols
+            .partition_point(|symbol| symbol.address <= address);
+        self.symbols[..end]
+            .iter()
+            .rev()
+            .take_while(|symbol| symbol.address >= region.address)
+            // Sections of relocatable files all start at address 0
+            .find(|symbol| region.index.is_none() || symbol.section == region.index)
+            .filter(|symbol| symbol.size == 0 || address - symbol.address < symbol.size)
+            .map(|symbol| NearSymbol {
+                name: symbol.name.clone(),
+                offset: address - symbol.address,
+            })
+    }
+}
+
+/// Converts an `(offset, size)` pair to a non-empty range within the file
+fn file_range((offset, size): (u64, u64), len: usize) -> Option<Range<usize>> {
+    let start = usize::try_from(offset).ok()?;
+    let end = start.checked_add(usize::try_from(size).ok()?)?;
+    (start < end && end <= len).then_some(start..end)
+}
+
+/// Keeps named symbols defined in a section, of code or data
+fn defined<'data>(symbol: impl ObjectSymbol<'data>) -> Option<Symbol> {
+    if !symbol.is_definition() || !matches!(symbol.kind(), SymbolKind::Data | SymbolKind::Text) {
+        return None;
+    }
+    let name = symbol.name().ok().filter(|name| !name.is_empty())?;
+    Some(Symbol {
+        address: symbol.address(),
+        size: symbol.size(),
+        section: symbol.section_index().map(|index| index.0),
+        name: name.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::{ScanStrategy, Scanner, ScannerConfig};
+    use object::write;
+    use object::{Architecture, BinaryFormat, Endianness, SymbolFlags, SymbolScope};
+
+    /// A relocatable file with `descriptor` in `.rodata` after 24 bytes
+    /// whose first 8 are a symbol, and `code` in `.text`
+    fn elf(descriptor: &[u8], code: &[u8]) -> Vec<u8> {
+        let mut file =
+            write::Object::new(BinaryFormat::Elf, Architecture::X86_64, Endianness::Little);
+        let text = file.section_id(write::StandardSection::Text);
+        file.append_section_data(text, code, 16);
+        let rodata = file.section_id(write::StandardSection::ReadOnlyData);
+        file.append_section_data(rodata, &[0xff; 24], 8);
+        let offset = file.append_section_data(rodata, descriptor, 8);
+        let size = descriptor.len() as u64;
+        for (name, value, size, section, kind) in [
+            ("main", 0, 0, text, SymbolKind::Text),
+            ("padding", 0, 8, rodata, SymbolKind::Data),
+            (
+                "file_foo_proto_rawDesc",
+                offset,
+                size,
+                rodata,
+                SymbolKind::Data,
+            ),
+        ] {
+            file.add_symbol(write::Symbol {
+                name: name.as_bytes().to_vec(),
+                value,
+                size,
+                kind,
+                scope: SymbolScope::Linkage,
+                weak: false,
+                section: write::SymbolSection::Section(section),
+                flags: SymbolFlags::None,
+            });
+        }
+        file.write().unwrap()
+    }
+
+    #[test]
+    fn test_locate() {
+        let descriptor = b"\x0a\x09foo.proto\x12\x03foo";
+        let data = elf(descriptor, &[0x90; 64]);
+        let map = ObjectMap::parse(&data).unwrap();
+        assert_eq!(map.format(), "ELF");
+
+        let at = memchr::memmem::find(&data, descriptor).unwrap();
+        let location = map.locate(at + 2).unwrap();
+        assert_eq!(location.section.as_deref(), Some(".rodata"));
+        assert_eq!(location.address, Some(26));
+        let symbol = location.symbol.as_ref().unwrap();
+        assert_eq!(symbol.name, "file_foo_proto_rawDesc");
+        assert_eq!(symbol.offset, 2);
+        assert_eq!(
+            location.to_string(),
+            ".rodata at 0x1a (file_foo_proto_rawDesc+0x2)"
+        );
+
+        // Past the end of the symbol before it
+        let location = map.locate(at - 8).unwrap();
+        assert_eq!(location.address, Some(16));
+        assert!(location.symbol.is_none());
+
+        // Code isn't data, and the header is in no section
+        let ranges = map.data_ranges();
+        assert_eq!(ranges.len(), 1);
+        assert!(ranges[0].contains(&at));
+        assert!(map.locate(0).is_none());
+    }
+
+    #[test]
+    fn test_scan_object() {
+        let descriptor = b"\x0a\x09foo.proto\x12\x03foo";
+        let data = elf(descriptor, b"\x0a\x09bar.proto\x12\x03bar\0");
+
+        let results = Scanner::new().scan(&data).unwrap();
+        let sections: Vec<_> = results
+            .iter()
+            .map(|r| r.location.as_ref().unwrap().section.as_deref().unwrap())
+            .collect();
+        assert_eq!(sections, [".text", ".rodata"]);
+        assert_eq!(
+            results[1]
+                .location
+                .as_ref()
+                .unwrap()
+                .symbol
+                .as_ref()
+                .unwrap()
+                .name,
+            "file_foo_proto_rawDesc"
+        );
+
+        let config = ScannerConfig::new().data_sections_only(true);
+        let results = Scanner::with_config(config).scan(&data).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(&results[0].data[..], descriptor);
+
+        let config = ScannerConfig::new().locate(false);
+        let results = Scanner::with_config(config).scan(&data).unwrap();
+        assert!(results.iter().all(|r| r.location.is_none()));
+    }
+
+    #[test]
+    fn test_parse_other_inputs() {
+        assert!(ObjectMap::parse(b"").is_none());
+        assert!(ObjectMap::parse(b"\x0a\x09foo.proto").is_none());
+        assert!(ObjectMap::parse(b"\x7fELF\x02\x01\x01").is_none());
+    }
+}
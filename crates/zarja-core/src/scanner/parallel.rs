@@ -0,0 +1,320 @@
+//! Scanning one input on several threads.
+//!
+//! The input is split into chunks, and threads take turns judging every
+//! suffix that starts in the next chunk. Each suffix is judged against the
+//! whole input, so a chunk overlaps its neighbours by what its records
+//! need: the name before a suffix at its start, and up to
+//! `max_descriptor_size` bytes of record past its end. A sequential pass
+//! then replays the search over the verdicts, resuming after an accepted
+//! record's end or a rejected suffix as one pass over the input would, so
+//! records that cross chunk boundaries are found once and the suffixes
+//! inside them are skipped.
+//!
+//! Scans sharing a [`ThreadBudget`] run on the calling thread, and borrow
+//! threads from the budget as they become idle.
+
+use super::{Scanner, Search, Verdict};
+use crate::diagnostics::Diagnostic;
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+/// Threads shared by the scans running at the same time
+///
+/// A scan with a budget (see [`ScannerConfig::budget`](super::ScannerConfig))
+/// searches its chunks on the calling thread, and takes on another thread
+/// whenever the budget has one to spare, until no chunks are left.
+#[derive(Debug, Default)]
+pub struct ThreadBudget {
+    /// Threads not in use
+    idle: AtomicUsize,
+}
+
+impl ThreadBudget {
+    /// Creates a budget of `threads` idle threads
+    pub fn new(threads: usize) -> Arc<Self> {
+        Arc::new(Self {
+            idle: AtomicUsize::new(threads),
+        })
+    }
+
+    /// Returns a thread that has run out of other work to the budget
+    pub fn release(&self) {
+        self.idle.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Takes an idle thread, if there is one
+    fn acquire(&self) -> bool {
+        self.idle
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |idle| {
+                idle.checked_sub(1)
+            })
+            .is_ok()
+    }
+}
+
+/// A suffix and what the scanner made of it
+#[derive(Debug)]
+struct Judged {
+    suffix: Range<usize>,
+    verdict: Verdict,
+    /// Why the suffix was rejected, when diagnostics are collected
+    diagnostic: Option<Diagnostic>,
+}
+
+/// Returns the number of threads to scan `len` bytes on, at most one per
+/// chunk
+///
+/// With a budget that is every chunk: how many threads the scan gets
+/// depends on when they become idle.
+pub(super) fn threads(scanner: &Scanner, len: usize) -> usize {
+    let threads = match (&scanner.config.budget, scanner.config.threads) {
+        (Some(_), _) => usize::MAX,
+        (None, 0) => thread::available_parallelism().map_or(1, |n| n.get()),
+        (None, threads) => threads,
+    };
+    threads.min(len.div_ceil(scanner.config.chunk_size))
+}
+
+/// Scans `data`, at offset `base` in the input, on `threads` threads
+///
+/// With a budget, the scan starts on the calling thread alone and takes
+/// threads from the budget instead.
+///
+/// Returns the accepted records in order, at ranges of `data`, as a single
+/// pass would find them, counting them in `search`. Adds the rejected
+/// suffixes to `diagnostics` if given.
+pub(super) fn scan(
+    scanner: &Scanner,
+    data: &[u8],
+    base: usize,
+    threads: usize,
+    search: &mut Search,
+    mut diagnostics: Option<&mut Vec<Diagnostic>>,
+) -> Vec<(Range<usize>, f32)> {
+    let chunk_size = scanner.config.chunk_size;
+    let chunks = data.len().div_ceil(chunk_size);
+    let collect = diagnostics.is_some();
+
+    // Threads take the next chunk until none are left
+    let next = AtomicUsize::new(0);
+    let take = || {
+        let chunk = next.fetch_add(1, Ordering::Relaxed);
+        (chunk < chunks).then(|| {
+            let start = chunk * chunk_size;
+            let end = (start + chunk_size).min(data.len());
+            (chunk, judge_chunk(scanner, data, base, start..end, collect))
+        })
+    };
+    let work = || std::iter::from_fn(take).collect::<Vec<_>>();
+    let mut judged: Vec<(usize, Vec<Judged>)> = thread::scope(|scope| {
+        let Some(budget) = &scanner.config.budget else {
+            let workers: Vec<_> = (0..threads).map(|_| scope.spawn(work)).collect();
+            return workers
+                .into_iter()
+                .flat_map(|worker| worker.join().expect("scanner thread panicked"))
+                .collect();
+        };
+
+        // Before each chunk, idle threads join in while chunks are left
+        // for them
+        let mut workers = Vec::new();
+        let mut done = Vec::new();
+        loop {
+            while next.load(Ordering::Relaxed) + 1 < chunks && budget.acquire() {
+                workers.push(scope.spawn(|| {
+                    let done = work();
+                    budget.release();
+                    done
+                }));
+            }
+            match take() {
+                Some(judged) => done.push(judged),
+                None => break,
+            }
+        }
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("scanner thread panicked"))
+            .chain(done)
+            .collect()
+    });
+    judged.sort_by_key(|(chunk, _)| *chunk);
+
+    // Replay the search: suffixes it would have skipped are left out
+    let max_results = scanner.config.max_results;
+    let mut results = Vec::new();
+    let mut position = 0;
+    for judged in judged.into_iter().flat_map(|(_, judged)| judged) {
+        if max_results > 0 && search.found >= max_results {
+            break;
+        }
+        if judged.suffix.start < position {
+            continue;
+        }
+        match judged.verdict {
+            Verdict::Found(range, score) => {
+                position = range.end;
+                search.found += 1;
+                results.push((range, score));
+            }
+            // Nothing needs more input than the whole of it
+            Verdict::Rejected | Verdict::NeedMore => {
+                position = judged.suffix.end;
+                if let (Some(diagnostics), Some(diagnostic)) =
+                    (diagnostics.as_deref_mut(), judged.diagnostic)
+                {
+                    diagnostics.push(diagnostic);
+                }
+            }
+        }
+    }
+    search.position = data.len();
+    results
+}
+
+/// Judges every suffix that starts in `chunk`, including ones that overlap
+fn judge_chunk(
+    scanner: &Scanner,
+    data: &[u8],
+    base: usize,
+    chunk: Range<usize>,
+    collect: bool,
+) -> Vec<Judged> {
+    // Suffixes that start in the chunk end by here
+    let limit = (chunk.end + scanner.search.longest()).min(data.len());
+    let mut judged = Vec::new();
+    let mut position = chunk.start;
+    while let Some(found) = scanner.search.find(&data[position..limit]) {
+        let suffix = position + found.start..position + found.end;
+        if suffix.start >= chunk.end {
+            break;
+        }
+        let mut diagnostics = Vec::new();
+        let verdict = scanner.judge(
+            data,
+            base,
+            suffix.clone(),
+            true,
+            collect.then_some(&mut diagnostics),
+        );
+        position = suffix.start + 1;
+        judged.push(Judged {
+            suffix,
+            verdict,
+            diagnostic: diagnostics.pop(),
+        });
+    }
+    judged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ThreadBudget;
+    use crate::scanner::{ScanStrategy, Scanner, ScannerConfig};
+    use prost::Message;
+    use std::sync::atomic::Ordering;
+
+    /// Noise with `.proto` strings, and descriptors every `stride` bytes
+    /// whose package holds a record of its own
+    fn input(len: usize, stride: usize) -> Vec<u8> {
+        let mut state = 0x2545_f491_u32;
+        let mut data: Vec<u8> = (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+        for at in (0..len - 200).step_by(101) {
+            data[at..at + 9].copy_from_slice(b"\x0a\x07a.proto");
+        }
+        for (i, at) in (stride / 2..len - 200).step_by(stride).enumerate() {
+            let record = prost_types::FileDescriptorProto {
+                name: Some(format!("file{}.proto", i)),
+                package: Some("\n\x0bhello.proto".to_string()),
+                syntax: Some("proto3".to_string()),
+                ..Default::default()
+            }
+            .encode_to_vec();
+            data[at..at + record.len()].copy_from_slice(&record);
+            // Ends the record
+            data[at + record.len()] = 0;
+        }
+        data
+    }
+
+    fn scan(
+        config: ScannerConfig,
+        data: &[u8],
+    ) -> (Vec<(std::ops::Range<usize>, f32)>, Vec<String>) {
+        let (results, diagnostics) = Scanner::with_config(config)
+            .scan_with_diagnostics(data)
+            .unwrap();
+        (
+            results
+                .into_iter()
+                .map(|r| (r.range, r.confidence))
+                .collect(),
+            diagnostics.iter().map(|d| d.to_string()).collect(),
+        )
+    }
+
+    #[test]
+    fn test_parallel_matches_sequential() {
+        let data = input(200_000, 777);
+        let sequential = ScannerConfig::new().threads(1);
+        let expected = scan(sequential.clone(), &data);
+        assert!(expected.0.len() > 250);
+        assert!(expected.1.len() > 500);
+
+        // Chunks that records and suffixes cross
+        for (threads, chunk_size) in [(2, 100_000), (4, 997), (3, 64)] {
+            let config = sequential.clone().threads(threads).chunk_size(chunk_size);
+            assert_eq!(
+                scan(config, &data),
+                expected,
+                "{} x {}",
+                threads,
+                chunk_size
+            );
+        }
+
+        // Threads borrowed from a budget are returned to it
+        let budget = ThreadBudget::new(3);
+        let config = sequential.clone().budget(budget.clone()).chunk_size(997);
+        assert_eq!(scan(config, &data), expected);
+        assert_eq!(budget.idle.load(Ordering::Relaxed), 3);
+        let config = sequential
+            .clone()
+            .budget(ThreadBudget::new(0))
+            .chunk_size(997);
+        assert_eq!(scan(config, &data), expected);
+
+        let limited = sequential.max_results(7);
+        let expected = scan(limited.clone(), &data);
+        let parallel = scan(limited.threads(4).chunk_size(1000), &data);
+        assert_eq!(parallel, expected);
+        assert_eq!(expected.0.len(), 7);
+    }
+
+    #[test]
+    fn test_overlapping_suffixes() {
+        // Each `oo` starts inside the one before
+        let config = ScannerConfig::new().suffixes(["oo"]).min_confidence(0.0);
+        let mut data = b"\x0a\x04fooo\x12\x08ooooxoo".to_vec();
+        data.extend_from_slice(&data.clone());
+        let expected = Scanner::with_config(config.clone().threads(1))
+            .scan(&data)
+            .unwrap();
+        let parallel = Scanner::with_config(config.threads(4).chunk_size(3))
+            .scan(&data)
+            .unwrap();
+        let ranges = |results: Vec<crate::ScanResult>| -> Vec<_> {
+            results.into_iter().map(|r| r.range).collect()
+        };
+        assert_eq!(ranges(parallel), ranges(expected));
+    }
+}
@@ -0,0 +1,77 @@
+//! Candidate search: finding file name suffixes and the records they end.
+//!
+//! All suffixes are searched in one pass with an Aho-Corasick automaton,
+//! which uses SIMD prefilters (through `memchr`) to skip over bytes that
+//! can't start a match. For each hit, `memrchr` finds the `0x0A` bytes
+//! before it, and each is checked against the one name length that would
+//! end at the suffix, instead of decoding a varint at every position.
+
+use super::MAGIC_BYTE;
+use aho_corasick::{AhoCorasick, MatchKind};
+use std::ops::Range;
+
+/// Longest file name looked for, in bytes
+pub(super) const MAX_NAME_LEN: usize = 256;
+
+/// Searches for any of a set of file name suffixes
+#[derive(Debug, Clone)]
+pub(super) struct SuffixSearch {
+    automaton: AhoCorasick,
+    /// Length of the longest suffix
+    longest: usize,
+}
+
+impl SuffixSearch {
+    /// Builds a search for the given suffixes, ignoring empty ones
+    pub(super) fn new(suffixes: &[Vec<u8>]) -> Self {
+        let suffixes: Vec<&[u8]> = suffixes
+            .iter()
+            .map(Vec::as_slice)
+            .filter(|s| !s.is_empty())
+            .collect();
+        let automaton = AhoCorasick::builder()
+            // `.protodevel` over the `.proto` it starts with
+            .match_kind(MatchKind::LeftmostLongest)
+            .build(&suffixes)
+            .expect("a handful of suffixes is within automaton limits");
+        Self {
+            automaton,
+            longest: suffixes.iter().map(|s| s.len()).max().unwrap_or(0),
+        }
+    }
+
+    /// Returns the range of the first suffix in `haystack`
+    pub(super) fn find(&self, haystack: &[u8]) -> Option<Range<usize>> {
+        self.automaton.find(haystack).map(|m| m.range())
+    }
+
+    /// Returns the length of the longest suffix
+    pub(super) fn longest(&self) -> usize {
+        self.longest
+    }
+}
+
+/// Finds the start of a record whose name field ends at `end`
+///
+/// The name field is `0x0A`, a varint length, and a name of at least
+/// `min_len` bytes (the suffix's). Each `0x0A` before the suffix, nearest
+/// first, is checked against the one length that would end its name at
+/// `end`.
+pub(super) fn record_start(data: &[u8], end: usize, min_len: usize) -> Option<usize> {
+    let from = end.saturating_sub(MAX_NAME_LEN + 3);
+    let to = end.checked_sub(min_len + 1)?;
+    memchr::memrchr_iter(MAGIC_BYTE, data.get(from..to)?)
+        .map(|i| from + i)
+        .find(|&start| {
+            // One-byte length, or two-byte for longer names
+            match end - start - 2 {
+                len @ 0..=0x7f => data[start + 1] == len as u8,
+                _ => {
+                    let len = end - start - 3;
+                    len >= 0x80
+                        && data[start + 1] == (len as u8) | 0x80
+                        && data[start + 2] == (len >> 7) as u8
+                }
+            }
+        })
+}
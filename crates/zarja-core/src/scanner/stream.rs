@@ -0,0 +1,529 @@
+//! Lazy scanning of in-memory buffers, mapped files and readers.
+
+use super::{fat, parallel, FatSlice, ScanResult, Scanner, Search, SliceMatch, Step};
+#[cfg(feature = "object-files")]
+use super::{DyldCache, Location, ObjectMap};
+use crate::diagnostics::Diagnostic;
+use crate::error::{Error, Result};
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::ops::Range;
+use std::path::Path;
+
+/// Bytes kept before the search position when a reader's window moves on,
+/// enough to backtrack from a `.proto` suffix to its record start
+const HISTORY: usize = 512;
+
+/// Bytes read from a reader beyond what the next record needs
+const CHUNK: usize = 1024 * 1024;
+
+/// Maps a file into memory as a shared buffer
+///
+/// The map lives as long as the returned [`Bytes`] or any slice of it,
+/// such as the data of results from [`Scanner::scan_bytes`]. The file must
+/// not be modified while it is mapped.
+#[allow(unsafe_code)]
+pub fn map_file(path: impl AsRef<Path>) -> Result<Bytes> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|e| Error::file_read(path, e))?;
+    let len = file
+        .metadata()
+        .map_err(|e| Error::file_read(path, e))?
+        .len();
+    if len == 0 {
+        // Empty maps are an error on some platforms
+        return Ok(Bytes::new());
+    }
+    // SAFETY: the map is read-only, and the caller is told not to modify the
+    // file while it is in use
+    let map = unsafe { memmap2::Mmap::map(&file) }.map_err(|e| Error::file_read(path, e))?;
+    Ok(Bytes::from_owner(map))
+}
+
+/// The input of a [`ScanIter`]
+#[derive(Debug)]
+enum Input<'a> {
+    /// Results are copied out
+    Borrowed(&'a [u8]),
+    /// Results are slices of the buffer
+    Shared(Bytes),
+}
+
+impl Input<'_> {
+    fn as_slice(&self) -> &[u8] {
+        match self {
+            Self::Borrowed(data) => data,
+            Self::Shared(data) => data,
+        }
+    }
+}
+
+/// Iterator over the results of scanning an in-memory input
+///
+/// Created by [`Scanner::scan_bytes`]. The input is searched as the
+/// iterator advances, except that an input split over several threads
+/// (see [`ScannerConfig::threads`](super::ScannerConfig)) is searched in
+/// full on the first call to `next`.
+#[derive(Debug)]
+pub struct ScanIter<'a> {
+    scanner: &'a Scanner,
+    input: Input<'a>,
+    /// Offset of the input in the file, for a slice of a universal binary
+    offset: usize,
+    /// Slices of a universal binary input, not yet scanned
+    fat: Option<Vec<FatSlice>>,
+    /// Results merged from the slices, not yet yielded
+    merged: Option<std::vec::IntoIter<ScanResult>>,
+    /// Ranges of the input to search, in order
+    regions: Vec<Range<usize>>,
+    /// Index of the region being searched
+    region: usize,
+    search: Search,
+    diagnostics: Option<Vec<Diagnostic>>,
+    /// Records found on several threads in the region, not yet yielded
+    found: Option<std::vec::IntoIter<(Range<usize>, f32)>>,
+    /// Layout of an object file input, to locate results in
+    #[cfg(feature = "object-files")]
+    object: Option<ObjectMap>,
+    /// The dyld shared cache the input is a file of, and the file's number
+    #[cfg(feature = "object-files")]
+    cache: Option<(&'a DyldCache, usize)>,
+}
+
+impl<'a> ScanIter<'a> {
+    pub(super) fn borrowed(scanner: &'a Scanner, data: &'a [u8]) -> Self {
+        Self::new(scanner, Input::Borrowed(data))
+    }
+
+    pub(super) fn shared(scanner: &'a Scanner, data: Bytes) -> Self {
+        Self::new(scanner, Input::Shared(data))
+    }
+
+    fn new(scanner: &'a Scanner, input: Input<'a>) -> Self {
+        let slices = fat::fat_slices(input.as_slice());
+        let mut iter = Self::thin(scanner, input);
+        if slices.is_some() {
+            // The slices are searched instead
+            iter.regions.clear();
+        }
+        iter.fat = slices;
+        iter
+    }
+
+    /// Creates an iterator that searches the input as one file
+    fn thin(scanner: &'a Scanner, input: Input<'a>) -> Self {
+        #[cfg(feature = "object-files")]
+        let (object, regions) = object_regions(scanner, input.as_slice());
+        #[cfg(not(feature = "object-files"))]
+        let regions = std::iter::once(0..input.as_slice().len()).collect();
+
+        Self {
+            scanner,
+            input,
+            offset: 0,
+            fat: None,
+            merged: None,
+            regions,
+            region: 0,
+            search: Search::default(),
+            diagnostics: None,
+            found: None,
+            #[cfg(feature = "object-files")]
+            object,
+            #[cfg(feature = "object-files")]
+            cache: None,
+        }
+    }
+
+    #[cfg(feature = "object-files")]
+    pub(super) fn dyld(
+        scanner: &'a Scanner,
+        cache: &'a DyldCache,
+        file: usize,
+        data: Bytes,
+    ) -> Self {
+        let len = data.len();
+        let regions = Some(cache.data_ranges(file))
+            .filter(|ranges| scanner.config.data_sections_only && !ranges.is_empty())
+            .unwrap_or_else(|| std::iter::once(0..len).collect());
+        Self {
+            regions,
+            object: None,
+            cache: Some((cache, file)),
+            ..Self::thin(scanner, Input::Shared(data))
+        }
+    }
+
+    /// Also collects why each rejected candidate was dropped, see
+    /// [`Scanner::scan_with_diagnostics`]
+    pub fn with_diagnostics(mut self) -> Self {
+        self.diagnostics = Some(Vec::new());
+        self
+    }
+
+    /// Returns the diagnostics collected so far, leaving none behind
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        self.diagnostics
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+}
+
+/// Parses an object file input, returning it if results are to be located,
+/// and the ranges of the input to search
+#[cfg(feature = "object-files")]
+fn object_regions(scanner: &Scanner, data: &[u8]) -> (Option<ObjectMap>, Vec<Range<usize>>) {
+    let config = &scanner.config;
+    let object = (config.locate || config.data_sections_only)
+        .then(|| ObjectMap::parse(data))
+        .flatten();
+    let ranges = object
+        .as_ref()
+        .filter(|_| config.data_sections_only)
+        .map(ObjectMap::data_ranges)
+        .filter(|ranges| !ranges.is_empty())
+        .unwrap_or_else(|| std::iter::once(0..data.len()).collect());
+    (object.filter(|_| config.locate), ranges)
+}
+
+impl<'a> ScanIter<'a> {
+    /// Scans each slice of a universal binary, merging identical records
+    fn scan_slices(&mut self, slices: Vec<FatSlice>) -> Vec<ScanResult> {
+        let mut merged: Vec<ScanResult> = Vec::new();
+        let mut seen: HashMap<Bytes, usize> = HashMap::new();
+        for slice in slices {
+            let input = match &self.input {
+                Input::Borrowed(data) => {
+                    let data: &'a [u8] = data;
+                    Input::Borrowed(&data[slice.range.clone()])
+                }
+                Input::Shared(data) => Input::Shared(data.slice(slice.range.clone())),
+            };
+            let mut child = Self::thin(self.scanner, input);
+            child.offset = slice.range.start;
+            if self.diagnostics.is_some() {
+                child = child.with_diagnostics();
+            }
+
+            for result in child.by_ref() {
+                let found = SliceMatch {
+                    arch: slice.arch.clone(),
+                    range: result.range.clone(),
+                    #[cfg(feature = "object-files")]
+                    location: result.location.clone(),
+                };
+                if let Some(&index) = seen.get(&result.data) {
+                    merged[index].slices.push(found);
+                    continue;
+                }
+                seen.insert(result.data.clone(), merged.len());
+                merged.push(ScanResult {
+                    range: slice.range.start + result.range.start
+                        ..slice.range.start + result.range.end,
+                    slices: vec![found],
+                    ..result
+                });
+            }
+            if let Some(diagnostics) = &mut self.diagnostics {
+                diagnostics.extend(child.take_diagnostics());
+            }
+        }
+
+        let max_results = self.scanner.config.max_results;
+        if max_results > 0 {
+            merged.truncate(max_results);
+        }
+        merged
+    }
+
+    /// Finds the dyld shared cache image and the location of an offset
+    #[cfg(feature = "object-files")]
+    fn locate(&self, offset: usize) -> (Option<String>, Option<Location>) {
+        let Some((cache, file)) = self.cache else {
+            let object = self.object.as_ref();
+            return (None, object.and_then(|object| object.locate(offset)));
+        };
+        match cache.locate(file, offset) {
+            Some((image, location)) => (
+                Some(image.to_string()),
+                self.scanner.config.locate.then_some(location),
+            ),
+            None => (None, None),
+        }
+    }
+
+    /// Finds the next record in a region, at its range in the input
+    fn next_in(&mut self, region: Range<usize>) -> Option<(Range<usize>, f32)> {
+        let window = &self.input.as_slice()[region.clone()];
+        let threads = parallel::threads(self.scanner, window.len());
+        let (range, confidence) = if threads > 1 {
+            let found = self.found.get_or_insert_with(|| {
+                let diagnostics = self.diagnostics.as_mut();
+                let base = self.offset + region.start;
+                parallel::scan(
+                    self.scanner,
+                    window,
+                    base,
+                    threads,
+                    &mut self.search,
+                    diagnostics,
+                )
+                .into_iter()
+            });
+            found.next()?
+        } else {
+            let step = self.scanner.step(
+                window,
+                self.offset + region.start,
+                &mut self.search,
+                true,
+                self.diagnostics.as_mut(),
+            );
+            let Step::Found(range, confidence) = step else {
+                return None;
+            };
+            (range, confidence)
+        };
+        Some((
+            region.start + range.start..region.start + range.end,
+            confidence,
+        ))
+    }
+}
+
+impl Iterator for ScanIter<'_> {
+    type Item = ScanResult;
+
+    fn next(&mut self) -> Option<ScanResult> {
+        if let Some(slices) = self.fat.take() {
+            self.merged = Some(self.scan_slices(slices).into_iter());
+        }
+        if let Some(merged) = &mut self.merged {
+            return merged.next();
+        }
+
+        let (range, confidence) = loop {
+            let region = self.regions.get(self.region)?.clone();
+            if let Some(found) = self.next_in(region) {
+                break found;
+            }
+            self.region += 1;
+            self.search.position = 0;
+            self.found = None;
+        };
+        let data = match &self.input {
+            Input::Borrowed(data) => Bytes::copy_from_slice(&data[range.clone()]),
+            Input::Shared(data) => data.slice(range.clone()),
+        };
+        #[cfg(feature = "object-files")]
+        let (image, location) = self.locate(range.start);
+        Some(ScanResult {
+            data,
+            #[cfg(feature = "object-files")]
+            location,
+            #[cfg(feature = "object-files")]
+            image,
+            range,
+            confidence,
+            slices: Vec::new(),
+        })
+    }
+}
+
+/// Iterator over the results of scanning a reader
+///
+/// Created by [`Scanner::scan_reader`]. The reader is read in chunks as
+/// the iterator advances, holding a window of a little over
+/// [`ScannerConfig::max_descriptor_size`](super::ScannerConfig) bytes.
+/// Reading stops at the first I/O error, which is yielded.
+#[derive(Debug)]
+pub struct ReaderScan<'a, R> {
+    scanner: &'a Scanner,
+    reader: R,
+    /// The part of the input being searched
+    window: Vec<u8>,
+    /// Offset of the window in the source
+    base: usize,
+    search: Search,
+    /// The window reaches the end of the source
+    eof: bool,
+    done: bool,
+}
+
+impl<'a, R: Read + Seek> ReaderScan<'a, R> {
+    pub(super) fn new(scanner: &'a Scanner, mut reader: R) -> Result<Self> {
+        let base = reader
+            .stream_position()
+            .map_err(|source| Error::ReadInput { offset: 0, source })?;
+        Ok(Self {
+            scanner,
+            reader,
+            window: Vec::new(),
+            base: base as usize,
+            search: Search::default(),
+            eof: false,
+            done: false,
+        })
+    }
+
+    /// Moves the window past what the search is done with, and reads
+    /// enough to decide the next record
+    fn fill(&mut self) -> Result<()> {
+        let drop = self.search.position.saturating_sub(HISTORY);
+        self.window.drain(..drop);
+        self.base += drop;
+        self.search.position -= drop;
+
+        let target = self
+            .search
+            .position
+            .saturating_add(self.scanner.config.max_descriptor_size)
+            .saturating_add(CHUNK);
+        let wanted = target.saturating_sub(self.window.len()).max(1);
+        let wanted = u64::try_from(wanted).unwrap_or(u64::MAX);
+        let read = self
+            .reader
+            .by_ref()
+            .take(wanted)
+            .read_to_end(&mut self.window)
+            .map_err(|source| Error::ReadInput {
+                offset: (self.base + self.window.len()) as u64,
+                source,
+            })?;
+        self.eof = (read as u64) < wanted;
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Iterator for ReaderScan<'_, R> {
+    type Item = Result<ScanResult>;
+
+    fn next(&mut self) -> Option<Result<ScanResult>> {
+        while !self.done {
+            let step = self
+                .scanner
+                .step(&self.window, self.base, &mut self.search, self.eof, None);
+            match step {
+                Step::Found(range, confidence) => {
+                    return Some(Ok(ScanResult {
+                        data: Bytes::copy_from_slice(&self.window[range.clone()]),
+                        range: self.base + range.start..self.base + range.end,
+                        confidence,
+                        #[cfg(feature = "object-files")]
+                        location: None,
+                        #[cfg(feature = "object-files")]
+                        image: None,
+                        slices: Vec::new(),
+                    }));
+                }
+                Step::NeedMore => {
+                    if let Err(e) = self.fill() {
+                        self.done = true;
+                        return Some(Err(e));
+                    }
+                }
+                Step::Done => self.done = true,
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::{ScanStrategy, ScannerConfig};
+    use prost::Message;
+    use std::io::{Cursor, Write};
+
+    fn descriptor(name: &str) -> Vec<u8> {
+        prost_types::FileDescriptorProto {
+            name: Some(name.to_string()),
+            package: Some("test".to_string()),
+            syntax: Some("proto3".to_string()),
+            ..Default::default()
+        }
+        .encode_to_vec()
+    }
+
+    /// Descriptors spread over `len` bytes of filler, one across each
+    /// multiple of `stride`
+    fn input(len: usize, stride: usize) -> Vec<u8> {
+        let mut data = vec![0u8; len];
+        for (i, at) in (stride - 20..len - 100).step_by(stride).enumerate() {
+            let record = descriptor(&format!("file{}.proto", i));
+            data[at..at + record.len()].copy_from_slice(&record);
+        }
+        data
+    }
+
+    #[test]
+    fn test_scan_bytes_shares_input() {
+        let data = Bytes::from(input(10_000, 1000));
+        let scanner = Scanner::new();
+        let results: Vec<_> = scanner.scan_bytes(data.clone()).collect();
+        assert_eq!(results.len(), 9);
+
+        let start = data.as_ptr() as usize;
+        for result in &results {
+            assert_eq!(result.data.as_ptr() as usize - start, result.range.start);
+        }
+        assert_eq!(
+            scanner.scan_iter(&data).filter_map(|r| r.ok()).count(),
+            results.len()
+        );
+    }
+
+    #[test]
+    fn test_reader_matches_slice() {
+        // Larger than a chunk, so the window moves
+        let data = input(3 * CHUNK, 997);
+        let config = ScannerConfig::new().max_descriptor_size(64);
+        let scanner = Scanner::with_config(config);
+        let expected: Vec<_> = scanner
+            .scan(&data)
+            .unwrap()
+            .into_iter()
+            .map(|r| (r.range, r.data))
+            .collect();
+        assert_eq!(expected.len(), 3155);
+
+        let mut reader = Cursor::new(&data);
+        let actual: Vec<_> = scanner
+            .scan_reader(&mut reader)
+            .unwrap()
+            .map(|r| r.map(|r| (r.range, r.data)))
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(actual, expected);
+
+        // Offsets are in the source, not from where reading started
+        reader.set_position(CHUNK as u64);
+        let first = scanner
+            .scan_reader(reader)
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+        let skipped = expected.iter().position(|(range, _)| range.start >= CHUNK);
+        assert_eq!(
+            Some(&(first.range, first.data)),
+            skipped.map(|i| &expected[i])
+        );
+    }
+
+    #[test]
+    fn test_map_file() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&input(4000, 1000)).unwrap();
+        let data = map_file(file.path()).unwrap();
+        assert_eq!(Scanner::new().scan_bytes(data).count(), 3);
+
+        let empty = tempfile::NamedTempFile::new().unwrap();
+        assert!(map_file(empty.path()).unwrap().is_empty());
+        assert!(map_file("/nonexistent/zarja").is_err());
+    }
+}
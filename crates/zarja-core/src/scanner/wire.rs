@@ -81,10 +81,42 @@ pub fn decode_varint(data: &[u8]) -> Result<(u64, usize)> {
     Err(Error::varint_decode(data.len()))
 }
 
-/// Consume a single protobuf field from the data.
+/// A single field read from protobuf wire data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WireField<'a> {
+    /// Field number from the tag
+    pub number: u32,
+    /// Wire type from the tag
+    pub wire_type: WireType,
+    /// Value bytes: the varint bytes, the fixed-width bytes, or the
+    /// payload of a length-delimited field (without its length prefix)
+    pub value: &'a [u8],
+    /// Total bytes consumed (including tag and value)
+    pub len: usize,
+}
+
+impl<'a> WireField<'a> {
+    /// Returns the decoded value of a varint field
+    pub fn as_varint(&self) -> Option<u64> {
+        match self.wire_type {
+            WireType::Varint => decode_varint(self.value).ok().map(|(v, _)| v),
+            _ => None,
+        }
+    }
+
+    /// Returns the payload of a length-delimited field
+    pub fn as_bytes(&self) -> Option<&'a [u8]> {
+        match self.wire_type {
+            WireType::Len => Some(self.value),
+            _ => None,
+        }
+    }
+}
+
+/// Read a single protobuf field from the data.
 ///
-/// Returns the field number and total bytes consumed (including tag and value).
-pub fn consume_field(data: &[u8]) -> Result<(u32, usize)> {
+/// Returns the field number, wire type, value bytes and total length.
+pub fn read_field(data: &[u8]) -> Result<WireField<'_>> {
     if data.is_empty() {
         return Err(Error::invalid_wire_format(0, "empty data"));
     }
@@ -105,15 +137,15 @@ pub fn consume_field(data: &[u8]) -> Result<(u32, usize)> {
         });
     }
 
-    // Calculate bytes consumed based on wire type
-    let value_len = match wire_type {
+    // Locate the value based on wire type
+    let (value_start, value_len) = match wire_type {
         WireType::Varint => {
             // Consume the varint value
             let remaining = &data[tag_len..];
             let (_, varint_len) = decode_varint(remaining).map_err(|_| {
                 Error::invalid_wire_format(tag_len, "failed to decode varint value")
             })?;
-            varint_len
+            (tag_len, varint_len)
         }
         WireType::I64 => {
             // Fixed 8 bytes
@@ -123,7 +155,7 @@ pub fn consume_field(data: &[u8]) -> Result<(u32, usize)> {
                     "not enough bytes for I64",
                 ));
             }
-            8
+            (tag_len, 8)
         }
         WireType::Len => {
             // Length-prefixed: decode length varint, then skip that many bytes
@@ -132,24 +164,23 @@ pub fn consume_field(data: &[u8]) -> Result<(u32, usize)> {
                 Error::invalid_wire_format(tag_len, "failed to decode length prefix")
             })?;
 
-            let total_value_len = length_varint_len + length as usize;
-            if data.len() < tag_len + total_value_len {
+            let available = data.len() - tag_len - length_varint_len;
+            if length > available as u64 {
                 return Err(Error::invalid_wire_format(
                     tag_len,
                     format!(
                         "not enough bytes for LEN field (need {}, have {})",
-                        length,
-                        data.len() - tag_len - length_varint_len
+                        length, available
                     ),
                 ));
             }
-            total_value_len
+            (tag_len + length_varint_len, length as usize)
         }
         WireType::StartGroup | WireType::EndGroup => {
             // Groups are deprecated and complex to parse
             // For our purposes, we can treat them as 0 additional bytes
             // (the tag itself is the marker)
-            0
+            (tag_len, 0)
         }
         WireType::I32 => {
             // Fixed 4 bytes
@@ -159,11 +190,35 @@ pub fn consume_field(data: &[u8]) -> Result<(u32, usize)> {
                     "not enough bytes for I32",
                 ));
             }
-            4
+            (tag_len, 4)
         }
     };
 
-    Ok((field_number, tag_len + value_len))
+    Ok(WireField {
+        number: field_number,
+        wire_type,
+        value: &data[value_start..value_start + value_len],
+        len: value_start + value_len,
+    })
+}
+
+/// Consume a single protobuf field from the data.
+///
+/// Returns the field number and total bytes consumed (including tag and value).
+pub fn consume_field(data: &[u8]) -> Result<(u32, usize)> {
+    read_field(data).map(|field| (field.number, field.len))
+}
+
+/// Iterate over the fields of a protobuf message.
+///
+/// Iteration stops at the end of the data or at the first malformed field.
+pub fn fields(data: &[u8]) -> impl Iterator<Item = WireField<'_>> {
+    let mut position = 0;
+    std::iter::from_fn(move || {
+        let field = read_field(data.get(position..)?).ok()?;
+        position += field.len;
+        Some(field)
+    })
 }
 
 /// Consume multiple fields and return total bytes consumed.
@@ -258,6 +313,21 @@ mod tests {
         assert_eq!(len, 9);
     }
 
+    #[test]
+    fn test_read_len_field_value() {
+        // Field 2, wire type 2 (len), length 3, "abc", then field 3 varint 7
+        let data = [0x12, 0x03, b'a', b'b', b'c', 0x18, 0x07];
+        let field = read_field(&data).unwrap();
+        assert_eq!(field.number, 2);
+        assert_eq!(field.as_bytes(), Some(&b"abc"[..]));
+        assert_eq!(field.len, 5);
+
+        let all: Vec<_> = fields(&data).collect();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[1].number, 3);
+        assert_eq!(all[1].as_varint(), Some(7));
+    }
+
     #[test]
     fn test_invalid_field_number() {
         // Field 0 is invalid